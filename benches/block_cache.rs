@@ -0,0 +1,39 @@
+//! Times indexed and prefixed code, which costs the most to decode, with and
+//! without the decoded block cache. Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+use z80_undead::cpu::Cpu;
+
+/// Steps timed in each run
+const STEPS: usize = 1_000_000;
+
+// LD IX,0x8000; LD IY,0x8100; LD B,0; loop: LD A,(IX+1); BIT 0,(IX+2);
+// SET 1,(IY+3); ADD A,(IX+0); LD (IY+4),A; NEG; RLC (IX+5); DJNZ loop;
+// JR to the start
+const PROGRAM: [u8; 37] = [
+    0xDD, 0x21, 0x00, 0x80, 0xFD, 0x21, 0x00, 0x81, 0x06, 0x00, 0xDD, 0x7E, 0x01, 0xDD, 0xCB, 0x02,
+    0x46, 0xFD, 0xCB, 0x03, 0xCE, 0xDD, 0x86, 0x00, 0xFD, 0x77, 0x04, 0xED, 0x44, 0xDD, 0xCB, 0x05,
+    0x06, 0x10, 0xE7, 0x18, 0xDB,
+];
+
+/// Returns the best time of a few runs of the program
+fn time_steps(cached: bool) -> Duration {
+    (0..10)
+        .map(|_| {
+            let mut cpu = Cpu::default();
+            cpu.load_program(0, &PROGRAM).unwrap();
+            cpu.set_block_cache(cached);
+            let start = Instant::now();
+            for _ in 0..STEPS {
+                cpu.step().unwrap();
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("plain decoder: {:?} per {STEPS} steps", time_steps(false));
+    println!("block cache:   {:?} per {STEPS} steps", time_steps(true));
+}
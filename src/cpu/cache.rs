@@ -0,0 +1,395 @@
+//! Decoded basic-block cache.
+//!
+//! While enabled, the CPU decodes a run of instructions up to the next
+//! jump, call, return or block instruction in one go, and replays the
+//! decoded instructions on later visits instead of decoding them again.
+//! Only the decode is skipped: each instruction still goes through `step`,
+//! so timing, interrupts and events are exactly those of the plain
+//! interpreter.
+//!
+//! Blocks are keyed by the physical address of their first byte, so a
+//! bank switch never replays code from another bank. The prefix and opcode
+//! bytes of every cached instruction are watched in memory, and a write to
+//! one drops the blocks that decoded it, found through an index of blocks
+//! by 256-byte physical page. Bytes no remaining block decodes are then
+//! unwatched. Operand bytes are fetched live by the handlers, so patching
+//! them leaves the cache alone.
+
+use super::decoder::{DecodedInstruction, Decoder};
+use super::instruction::InstructionType;
+use crate::memory::Memory;
+use crate::Result;
+use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::rc::Rc;
+
+/// Most instructions decoded into one block
+const MAX_BLOCK_INSTRUCTIONS: usize = 32;
+
+/// Shift from a physical address to the page blocks are indexed by
+const PAGE_SHIFT: u32 = 8;
+
+/// A decoded instruction and the physical address of its first byte
+#[derive(Debug, Clone, Copy)]
+struct CachedInstruction {
+    physical: u32,
+    decoded: DecodedInstruction,
+}
+
+impl CachedInstruction {
+    /// Returns the physical bytes decoded for the instruction
+    fn bytes(&self) -> Range<u32> {
+        self.physical..self.physical + u32::from(self.decoded.opcode_length)
+    }
+
+    /// Returns the pages holding the decoded bytes
+    fn pages(&self) -> RangeInclusive<u32> {
+        let bytes = self.bytes();
+        bytes.start >> PAGE_SHIFT..=(bytes.end - 1) >> PAGE_SHIFT
+    }
+
+    /// Returns true if the decoded bytes of the instruction include the
+    /// physical byte at `address`
+    fn covers(&self, address: u32) -> bool {
+        self.bytes().contains(&address)
+    }
+}
+
+/// Decoded instructions keyed by the physical address of the block start
+#[derive(Debug, Default)]
+pub(super) struct BlockCache {
+    blocks: HashMap<u32, Rc<[CachedInstruction]>>,
+    // Starts of the blocks with decoded bytes in each page
+    pages: HashMap<u32, Vec<u32>>,
+    // Start and block of the instruction expected to run next, and its
+    // index, so that replaying within a block needs no lookup
+    cursor: Option<(u32, Rc<[CachedInstruction]>, usize)>,
+}
+
+impl BlockCache {
+    /// Returns the decoded instruction at `address`, from the cache when
+    /// it holds one still matching memory, or decoding a new block. Returns
+    /// `None` for an instruction that cannot be cached, which the CPU then
+    /// decodes itself.
+    pub(super) fn decode(
+        &mut self,
+        decoder: &Decoder,
+        memory: &mut Memory,
+        address: u16,
+    ) -> Result<Option<DecodedInstruction>> {
+        while let Some(written) = memory.take_code_write() {
+            self.invalidate(memory, written);
+        }
+
+        let physical = memory.physical_address(address);
+        if let Some(decoded) = self.replay(memory, physical, address) {
+            return Ok(Some(decoded));
+        }
+
+        let block: Rc<[CachedInstruction]> = Self::decode_block(decoder, memory, address)?.into();
+        let Some(first) = block.first().copied() else {
+            // An instruction whose bytes are not contiguous in physical
+            // memory is never cached
+            self.cursor = None;
+            return Ok(None);
+        };
+        self.remove(memory, physical);
+        self.insert(memory, physical, Rc::clone(&block));
+        self.cursor = Some((physical, block, 1));
+        Ok(Some(first.decoded))
+    }
+
+    /// Adds `block` at `start`, watching its decoded bytes
+    fn insert(&mut self, memory: &mut Memory, start: u32, block: Rc<[CachedInstruction]>) {
+        for cached in block.iter() {
+            let bytes = cached.bytes();
+            memory.watch_code(bytes.start, bytes.end - bytes.start);
+            for page in cached.pages() {
+                let starts = self.pages.entry(page).or_default();
+                if !starts.contains(&start) {
+                    starts.push(start);
+                }
+            }
+        }
+        self.blocks.insert(start, block);
+    }
+
+    /// Drops the blocks that decoded the physical byte at `address`
+    fn invalidate(&mut self, memory: &mut Memory, address: u32) {
+        let Some(starts) = self.pages.get(&(address >> PAGE_SHIFT)) else {
+            return;
+        };
+        let stale: Vec<u32> = starts
+            .iter()
+            .copied()
+            .filter(|start| {
+                self.blocks[start]
+                    .iter()
+                    .any(|cached| cached.covers(address))
+            })
+            .collect();
+        for start in stale {
+            self.remove(memory, start);
+        }
+    }
+
+    /// Drops the block at `start`, if any, and unwatches the bytes it
+    /// decoded that no other block decodes
+    fn remove(&mut self, memory: &mut Memory, start: u32) {
+        let Some(block) = self.blocks.remove(&start) else {
+            return;
+        };
+        if self
+            .cursor
+            .as_ref()
+            .is_some_and(|(cursor, ..)| *cursor == start)
+        {
+            self.cursor = None;
+        }
+        for page in block.iter().flat_map(CachedInstruction::pages) {
+            if let Some(starts) = self.pages.get_mut(&page) {
+                starts.retain(|&other| other != start);
+                if starts.is_empty() {
+                    self.pages.remove(&page);
+                }
+            }
+        }
+        for address in block.iter().flat_map(CachedInstruction::bytes) {
+            if !self.decodes(address) {
+                memory.unwatch_code(address, 1);
+            }
+        }
+    }
+
+    /// Returns true if a cached block decoded the physical byte at `address`
+    fn decodes(&self, address: u32) -> bool {
+        self.pages
+            .get(&(address >> PAGE_SHIFT))
+            .is_some_and(|starts| {
+                starts.iter().any(|start| {
+                    self.blocks[start]
+                        .iter()
+                        .any(|cached| cached.covers(address))
+                })
+            })
+    }
+
+    /// Returns the cached instruction at `address`, which is either the next
+    /// one in the current block or the start of another block, and moves
+    /// the cursor past it
+    fn replay(
+        &mut self,
+        memory: &Memory,
+        physical: u32,
+        address: u16,
+    ) -> Option<DecodedInstruction> {
+        let matches = |cached: &CachedInstruction| {
+            cached.physical == physical
+                && cached.decoded.address == address
+                && Self::contiguous(memory, address, cached.decoded.opcode_length)
+        };
+        if let Some((_, block, index)) = &mut self.cursor {
+            if let Some(cached) = block.get(*index).filter(|cached| matches(cached)) {
+                *index += 1;
+                return Some(cached.decoded);
+            }
+        }
+        let Some(block) = self
+            .blocks
+            .get(&physical)
+            .filter(|block| matches(&block[0]))
+        else {
+            self.cursor = None;
+            return None;
+        };
+        let decoded = block[0].decoded;
+        self.cursor = Some((physical, Rc::clone(block), 1));
+        Some(decoded)
+    }
+
+    /// Decodes instructions from `address` until one that may branch, one
+    /// that cannot be decoded, one whose bytes straddle a bank boundary, or
+    /// the end of the 64 KB logical space. Only a failure to decode the
+    /// first instruction is an error.
+    fn decode_block(
+        decoder: &Decoder,
+        memory: &Memory,
+        mut address: u16,
+    ) -> Result<Vec<CachedInstruction>> {
+        let mut block = Vec::new();
+        while block.len() < MAX_BLOCK_INSTRUCTIONS {
+            let decoded = match decoder.decode(memory, address) {
+                Ok(decoded) => decoded,
+                Err(error) if block.is_empty() => return Err(error),
+                Err(_) => break,
+            };
+            if !Self::contiguous(memory, address, decoded.opcode_length) {
+                break;
+            }
+            block.push(CachedInstruction {
+                physical: memory.physical_address(address),
+                decoded,
+            });
+            if matches!(
+                decoded.instruction.instruction_type,
+                InstructionType::Jump
+                    | InstructionType::Call
+                    | InstructionType::Return
+                    | InstructionType::Block
+            ) {
+                break;
+            }
+            let (next, wrapped) = address.overflowing_add(u16::from(decoded.length));
+            if wrapped {
+                break;
+            }
+            address = next;
+        }
+        Ok(block)
+    }
+
+    /// Returns true if the `length` bytes at `address` map to consecutive
+    /// physical bytes
+    fn contiguous(memory: &Memory, address: u16, length: u8) -> bool {
+        let last = address.wrapping_add(u16::from(length) - 1);
+        memory.physical_address(last) == memory.physical_address(address) + u32::from(length) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockCache;
+    use crate::cpu::decoder::Decoder;
+    use crate::cpu::{Cpu, CpuModel};
+    use crate::memory::Memory;
+
+    /// Runs `program` on a plain and a caching CPU side by side, checking
+    /// that every step leaves both in the same state
+    fn run_both(program: &[u8], steps: usize) -> Cpu {
+        let mut plain = Cpu::default();
+        let mut cached = Cpu::default();
+        plain.load_program(0, program).unwrap();
+        cached.load_program(0, program).unwrap();
+        cached.set_block_cache(true);
+
+        for _ in 0..steps {
+            plain.step().unwrap();
+            cached.step().unwrap();
+            assert_eq!(cached.get_pc(), plain.get_pc());
+            assert_eq!(cached.get_af(), plain.get_af());
+            assert_eq!(cached.get_bc(), plain.get_bc());
+            assert_eq!(cached.get_hl(), plain.get_hl());
+            assert_eq!(cached.get_r(), plain.get_r());
+            assert_eq!(cached.get_t_states(), plain.get_t_states());
+        }
+        cached
+    }
+
+    #[test]
+    fn test_loop_matches_interpreter() {
+        // LD B,10; loop: INC A; ADD HL,BC; DJNZ loop; HALT
+        let cpu = run_both(&[0x06, 0x0A, 0x3C, 0x09, 0x10, 0xFC, 0x76], 40);
+        assert_eq!(cpu.get_a(), 10);
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // LD B,3; loop: LD HL,patch; LD (HL),0x3C; patch: NOP; DJNZ loop.
+        // The NOP is decoded with the block before the store patches it
+        // into INC A, which must then run on every pass.
+        let cpu = run_both(
+            &[
+                0x06, 0x03, 0x21, 0x07, 0x00, 0x36, 0x3C, 0x00, 0x10, 0xF8, 0x76,
+            ],
+            20,
+        );
+        assert_eq!(cpu.get_a(), 3);
+    }
+
+    #[test]
+    fn test_operand_patch_keeps_block() {
+        // loop: LD A,n; INC (HL) with HL on n; DJNZ loop
+        let mut cpu = Cpu::default();
+        cpu.load_program(
+            0,
+            &[0x06, 0x03, 0x21, 0x06, 0x00, 0x3E, 0x00, 0x34, 0x10, 0xFB],
+        )
+        .unwrap();
+        cpu.set_block_cache(true);
+        for _ in 0..11 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_a(), 2);
+        assert!(cpu
+            .block_cache
+            .as_ref()
+            .unwrap()
+            .blocks
+            .contains_key(&0x0005));
+    }
+
+    #[test]
+    fn test_runtime_switch() {
+        let program = [0x06, 0x0A, 0x3C, 0x09, 0x10, 0xFC, 0x76];
+        let mut plain = Cpu::default();
+        let mut switched = Cpu::default();
+        plain.load_program(0, &program).unwrap();
+        switched.load_program(0, &program).unwrap();
+        for step in 0..30 {
+            switched.set_block_cache(step % 7 < 4);
+            plain.step().unwrap();
+            switched.step().unwrap();
+        }
+        assert_eq!(switched.get_pc(), plain.get_pc());
+        assert_eq!(switched.get_a(), plain.get_a());
+        assert_eq!(switched.get_t_states(), plain.get_t_states());
+    }
+
+    #[test]
+    fn test_bank_switch_redecodes() {
+        // The same logical address holds different code in two banks
+        let mut cpu = Cpu::new(Memory::with_mmu(), CpuModel::Z180);
+        cpu.set_block_cache(true);
+        cpu.memory
+            .load_physical(0x8_1000, &[0x3C, 0x18, 0xFD])
+            .unwrap();
+        cpu.memory
+            .load_physical(0x9_1000, &[0x3D, 0x18, 0xFD])
+            .unwrap();
+        cpu.memory.mmu_mut().unwrap().cbar = 0xF1;
+        cpu.memory.mmu_mut().unwrap().bbr = 0x80;
+        cpu.pc = 0x1000;
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_a(), 2);
+        cpu.memory.mmu_mut().unwrap().bbr = 0x90;
+        for _ in 0..2 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_a(), 1);
+    }
+
+    #[test]
+    fn test_dropped_block_unwatches_its_bytes() {
+        // Blocks of NOPs from 0x0000 and 0x0004 overlap from 0x0004 on
+        let decoder = Decoder::default();
+        let mut memory = Memory::default();
+        memory.track_code(true);
+        let mut cache = BlockCache::default();
+        cache.decode(&decoder, &mut memory, 0x0000).unwrap();
+        cache.decode(&decoder, &mut memory, 0x0004).unwrap();
+
+        memory.write_byte(0x0002, 0x00).unwrap();
+        cache.decode(&decoder, &mut memory, 0x0005).unwrap();
+        assert!(!cache.blocks.contains_key(&0x0000));
+        assert!(cache.blocks.contains_key(&0x0004));
+
+        // Bytes only the dropped block decoded are no longer watched
+        memory.write_byte(0x0001, 0x00).unwrap();
+        assert_eq!(memory.take_code_write(), None);
+        memory.write_byte(0x0005, 0x00).unwrap();
+        assert_eq!(memory.take_code_write(), Some(0x0005));
+    }
+}
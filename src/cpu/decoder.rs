@@ -1,9 +1,15 @@
 //! Decoder module handles Z80 instruction decoding and prefix handling
 
-use super::instruction::{create_nop, Instruction, InstructionType};
+use super::instruction::Instruction;
+use super::model::CpuModel;
 use super::tables::InstructionTables;
+use crate::memory::Memory;
 use crate::Result;
-use std::sync::LazyLock;
+
+/// T-states of a prefix byte that executes as a NOP
+const IGNORED_PREFIX_T_STATES: u32 = 4;
+/// R800 cycles of a prefix byte that executes as a NOP
+const R800_IGNORED_PREFIX_T_STATES: u32 = 1;
 
 /// Represents Z80 instruction prefixes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,11 +23,44 @@ pub enum Prefix {
     FdCb, // IY bit operations
 }
 
+/// A complete instruction as decoded from memory, including its prefixes
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedInstruction {
+    /// Address of the first byte, including any prefixes
+    pub address: u16,
+    /// Prefix selecting the table the opcode was found in
+    pub prefix: Prefix,
+    /// The opcode byte following the prefixes
+    pub opcode: u8,
+    /// Displacement of a DDCB/FDCB instruction, which precedes its opcode
+    pub displacement: Option<i8>,
+    /// eZ80 mode suffix (.SIS, .LIS, .SIL or .LIL) ahead of the instruction
+    pub suffix: Option<u8>,
+    /// Bytes up to and including the opcode, where operands begin
+    pub opcode_length: u8,
+    /// Opcode fetch (M1) cycles, one per prefix and one for the opcode
+    /// itself, except that a DDCB/FDCB opcode is read as ordinary data.
+    /// Each increments R.
+    pub m1_cycles: u8,
+    /// Bytes in the whole instruction, including prefixes that execute as
+    /// NOPs and any mode suffix
+    pub length: u8,
+    /// T-states of the whole instruction, including prefixes that execute
+    /// as NOPs and any mode suffix
+    pub t_states: u32,
+    /// Entry in the shared instruction tables
+    pub instruction: &'static Instruction,
+}
+
 /// The main instruction decoder
 pub struct Decoder {
-    current_prefix: Prefix,
-    tables: InstructionTables,
-    current_prefix_t_states: u8,
+    tables: &'static InstructionTables,
+    // Opcodes that act as prefixes on the emulated part
+    prefixes: &'static [u8],
+    // Opcodes that act as mode suffixes on the emulated part
+    suffixes: &'static [u8],
+    // T-states of each prefix that executes as a NOP
+    ignored_prefix_t_states: u32,
 }
 
 impl Default for Decoder {
@@ -32,117 +71,278 @@ impl Default for Decoder {
 
 impl Decoder {
     pub fn new() -> Self {
+        Self::for_model(CpuModel::default())
+    }
+
+    /// Creates a decoder for the instruction set of `model`
+    pub fn for_model(model: CpuModel) -> Self {
         Self {
-            current_prefix: Prefix::None,
-            tables: InstructionTables::new(),
-            current_prefix_t_states: 0,
+            tables: InstructionTables::for_model(model),
+            prefixes: model.prefixes(),
+            suffixes: model.suffixes(),
+            ignored_prefix_t_states: if model.is_r800() {
+                R800_IGNORED_PREFIX_T_STATES
+            } else {
+                IGNORED_PREFIX_T_STATES
+            },
         }
     }
 
-    /// Handles prefix bytes and updates decoder state
-    pub fn handle_prefix(&mut self, opcode: u8) -> bool {
-        match (self.current_prefix, opcode) {
-            (Prefix::None, 0xCB) => {
-                self.current_prefix = Prefix::Cb;
-                true
-            }
-            (Prefix::None, 0xDD) => {
-                self.current_prefix = Prefix::Dd;
-                true
-            }
-            (Prefix::None, 0xFD) => {
-                self.current_prefix = Prefix::Fd;
-                true
-            }
-            (Prefix::None, 0xED) => {
-                self.current_prefix = Prefix::Ed;
-                true
-            }
-            (Prefix::Dd, 0xCB) => {
-                self.current_prefix = Prefix::DdCb;
-                true
-            }
-            (Prefix::Fd, 0xCB) => {
-                self.current_prefix = Prefix::FdCb;
-                true
-            }
-            _ => false,
+    /// Returns the prefix state after `opcode` is read in state `prefix`, or
+    /// `None` if the byte is not a prefix there
+    fn next_prefix(prefix: Prefix, opcode: u8) -> Option<Prefix> {
+        match (prefix, opcode) {
+            (Prefix::None, 0xCB) => Some(Prefix::Cb),
+            // A DD or FD followed by another prefix acts as a NOP, and the
+            // last index prefix wins
+            (Prefix::None | Prefix::Dd | Prefix::Fd, 0xDD) => Some(Prefix::Dd),
+            (Prefix::None | Prefix::Dd | Prefix::Fd, 0xFD) => Some(Prefix::Fd),
+            (Prefix::None | Prefix::Dd | Prefix::Fd, 0xED) => Some(Prefix::Ed),
+            (Prefix::Dd, 0xCB) => Some(Prefix::DdCb),
+            (Prefix::Fd, 0xCB) => Some(Prefix::FdCb),
+            _ => None,
         }
     }
 
-    /// Decodes an opcode based on the current prefix state
-    pub fn decode(&mut self, opcode: u8) -> Result<Instruction> {
-        // Check for prefix byte
-        if self.handle_prefix(opcode) {
-            self.current_prefix_t_states += 4; // Add T-states for prefix byte
-            return Ok(PREFIX_INSTRUCTION.clone());
-        }
+    /// Decodes the instruction at `address`, reading its prefixes, the
+    /// DDCB/FDCB displacement and the opcode. Operands are left for the
+    /// handler to fetch.
+    pub fn decode(&self, memory: &Memory, address: u16) -> Result<DecodedInstruction> {
+        self.decode_from(address, |offset| {
+            memory.read_byte(address.wrapping_add(offset))
+        })
+    }
+
+    /// Decodes an instruction whose bytes are supplied by `read`, which is
+    /// given the offset of each byte from the start of the instruction
+    pub(crate) fn decode_from(
+        &self,
+        address: u16,
+        mut read: impl FnMut(u16) -> Result<u8>,
+    ) -> Result<DecodedInstruction> {
+        let mut offset = 0u16;
+        let mut next_byte = || {
+            let byte = read(offset);
+            offset += 1;
+            byte
+        };
+
+        // Prefixes that are superseded, or that precede an opcode they do
+        // not affect, each execute as a NOP
+        let mut ignored_prefixes = 0u8;
+        let mut prefix = Prefix::None;
+        let mut displacement = None;
+        let mut m1_cycles = 1;
+        let mut opcode = next_byte()?;
 
-        // Reset prefix T-states on non-prefix instruction
-        let prefix_t_states = u32::from(self.current_prefix_t_states);
-        self.current_prefix_t_states = 0;
+        // A mode suffix is fetched like a prefix and costs the same
+        let suffix = self.suffixes.contains(&opcode).then_some(opcode);
+        if suffix.is_some() {
+            opcode = next_byte()?;
+            m1_cycles += 1;
+        }
+        while let Some(next) = self
+            .prefixes
+            .contains(&opcode)
+            .then(|| Self::next_prefix(prefix, opcode))
+            .flatten()
+        {
+            if prefix != Prefix::None && !matches!(next, Prefix::DdCb | Prefix::FdCb) {
+                ignored_prefixes += 1;
+            }
+            prefix = next;
+            // In DDCB/FDCB instructions the displacement byte sits between
+            // CB and the opcode
+            if matches!(prefix, Prefix::DdCb | Prefix::FdCb) {
+                displacement = Some(next_byte()? as i8);
+                opcode = next_byte()?;
+                break;
+            }
+            opcode = next_byte()?;
+            m1_cycles += 1;
+        }
 
-        // Decode based on current prefix
-        let mut instruction = match self.current_prefix {
-            Prefix::None => self.tables.lookup_main(opcode),
+        let prefixed = match prefix {
+            Prefix::None => None,
             Prefix::Cb => self.tables.lookup_cb(opcode),
             Prefix::Ed => self.tables.lookup_ed(opcode),
-            Prefix::Dd | Prefix::Fd => self.tables.lookup_ddfd(opcode),
-            _ => None,
-        }
-        .cloned()
-        .ok_or(crate::EmulatorError::InvalidOpcode(opcode))?;
+            Prefix::Dd => self.tables.lookup_dd(opcode),
+            Prefix::Fd => self.tables.lookup_fd(opcode),
+            Prefix::DdCb => self.tables.lookup_ddcb(opcode),
+            Prefix::FdCb => self.tables.lookup_fdcb(opcode),
+        };
 
-        // Add prefix timing to instruction
-        instruction.t_states += prefix_t_states;
+        let (prefix, instruction) = match (prefixed, prefix) {
+            (Some(instruction), _) => (prefix, instruction),
+            // DD or FD before an opcode that does not use HL leaves the
+            // opcode unchanged
+            (None, Prefix::None | Prefix::Dd | Prefix::Fd) => {
+                if prefix != Prefix::None {
+                    ignored_prefixes += 1;
+                }
+                let instruction = self
+                    .tables
+                    .lookup_main(opcode)
+                    .ok_or(crate::EmulatorError::InvalidOpcode(opcode))?;
+                (Prefix::None, instruction)
+            }
+            (None, _) => return Err(crate::EmulatorError::InvalidOpcode(opcode)),
+        };
 
-        // Reset prefix state
-        self.current_prefix = Prefix::None;
+        let extra_bytes = ignored_prefixes + u8::from(suffix.is_some());
 
-        Ok(instruction)
+        Ok(DecodedInstruction {
+            address,
+            prefix,
+            opcode,
+            displacement,
+            suffix,
+            opcode_length: offset as u8,
+            m1_cycles,
+            length: instruction.length + extra_bytes,
+            t_states: instruction.t_states + self.ignored_prefix_t_states * u32::from(extra_bytes),
+            instruction,
+        })
     }
 }
 
-static PREFIX_INSTRUCTION: LazyLock<Instruction> =
-    LazyLock::new(|| Instruction::new("PREFIX", 1, 4, InstructionType::Special, create_nop()));
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cpu::InstructionType;
+
+    /// Decodes `bytes` placed at address 0
+    fn decode(bytes: &[u8]) -> DecodedInstruction {
+        let mut memory = Memory::new();
+        memory.load(0, bytes).unwrap();
+        Decoder::new().decode(&memory, 0).unwrap()
+    }
 
     #[test]
     fn test_nop_decoding() {
-        let mut decoder = Decoder::new();
-        let instruction = decoder.decode(0x00).unwrap();
+        let decoded = decode(&[0x00]);
+        let instruction = decoded.instruction;
 
         assert_eq!(instruction.length, 1);
         assert_eq!(instruction.mnemonic, "NOP");
         assert_eq!(instruction.instruction_type, InstructionType::Control);
         assert_eq!(instruction.t_states, 4);
+        assert_eq!(decoded.opcode_length, 1);
+    }
+
+    #[test]
+    fn test_undefined_ed_opcode() {
+        let instruction = decode(&[0xED, 0x00]).instruction;
+        assert_eq!(instruction.mnemonic, "NOP");
+        assert_eq!(instruction.t_states, 8);
+        assert_eq!(instruction.length, 2);
+    }
+
+    #[test]
+    fn test_rst_decoding() {
+        let instruction = decode(&[0xFF]).instruction;
+
+        assert_eq!(instruction.mnemonic, "RST 38H");
+        assert_eq!(instruction.instruction_type, InstructionType::Call);
+        assert_eq!(instruction.t_states, 11);
+    }
+
+    #[test]
+    fn test_prefixed_decoding() {
+        let decoded = decode(&[0xCB, 0x06]);
+        assert_eq!(decoded.prefix, Prefix::Cb);
+        assert_eq!(decoded.opcode, 0x06);
+        assert_eq!(decoded.instruction.mnemonic, "RLC (HL)");
+        assert_eq!(decoded.t_states, 15);
+        assert_eq!(decoded.length, 2);
+        assert_eq!(decoded.opcode_length, 2);
+        assert_eq!(decoded.m1_cycles, 2);
     }
 
     #[test]
-    fn test_invalid_opcode() {
-        let mut decoder = Decoder::new();
-        let result = decoder.decode(0xFF);
-        assert!(matches!(
-            result,
-            Err(crate::EmulatorError::InvalidOpcode(0xFF))
-        ));
+    fn test_indexed_bit_decoding() {
+        // The displacement sits between CB and the opcode
+        let decoded = decode(&[0xFD, 0xCB, 0xFE, 0x46]);
+        assert_eq!(decoded.prefix, Prefix::FdCb);
+        assert_eq!(decoded.displacement, Some(-2));
+        assert_eq!(decoded.opcode, 0x46);
+        assert_eq!(decoded.instruction.mnemonic, "BIT 0, (IY+d)");
+        assert_eq!(decoded.t_states, 20);
+        assert_eq!(decoded.length, 4);
+        assert_eq!(decoded.opcode_length, 4);
+        assert_eq!(decoded.m1_cycles, 2);
+    }
+
+    #[test]
+    fn test_index_prefix_fallback() {
+        // DD before an opcode that does not use HL decodes the plain opcode
+        let decoded = decode(&[0xDD, 0x00]);
+        assert_eq!(decoded.prefix, Prefix::None);
+        assert_eq!(decoded.instruction.mnemonic, "NOP");
+        assert_eq!(decoded.t_states, 8);
+        assert_eq!(decoded.length, 2);
+
+        // A following prefix supersedes DD, which then counts as a NOP
+        let decoded = decode(&[0xDD, 0xFD, 0x21, 0x34, 0x12]);
+        assert_eq!(decoded.prefix, Prefix::Fd);
+        assert_eq!(decoded.instruction.mnemonic, "LD IY, nn");
+        assert_eq!(decoded.t_states, 18);
+        assert_eq!(decoded.length, 5);
+        assert_eq!(decoded.opcode_length, 3);
+
+        let decoded = decode(&[0xFD, 0xED, 0x44]);
+        assert_eq!(decoded.prefix, Prefix::Ed);
+        assert_eq!(decoded.instruction.mnemonic, "NEG");
+        assert_eq!(decoded.t_states, 12);
     }
 
     #[test]
     fn test_prefix_handling() {
-        let mut decoder = Decoder::new();
+        assert_eq!(Decoder::next_prefix(Prefix::None, 0xCB), Some(Prefix::Cb));
+        assert_eq!(Decoder::next_prefix(Prefix::None, 0xDD), Some(Prefix::Dd));
+        assert_eq!(Decoder::next_prefix(Prefix::Dd, 0xCB), Some(Prefix::DdCb));
+        assert_eq!(Decoder::next_prefix(Prefix::Cb, 0xDD), None);
+        assert_eq!(Decoder::next_prefix(Prefix::Ed, 0xED), None);
+    }
 
-        assert!(decoder.handle_prefix(0xCB));
-        assert_eq!(decoder.current_prefix, Prefix::Cb);
+    #[test]
+    fn test_8080_decoding() {
+        let decoder = Decoder::for_model(CpuModel::I8080);
+        let mut memory = Memory::new();
+        memory.load(0, &[0xDD, 0x34, 0x12, 0x10, 0x04]).unwrap();
+
+        // DD is an alias of CALL rather than a prefix
+        let decoded = decoder.decode(&memory, 0).unwrap();
+        assert_eq!(decoded.prefix, Prefix::None);
+        assert_eq!(decoded.instruction.mnemonic, "CALL nn");
+        assert_eq!(decoded.length, 3);
+        assert_eq!(decoded.t_states, 17);
+
+        // DJNZ is a NOP and INR takes 5 T-states
+        let decoded = decoder.decode(&memory, 3).unwrap();
+        assert_eq!(decoded.instruction.mnemonic, "NOP");
+        assert_eq!(decoded.length, 1);
+        assert_eq!(decoder.decode(&memory, 4).unwrap().t_states, 5);
+    }
+
+    #[test]
+    fn test_ez80_suffix_decoding() {
+        let decoder = Decoder::for_model(CpuModel::Ez80);
+        let mut memory = Memory::new();
+        memory
+            .load(0, &[0x5B, 0xDD, 0x21, 0x56, 0x34, 0x12, 0x40])
+            .unwrap();
 
-        decoder.current_prefix = Prefix::None;
-        assert!(decoder.handle_prefix(0xDD));
-        assert_eq!(decoder.current_prefix, Prefix::Dd);
+        let decoded = decoder.decode(&memory, 0).unwrap();
+        assert_eq!(decoded.suffix, Some(0x5B));
+        assert_eq!(decoded.prefix, Prefix::Dd);
+        assert_eq!(decoded.instruction.mnemonic, "LD IX, nn");
+        assert_eq!(decoded.opcode_length, 3);
+        assert_eq!(decoded.m1_cycles, 3);
 
-        assert!(decoder.handle_prefix(0xCB));
-        assert_eq!(decoder.current_prefix, Prefix::DdCb);
+        // LD B,B is an ordinary load on the Z80
+        let decoded = Decoder::new().decode(&memory, 6).unwrap();
+        assert_eq!(decoded.suffix, None);
+        assert_eq!(decoded.instruction.mnemonic, "LD B, B");
     }
 }
@@ -0,0 +1,124 @@
+//! Zilog eZ80 mode state.
+//!
+//! The eZ80 runs either in Z80 mode, where addresses are 16 bits with MBASE
+//! supplying A23-A16, or in ADL mode, where PC, SP and the register pairs
+//! are 24 bits wide. The upper bytes of the pairs are kept here alongside
+//! the Z80 registers. A mode suffix (.SIS, .LIS, .SIL, .LIL) overrides the
+//! register and immediate widths for a single instruction.
+
+use super::Cpu;
+
+/// ADL mode flag, MBASE and the upper bytes that extend the register
+/// pairs, PC and SP to 24 bits
+#[derive(Debug, Default, Clone)]
+pub struct Ez80 {
+    /// ADL mode: 24-bit addresses and registers. Clear in Z80 mode.
+    pub adl: bool,
+    /// Mixed-memory mode flag, set by STMIX and cleared by RSMIX
+    pub madl: bool,
+    /// MBASE, the upper address byte of 16-bit addresses
+    pub mb: u8,
+    /// Upper byte of BC
+    pub bcu: u8,
+    /// Upper byte of DE
+    pub deu: u8,
+    /// Upper byte of HL
+    pub hlu: u8,
+    /// Upper byte of IX
+    pub ixu: u8,
+    /// Upper byte of IY
+    pub iyu: u8,
+    /// Upper byte of PC, used in ADL mode
+    pub pcu: u8,
+    /// The ADL mode stack pointer. Z80 mode uses SPS, the Z80 SP.
+    pub spl: u32,
+    // Upper bytes of the alternate BC', DE' and HL'
+    bcu_prime: u8,
+    deu_prime: u8,
+    hlu_prime: u8,
+    // Widths of the executing instruction: 24-bit registers and data (L)
+    // and 24-bit immediates (IL), and whether a suffix chose them
+    long_data: bool,
+    long_immediate: bool,
+    suffixed: bool,
+}
+
+impl Ez80 {
+    /// Returns the address of `pc` in the current code bank: PCU in ADL
+    /// mode and MBASE in Z80 mode
+    pub fn code_address(&self, pc: u16) -> u32 {
+        (u32::from(self.code_base()) << 16) | u32::from(pc)
+    }
+
+    /// Returns the code address `offset` bytes on from `pc`. In ADL mode the
+    /// offset carries into PCU, and in Z80 mode it wraps within the bank.
+    pub fn code_address_at(&self, pc: u16, offset: u16) -> u32 {
+        if self.adl {
+            self.code_address(pc).wrapping_add(u32::from(offset)) & 0xFF_FFFF
+        } else {
+            self.code_address(pc.wrapping_add(offset))
+        }
+    }
+
+    /// Returns the upper address byte of instruction fetches
+    fn code_base(&self) -> u8 {
+        if self.adl {
+            self.pcu
+        } else {
+            self.mb
+        }
+    }
+
+    /// Returns true if the executing instruction has 24-bit registers
+    pub(super) fn long_data(&self) -> bool {
+        self.long_data
+    }
+
+    /// Returns true if the executing instruction has 24-bit immediates
+    pub(super) fn long_immediate(&self) -> bool {
+        self.long_immediate
+    }
+
+    /// Returns true if the executing instruction has a mode suffix
+    pub(super) fn suffixed(&self) -> bool {
+        self.suffixed
+    }
+
+    /// Swaps the upper bytes of BC, DE and HL with their alternates, as
+    /// EXX does
+    pub(super) fn exchange_upper(&mut self) {
+        std::mem::swap(&mut self.bcu, &mut self.bcu_prime);
+        std::mem::swap(&mut self.deu, &mut self.deu_prime);
+        std::mem::swap(&mut self.hlu, &mut self.hlu_prime);
+    }
+}
+
+impl Cpu {
+    /// Returns the eZ80 mode state
+    pub fn ez80(&mut self) -> Option<&mut Ez80> {
+        self.ez80.as_mut()
+    }
+
+    /// Selects the code bank for fetching the next instruction
+    pub(super) fn select_ez80_code_bank(&mut self) {
+        if let Some(ez80) = &self.ez80 {
+            self.memory.set_address_base(ez80.code_base());
+        }
+    }
+
+    /// Sets the register and immediate widths of the next instruction from
+    /// its suffix, or from the ADL flag without one, and points 16-bit
+    /// data addresses at HLU or MBASE accordingly
+    pub(super) fn begin_ez80_instruction(&mut self, suffix: Option<u8>) {
+        let Some(ez80) = &mut self.ez80 else {
+            return;
+        };
+        (ez80.long_data, ez80.long_immediate) = match suffix {
+            Some(suffix) => (suffix & 0x01 != 0, suffix & 0x10 != 0),
+            None => (ez80.adl, ez80.adl),
+        };
+        ez80.suffixed = suffix.is_some();
+        let base = if ez80.long_data { ez80.hlu } else { ez80.mb };
+        self.memory.set_address_base(base);
+    }
+}
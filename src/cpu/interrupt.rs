@@ -0,0 +1,388 @@
+//! Maskable and non-maskable interrupt acceptance.
+//!
+//! Interrupts are sampled at instruction boundaries only, which fall between
+//! calls to `Cpu::step`, and never straight after EI. Accepting one takes the
+//! place of an instruction within `Cpu::step`.
+
+use super::{Cpu, IndexMode, Prefix};
+use crate::Result;
+
+/// Acknowledge T-states for NMI: a 5 T-state opcode fetch plus the push
+const NMI_T_STATES: u32 = 11;
+/// Acknowledge T-states for IM 1
+const IM1_T_STATES: u32 = 13;
+/// Extra T-states an IM 0 acknowledge adds to the injected instruction's
+/// own timing (13 for RST, 19 for CALL)
+const IM0_EXTRA_T_STATES: u32 = 2;
+/// Acknowledge T-states for IM 2, which also reads the vector table
+const IM2_T_STATES: u32 = 19;
+
+/// Acknowledge T-states on the LR35902, which dispatches like a CALL
+const LR35902_T_STATES: u32 = 20;
+
+/// Restart address taken by a non-maskable interrupt
+const NMI_VECTOR: u16 = 0x0066;
+/// Restart address taken in interrupt mode 1
+const IM1_VECTOR: u16 = 0x0038;
+
+impl Cpu {
+    /// Accepts a pending NMI or maskable interrupt, returning the T-states
+    /// the acknowledge took
+    pub(super) fn accept_interrupt(&mut self) -> Result<Option<u32>> {
+        // EI holds off maskable interrupts until after the next instruction
+        let ei_delay = std::mem::take(&mut self.ei_delay);
+
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            // The acknowledge is an opcode fetch cycle and refreshes memory
+            self.increment_r();
+            self.halted = false;
+            self.iff2 = self.iff1;
+            self.iff1 = false;
+            self.interrupt_call(NMI_VECTOR)?;
+            return Ok(Some(NMI_T_STATES));
+        }
+
+        if !self.iff1 || ei_delay {
+            return Ok(None);
+        }
+
+        // The on-chip peripherals of a Z180 rank below the INT input and
+        // always supply a vector from I and IL, whatever the mode
+        if !(self.int_line || self.int_pending) {
+            let Some(low) = self.z180_interrupt_vector() else {
+                return Ok(None);
+            };
+            self.increment_r();
+            self.halted = false;
+            self.iff1 = false;
+            self.iff2 = false;
+            let address = self.read_word(u16::from_le_bytes([low, self.i]))?;
+            self.interrupt_call(address)?;
+            return Ok(Some(IM2_T_STATES));
+        }
+
+        self.int_pending = false;
+        self.increment_r();
+        if self.iff2_read && self.model.has_ld_a_ir_bug() {
+            self.flags.parity = false;
+        }
+        self.halted = false;
+        self.iff1 = false;
+        self.iff2 = false;
+
+        // The LR35902 calls a vector in page 0 chosen by the interrupt
+        // controller, which the device supplies as the acknowledge byte
+        if self.model.is_lr35902() {
+            let vector = self.io.interrupt_acknowledge();
+            self.interrupt_call(u16::from(vector))?;
+            return Ok(Some(LR35902_T_STATES));
+        }
+
+        let t_states = match self.im {
+            0 => self.execute_bus_instruction()?,
+            1 => {
+                // The acknowledge cycle still runs, but the byte is ignored
+                self.io.interrupt_acknowledge();
+                self.interrupt_call(IM1_VECTOR)?;
+                IM1_T_STATES
+            }
+            _ => {
+                let low = self.io.interrupt_acknowledge();
+                let address = self.read_word(u16::from_le_bytes([low, self.i]))?;
+                self.interrupt_call(address)?;
+                IM2_T_STATES
+            }
+        };
+        Ok(Some(t_states))
+    }
+
+    /// Executes the instruction the interrupting device supplies in IM 0.
+    /// Its prefixes, opcode and operands are all read from the data bus
+    /// rather than memory, and PC stays put, so RST and CALL push the
+    /// address of the interrupted instruction.
+    fn execute_bus_instruction(&mut self) -> Result<u32> {
+        let decoded = self
+            .decoder
+            .decode_from(self.pc, |_| Ok(self.io.interrupt_acknowledge()))?;
+        self.begin_ez80_instruction(decoded.suffix);
+        // The acknowledge cycle stood in for the first opcode fetch
+        for _ in 1..decoded.m1_cycles {
+            self.increment_r();
+        }
+        self.displacement = decoded.displacement;
+        self.index_mode = match decoded.prefix {
+            Prefix::Dd | Prefix::DdCb => IndexMode::Ix,
+            Prefix::Fd | Prefix::FdCb => IndexMode::Iy,
+            _ => IndexMode::Hl,
+        };
+        self.opcode = decoded.opcode;
+        self.operand_t_states = 0;
+
+        self.fetch_from_bus = true;
+        let result = (decoded.instruction.execute)(self);
+        self.fetch_from_bus = false;
+        result?;
+
+        self.q = if decoded.instruction.affects_flags {
+            self.flags.to_byte()
+        } else {
+            0
+        };
+        Ok(decoded.t_states + IM0_EXTRA_T_STATES - self.operand_t_states)
+    }
+
+    /// Pushes PC and continues at `address`
+    fn interrupt_call(&mut self, address: u16) -> Result<()> {
+        if self.ez80.is_some() {
+            self.begin_ez80_instruction(None);
+            self.call_long(u32::from(address))?;
+        } else {
+            self.push_word(self.pc)?;
+            self.pc = address;
+            self.wz = address;
+        }
+        self.q = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{Cpu, CpuModel};
+    use crate::event::Event;
+    use crate::io::IoDevice;
+    use crate::memory::Memory;
+    use std::collections::VecDeque;
+
+    /// A device that places a fixed sequence of bytes on the data bus
+    struct BusDevice(VecDeque<u8>);
+
+    impl IoDevice for BusDevice {
+        fn read_port(&mut self, _port: u16) -> u8 {
+            0xFF
+        }
+
+        fn write_port(&mut self, _port: u16, _value: u8) {}
+
+        fn interrupt_acknowledge(&mut self) -> u8 {
+            self.0.pop_front().expect("unexpected acknowledge cycle")
+        }
+    }
+
+    /// Runs EI; NOP in the given mode and raises INT with `bus` on the data
+    /// bus, returning the CPU after the acknowledge and its T-states
+    fn acknowledge(im: u8, bus: &[u8]) -> (Cpu, u32) {
+        let mut cpu = interruptible(&[0xFB, 0x00]);
+        cpu.im = im;
+        cpu.set_io_device(Box::new(BusDevice(bus.iter().copied().collect())));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.set_int_line(true);
+        let before = cpu.get_t_states();
+        cpu.step().unwrap();
+        let t_states = cpu.get_t_states() - before;
+        (cpu, t_states)
+    }
+
+    /// Loads a program at 0x0000 with the stack at 0x8000
+    fn interruptible(program: &[u8]) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, program).unwrap();
+        cpu.sp = 0x8000;
+        cpu
+    }
+
+    #[test]
+    fn test_im1_interrupt() {
+        let mut cpu = interruptible(&[0xFB, 0x00, 0x00]); // EI; NOP; NOP
+        cpu.step().unwrap();
+        cpu.set_int_line(true);
+
+        // The instruction after EI always runs first
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0002);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0038);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0002);
+        assert!(!cpu.get_iff1() && !cpu.get_iff2());
+        assert_eq!(cpu.get_t_states(), 4 + 4 + 13);
+    }
+
+    #[test]
+    fn test_interrupt_ignored_when_disabled() {
+        let mut cpu = interruptible(&[0x00, 0x00]);
+        cpu.set_int_line(true);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0002);
+    }
+
+    #[test]
+    fn test_im2_interrupt() {
+        // IM 2; EI; NOP with I = 0x40 and the vector at 0x40FF
+        let mut cpu = interruptible(&[0xED, 0x5E, 0xFB, 0x00]);
+        cpu.i = 0x40;
+        cpu.write_word(0x40FF, 0x1234).unwrap();
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        cpu.set_int_line(true);
+        let before = cpu.get_t_states();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.get_pc(), 0x1234);
+        assert_eq!(cpu.get_t_states() - before, 19);
+    }
+
+    #[test]
+    fn test_nmi() {
+        let mut cpu = interruptible(&[0xFB, 0x00]); // EI; NOP
+        cpu.step().unwrap();
+        cpu.request_nmi();
+        cpu.step().unwrap();
+
+        // NMI is not held off by EI and keeps the old IFF1 in IFF2
+        assert_eq!(cpu.get_pc(), 0x0066);
+        assert!(!cpu.get_iff1());
+        assert!(cpu.get_iff2());
+        assert_eq!(cpu.get_t_states(), 4 + 11);
+
+        // RETN restores IFF1
+        cpu.load_program(0x0066, &[0xED, 0x45]).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0001);
+        assert!(cpu.get_iff1());
+    }
+
+    #[test]
+    fn test_ld_a_i_interrupt_bug() {
+        // EI; NOP; LD A,I with the interrupt accepted straight afterwards
+        for (model, parity) in [(CpuModel::ZilogNmos, false), (CpuModel::ZilogCmos, true)] {
+            let mut cpu = Cpu::new(Memory::default(), model);
+            cpu.load_program(0, &[0xFB, 0x00, 0xED, 0x57]).unwrap();
+            cpu.sp = 0x8000;
+            cpu.step().unwrap();
+            cpu.step().unwrap();
+            cpu.step().unwrap();
+            assert!(cpu.flags.parity);
+
+            cpu.set_int_line(true);
+            cpu.step().unwrap();
+            assert_eq!(cpu.get_pc(), 0x0038);
+            assert_eq!(cpu.flags.parity, parity, "{model:?}");
+        }
+    }
+
+    #[test]
+    fn test_interrupt_not_taken_after_prefix() {
+        // EI; LD IX,0x1234
+        let mut cpu = interruptible(&[0xFB, 0xDD, 0x21, 0x34, 0x12]);
+        cpu.step().unwrap();
+        cpu.set_int_line(true);
+
+        // The prefixed instruction runs whole in a single step
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_ix(), 0x1234);
+        assert_eq!(cpu.get_pc(), 0x0005);
+        assert_eq!(cpu.get_t_states(), 4 + 14);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0038);
+    }
+
+    #[test]
+    fn test_scheduled_interrupt_event() {
+        let mut cpu = interruptible(&[0xFB, 0x00, 0x00, 0x00]);
+        cpu.schedule_event(Event::Interrupt, 8);
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_pc(), 0x0038);
+
+        // The request was consumed by the acknowledge
+        cpu.load_program(0x0038, &[0xFB, 0x00, 0x00]).unwrap();
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_pc(), 0x003B);
+    }
+
+    #[test]
+    fn test_im0_rst_from_bus() {
+        let (cpu, t_states) = acknowledge(0, &[0xD7]); // RST 10H
+        assert_eq!(cpu.get_pc(), 0x0010);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0002);
+        assert_eq!(t_states, 13);
+    }
+
+    #[test]
+    fn test_im0_call_from_bus() {
+        let (cpu, t_states) = acknowledge(0, &[0xCD, 0x34, 0x12]); // CALL 0x1234
+        assert_eq!(cpu.get_pc(), 0x1234);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0002);
+        assert_eq!(t_states, 19);
+    }
+
+    #[test]
+    fn test_im0_single_byte_instruction() {
+        let (cpu, t_states) = acknowledge(0, &[0x3C]); // INC A
+        assert_eq!(cpu.get_a(), 0x01);
+        assert_eq!(cpu.get_pc(), 0x0002);
+        assert_eq!(cpu.get_sp(), 0x8000);
+        assert_eq!(t_states, 6);
+    }
+
+    #[test]
+    fn test_im0_operands_from_bus() {
+        let (cpu, t_states) = acknowledge(0, &[0x21, 0x34, 0x12]); // LD HL,0x1234
+        assert_eq!(cpu.get_hl(), 0x1234);
+        assert_eq!(cpu.get_pc(), 0x0002);
+        assert_eq!(cpu.get_sp(), 0x8000);
+        assert_eq!(t_states, 12);
+
+        let (cpu, t_states) = acknowledge(0, &[0x3E, 0x42]); // LD A,0x42
+        assert_eq!(cpu.get_a(), 0x42);
+        assert_eq!(cpu.get_pc(), 0x0002);
+        assert_eq!(t_states, 9);
+    }
+
+    #[test]
+    fn test_im0_prefixed_instruction() {
+        let (cpu, t_states) = acknowledge(0, &[0xDD, 0x21, 0xCD, 0xAB]); // LD IX,0xABCD
+        assert_eq!(cpu.get_ix(), 0xABCD);
+        assert_eq!(cpu.get_pc(), 0x0002);
+        assert_eq!(t_states, 16);
+    }
+
+    #[test]
+    fn test_im2_vector_from_bus() {
+        let mut cpu = interruptible(&[0xFB, 0x00]);
+        cpu.i = 0x80;
+        cpu.im = 2;
+        cpu.write_word(0x8020, 0xBEEF).unwrap();
+        cpu.set_io_device(Box::new(BusDevice(VecDeque::from([0x20]))));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.set_int_line(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0xBEEF);
+    }
+
+    #[test]
+    fn test_lr35902_vector_from_bus() {
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::Lr35902);
+        cpu.load_program(0, &[0xFB, 0x00]).unwrap();
+        cpu.sp = 0x8000;
+        cpu.set_io_device(Box::new(BusDevice(VecDeque::from([0x50]))));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.set_int_line(true);
+        let before = cpu.get_t_states();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0050);
+        assert_eq!(cpu.get_t_states() - before, 20);
+        assert!(!cpu.get_iff1());
+    }
+}
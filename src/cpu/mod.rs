@@ -1,14 +1,30 @@
 //! CPU module handles Z80 CPU emulation including registers, flags, and instruction execution.
 
+mod cache;
 mod decoder;
+mod ez80;
 mod instruction;
+mod interrupt;
+mod model;
+mod ops;
 mod tables;
+mod z180;
 
 use crate::event::{Event, EventQueue};
+use crate::io::{IoDevice, OpenBus};
 use crate::timing::TimingConverter;
 use crate::{memory::Memory, Result};
+use cache::BlockCache;
 use decoder::Decoder;
-use instruction::{create_nop, Instruction};
+
+pub use decoder::{DecodedInstruction, Prefix};
+pub use ez80::Ez80;
+pub use instruction::{ExecuteFn, Instruction, InstructionType};
+pub use model::{CpuModel, ScfCcfVariant};
+pub use z180::{Asci, Prt, Z180};
+
+/// T-states of each internal NOP cycle while the CPU is halted
+const HALT_NOP_T_STATES: u32 = 4;
 
 /// Represents the Z80 CPU state
 pub struct Cpu {
@@ -38,16 +54,69 @@ pub struct Cpu {
     // Special purpose registers
     i: u8, // Interrupt vector
     r: u8, // Memory refresh
+    // Internal MEMPTR (WZ) register, visible only through the X and Y flags
+    wz: u16,
+    // Interrupt enable flip-flops
+    iff1: bool,
+    iff2: bool,
+    // Interrupt mode (0, 1 or 2)
+    im: u8,
+    // Level of the INT input, as driven by devices
+    int_line: bool,
+    // Maskable interrupt requested by an event, held until acknowledged
+    int_pending: bool,
+    // NMI edge seen but not yet acknowledged
+    nmi_pending: bool,
+    // Set by EI to hold off maskable interrupts for one instruction
+    ei_delay: bool,
+    // HALT output: executing NOPs until an interrupt arrives
+    halted: bool,
     // Flags register
     flags: Flags,
     flags_prime: Flags,
+    // Internal Q register: F if the last instruction changed the flags, else 0
+    q: u8,
+    // Set by LD A,I and LD A,R, whose copy of IFF2 in P/V an interrupt
+    // accepted straight afterwards spoils on NMOS parts
+    iff2_read: bool,
+    // The emulated part, which selects its undocumented behaviour
+    model: CpuModel,
+    // On-chip peripherals of a Z180
+    z180: Option<Z180>,
+    // Mode and upper register bytes of an eZ80
+    ez80: Option<Ez80>,
     // Memory reference
     memory: Memory,
     // Add T-state counter
     t_states: u32,
     event_queue: EventQueue,
     decoder: Decoder,
+    // Decoded instructions replayed in place of decoding, when enabled
+    block_cache: Option<BlockCache>,
     timing: TimingConverter,
+    // Port-mapped devices
+    io: Box<dyn IoDevice>,
+    // Opcode of the instruction being executed
+    opcode: u8,
+    // Register standing in for HL in the instruction being executed
+    index_mode: IndexMode,
+    // Displacement of an (IX+d)/(IY+d) operand, once fetched
+    displacement: Option<i8>,
+    // T-states charged by the executing instruction's own memory reads
+    operand_t_states: u32,
+    // Page of the last instruction byte fetched, for R800 page breaks
+    fetch_page: u8,
+    // Set while an IM 0 instruction fetches its operands from the data bus
+    fetch_from_bus: bool,
+}
+
+/// Selects the register used where an instruction encodes HL, as chosen by
+/// a DD (IX) or FD (IY) prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndexMode {
+    Hl,
+    Ix,
+    Iy,
 }
 
 #[derive(Debug, Default, Clone, Copy)]
@@ -55,7 +124,9 @@ pub struct Flags {
     // Primary flags
     pub sign: bool,         // S: Bit 7 - Set if result is negative
     pub zero: bool,         // Z: Bit 6 - Set if result is zero
+    pub y: bool,            // Y: Bit 5 - Undocumented, usually bit 5 of the result
     pub half_carry: bool,   // H: Bit 4 - Set if carry from bit 3 to 4
+    pub x: bool,            // X: Bit 3 - Undocumented, usually bit 3 of the result
     pub parity: bool,       // P/V: Bit 2 - Parity/Overflow flag
     pub add_subtract: bool, // N: Bit 1 - Set if last op was subtraction
     pub carry: bool,        // C: Bit 0 - Set if result overflowed
@@ -75,9 +146,15 @@ impl Flags {
         if self.zero {
             result |= 0b0100_0000;
         }
+        if self.y {
+            result |= 0b0010_0000;
+        }
         if self.half_carry {
             result |= 0b0001_0000;
         }
+        if self.x {
+            result |= 0b0000_1000;
+        }
         if self.parity {
             result |= 0b0000_0100;
         }
@@ -94,16 +171,45 @@ impl Flags {
     pub fn from_byte(&mut self, byte: u8) {
         self.sign = (byte & 0b1000_0000) != 0;
         self.zero = (byte & 0b0100_0000) != 0;
+        self.y = (byte & 0b0010_0000) != 0;
         self.half_carry = (byte & 0b0001_0000) != 0;
+        self.x = (byte & 0b0000_1000) != 0;
         self.parity = (byte & 0b0000_0100) != 0;
         self.add_subtract = (byte & 0b0000_0010) != 0;
         self.carry = (byte & 0b0000_0001) != 0;
     }
+
+    // Convert flags to the LR35902 layout, Z N H C in the upper nibble
+    pub fn to_lr35902_byte(&self) -> u8 {
+        (u8::from(self.zero) << 7)
+            | (u8::from(self.add_subtract) << 6)
+            | (u8::from(self.half_carry) << 5)
+            | (u8::from(self.carry) << 4)
+    }
+
+    // Set flags from a byte in the LR35902 layout, clearing the rest
+    pub fn from_lr35902_byte(&mut self, byte: u8) {
+        *self = Self {
+            zero: (byte & 0b1000_0000) != 0,
+            add_subtract: (byte & 0b0100_0000) != 0,
+            half_carry: (byte & 0b0010_0000) != 0,
+            carry: (byte & 0b0001_0000) != 0,
+            ..Self::default()
+        };
+    }
 }
 
 impl Cpu {
-    /// Creates a new CPU instance with initialized memory
-    pub fn new(memory: Memory) -> Self {
+    /// Creates a new CPU instance of the given model with initialized memory
+    pub fn new(mut memory: Memory, model: CpuModel) -> Self {
+        let z180 = (model == CpuModel::Z180).then(|| {
+            memory.enable_mmu();
+            Z180::default()
+        });
+        let ez80 = model.is_ez80().then(|| {
+            memory.enable_long_addressing();
+            Ez80::default()
+        });
         Self {
             pc: 0,
             sp: 0xFFFF,
@@ -125,13 +231,35 @@ impl Cpu {
             iy: 0,
             i: 0,
             r: 0,
+            wz: 0,
+            iff1: false,
+            iff2: false,
+            im: 0,
+            int_line: false,
+            int_pending: false,
+            nmi_pending: false,
+            ei_delay: false,
+            halted: false,
             flags: Flags::default(),
             flags_prime: Flags::default(),
+            q: 0,
+            iff2_read: false,
+            model,
+            z180,
+            ez80,
             memory,
             t_states: 0,
             event_queue: EventQueue::new(),
-            decoder: Decoder::new(),
+            decoder: Decoder::for_model(model),
+            block_cache: None,
             timing: TimingConverter::default(),
+            io: Box::new(OpenBus),
+            opcode: 0,
+            index_mode: IndexMode::Hl,
+            displacement: None,
+            operand_t_states: 0,
+            fetch_page: 0,
+            fetch_from_bus: false,
         }
     }
 
@@ -142,31 +270,84 @@ impl Cpu {
         // Process any pending events before fetch
         self.process_events()?;
 
-        // Fetch and decode instruction
-        let opcode = self.memory.read_byte(self.pc)?;
-        let instruction = self.decoder.decode(opcode)?;
+        // An accepted interrupt takes the place of the next instruction
+        if let Some(t_states) = self.accept_interrupt()? {
+            self.t_states += t_states;
+            self.process_events()?;
+            return Ok(self.finish_step(start_t_states));
+        }
+
+        // While halted the CPU keeps fetching without executing, refreshing
+        // memory with 4 T-state NOP cycles
+        if self.halted {
+            self.increment_r();
+            self.t_states += HALT_NOP_T_STATES;
+            self.process_events()?;
+            return Ok(self.finish_step(start_t_states));
+        }
+
+        // Fetch and decode the whole instruction up to its opcode, leaving
+        // PC on the first operand
+        self.select_ez80_code_bank();
+        let cached = match &mut self.block_cache {
+            Some(cache) => cache.decode(&self.decoder, &mut self.memory, self.pc)?,
+            None => None,
+        };
+        let decoded = match cached {
+            Some(decoded) => decoded,
+            None => self.decode_code(self.pc)?,
+        };
+        self.begin_ez80_instruction(decoded.suffix);
+        self.advance_pc(u16::from(decoded.opcode_length));
+        for _ in 0..decoded.m1_cycles {
+            self.increment_r();
+        }
+        self.displacement = decoded.displacement;
+        self.index_mode = match decoded.prefix {
+            Prefix::Dd | Prefix::DdCb => IndexMode::Ix,
+            Prefix::Fd | Prefix::FdCb => IndexMode::Iy,
+            _ => IndexMode::Hl,
+        };
+        let instruction = decoded.instruction;
+        if self.model.is_r800() {
+            self.charge_page_break(decoded.address, decoded.length);
+        }
 
         // Process events after fetch/decode
         self.process_events()?;
 
-        // Execute instruction
+        // Execute instruction. Handlers fetch their own operands and set PC
+        // directly when they branch.
+        self.opcode = decoded.opcode;
+        self.operand_t_states = 0;
+        self.iff2_read = false;
         (instruction.execute)(self)?;
 
-        // Update PC after execution
-        self.pc = self.pc.wrapping_add(instruction.length as u16);
+        // Q latches F after an instruction that changed the flags
+        self.q = if instruction.affects_flags {
+            self.flags.to_byte()
+        } else {
+            0
+        };
 
-        // Add instruction T-states and process final events
-        self.t_states += instruction.t_states;
+        // Add the instruction T-states not already charged by operand reads
+        // and process final events
+        self.t_states += decoded.t_states - self.operand_t_states;
         self.process_events()?;
+        Ok(self.finish_step(start_t_states))
+    }
 
-        // Calculate frame timing
+    /// Advances on-chip timers and frame timing by the T-states of the
+    /// step, returning true if a frame boundary was reached
+    fn finish_step(&mut self, start_t_states: u32) -> bool {
         let step_t_states = self.t_states - start_t_states;
-        Ok(self.timing.update_frame_t_states(step_t_states))
+        self.tick_z180(step_t_states);
+        self.timing.update_frame_t_states(step_t_states)
     }
 
     /// Process any events scheduled for the current T-state
     fn process_events(&mut self) -> Result<()> {
-        while let Some((event, t_state)) = self.event_queue.peek() {
+        while let Some((_, t_state)) = self.event_queue.peek() {
             if *t_state > self.t_states {
                 break;
             }
@@ -183,6 +364,7 @@ impl Cpu {
             // Handle different event types
             // This will be expanded as we add more event types
             Event::Interrupt => self.handle_interrupt()?,
+            Event::Nmi => self.request_nmi(),
             Event::Timer => self.handle_timer()?,
             // ... other event types
         }
@@ -194,12 +376,140 @@ impl Cpu {
         self.pc
     }
 
+    /// Returns the current stack pointer value
+    pub fn get_sp(&self) -> u16 {
+        self.sp
+    }
+
+    /// Returns the accumulator
+    pub fn get_a(&self) -> u8 {
+        self.a
+    }
+
+    /// Returns the flags register
+    pub fn get_flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// Returns the IX index register
+    pub fn get_ix(&self) -> u16 {
+        self.ix
+    }
+
+    /// Returns the IY index register
+    pub fn get_iy(&self) -> u16 {
+        self.iy
+    }
+
+    /// Returns the interrupt vector register
+    pub fn get_i(&self) -> u8 {
+        self.i
+    }
+
+    /// Returns the memory refresh register
+    pub fn get_r(&self) -> u8 {
+        self.r
+    }
+
+    /// Returns the internal MEMPTR (WZ) register
+    pub fn get_memptr(&self) -> u16 {
+        self.wz
+    }
+
+    /// Decodes the instruction at `address` without executing it, for
+    /// debuggers and disassembly
+    pub fn decode_at(&self, address: u16) -> Result<DecodedInstruction> {
+        self.decode_code(address)
+    }
+
+    /// Decodes the instruction at `address` in the current code bank. The
+    /// eZ80 reads it by full code address, so in ADL mode an instruction can
+    /// run on past the end of a 64 KB bank.
+    fn decode_code(&self, address: u16) -> Result<DecodedInstruction> {
+        match &self.ez80 {
+            Some(ez80) => self.decoder.decode_from(address, |offset| {
+                Ok(self
+                    .memory
+                    .read_physical(ez80.code_address_at(address, offset)))
+            }),
+            None => self.decoder.decode(&self.memory, address),
+        }
+    }
+
+    /// Enables or disables the decoded block cache. While enabled, `step`
+    /// replays instructions decoded on earlier visits, with the same
+    /// results and timing as decoding them afresh. Disabling it returns to
+    /// decoding every instruction, as a debugger stepping through
+    /// self-modifying code may prefer.
+    pub fn set_block_cache(&mut self, enabled: bool) {
+        if enabled != self.block_cache.is_some() {
+            self.block_cache = enabled.then(BlockCache::default);
+            self.memory.track_code(enabled);
+        }
+    }
+
+    /// Returns true if the decoded block cache is enabled
+    pub fn block_cache_enabled(&self) -> bool {
+        self.block_cache.is_some()
+    }
+
+    /// Returns the state of the HALT output pin, which is active while the
+    /// CPU waits in HALT for an interrupt
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns the interrupt enable flip-flop IFF1
+    pub fn get_iff1(&self) -> bool {
+        self.iff1
+    }
+
+    /// Returns the interrupt enable flip-flop IFF2
+    pub fn get_iff2(&self) -> bool {
+        self.iff2
+    }
+
+    /// Returns the interrupt mode selected by the last IM instruction
+    pub fn get_im(&self) -> u8 {
+        self.im
+    }
+
+    /// Attaches the device that answers IN and OUT instructions
+    pub fn set_io_device(&mut self, device: Box<dyn IoDevice>) {
+        self.io = device;
+    }
+
+    /// Returns the emulated Z80 model
+    pub fn get_model(&self) -> CpuModel {
+        self.model
+    }
+
     /// Loads a program into memory at the specified address
     pub fn load_program(&mut self, address: u16, program: &[u8]) -> Result<()> {
         self.memory.load(address, program)
     }
 
     // Helper methods for 16-bit register pairs
+    /// Returns AF. On the 8080, F bits 5 and 3 always read as 0 and bit 1
+    /// as 1. The LR35902 keeps its four flags in the upper nibble.
+    pub fn get_af(&self) -> u16 {
+        let f = match self.model {
+            CpuModel::I8080 => (self.flags.to_byte() & 0xD5) | 0x02,
+            CpuModel::Lr35902 => self.flags.to_lr35902_byte(),
+            _ => self.flags.to_byte(),
+        };
+        ((self.a as u16) << 8) | (f as u16)
+    }
+
+    pub fn set_af(&mut self, value: u16) {
+        self.a = (value >> 8) as u8;
+        if self.model.is_lr35902() {
+            self.flags.from_lr35902_byte(value as u8);
+        } else {
+            self.flags.from_byte(value as u8);
+        }
+    }
+
     pub fn get_bc(&self) -> u16 {
         ((self.b as u16) << 8) | (self.c as u16)
     }
@@ -239,9 +549,10 @@ impl Cpu {
         std::mem::swap(&mut self.flags, &mut self.flags_prime);
     }
 
-    /// Increment R register (called during instruction fetch)
+    /// Increment R register (called on every opcode fetch). Only the low
+    /// seven bits count; bit 7 keeps the value last loaded by LD R,A.
     pub fn increment_r(&mut self) {
-        self.r = (self.r & 0x80) | ((self.r + 1) & 0x7f);
+        self.r = (self.r & 0x80) | (self.r.wrapping_add(1) & 0x7f);
     }
 
     /// Returns the current T-state count
@@ -254,11 +565,29 @@ impl Cpu {
         self.t_states = 0;
     }
 
+    /// Latches a maskable interrupt request until the CPU acknowledges it
     fn handle_interrupt(&mut self) -> Result<()> {
-        // TODO: Implement interrupt handling
+        self.int_pending = true;
         Ok(())
     }
 
+    /// Drives the INT input. While asserted, an interrupt is taken at every
+    /// instruction boundary where IFF1 is set.
+    pub fn set_int_line(&mut self, asserted: bool) {
+        self.int_line = asserted;
+    }
+
+    /// Signals a falling edge on the NMI input
+    pub fn request_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Schedules an event to be handled once the T-state counter reaches
+    /// `t_state`
+    pub fn schedule_event(&mut self, event: Event, t_state: u32) {
+        self.event_queue.push(event, t_state);
+    }
+
     fn handle_timer(&mut self) -> Result<()> {
         // TODO: Implement timer event handling
         Ok(())
@@ -277,14 +606,13 @@ impl Cpu {
 
 impl Default for Cpu {
     fn default() -> Self {
-        Self::new(Memory::default())
+        Self::new(Memory::default(), CpuModel::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::EmulatorError;
 
     #[test]
     fn test_cpu_initialization() {
@@ -305,13 +633,28 @@ mod tests {
     }
 
     #[test]
-    fn test_invalid_opcode() {
+    fn test_undefined_ed_opcode() {
+        let mut cpu = Cpu::default();
+        let program = [0xED, 0x00]; // Undefined ED-prefixed opcode
+        cpu.load_program(0, &program).unwrap();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 2);
+        assert_eq!(cpu.get_t_states(), 8);
+    }
+
+    #[test]
+    fn test_simple_program() {
         let mut cpu = Cpu::default();
-        let program = [0xFF]; // Invalid opcode
+        // LD B,5; XOR A; loop: ADD A,B; DJNZ loop; HALT
+        let program = [0x06, 0x05, 0xAF, 0x80, 0x10, 0xFD, 0x76];
         cpu.load_program(0, &program).unwrap();
 
-        let result = cpu.step();
-        assert!(matches!(result, Err(EmulatorError::InvalidOpcode(0xFF))));
+        while cpu.get_pc() != 0x0006 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.a, 15);
+        assert_eq!(cpu.b, 0);
     }
 
     #[test]
@@ -345,11 +688,26 @@ mod tests {
         assert!(!flags.half_carry);
         assert!(!flags.parity);
         assert!(!flags.add_subtract);
+        assert!(!flags.x);
+        assert!(!flags.y);
+    }
+
+    #[test]
+    fn test_flags_undocumented_bits_round_trip() {
+        let mut flags = Flags::new();
+        flags.from_byte(0b0010_1000);
+        assert!(flags.x);
+        assert!(flags.y);
+        assert_eq!(flags.to_byte(), 0b0010_1000);
+
+        let mut cpu = Cpu::default();
+        cpu.set_af(0x12FF);
+        assert_eq!(cpu.get_af(), 0x12FF);
     }
 
     #[test]
     fn test_arithmetic_flags() {
-        let mut cpu = Cpu::new(Memory::default());
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::default());
 
         // Test addition with carry
         cpu.update_arithmetic_flags(0x7F, 0x01, false, true);
@@ -436,6 +794,30 @@ mod tests {
         cpu.r = 0x80;
         cpu.increment_r();
         assert_eq!(cpu.r, 0x81);
+
+        cpu.r = 0xFF;
+        cpu.increment_r();
+        assert_eq!(cpu.r, 0x80);
+    }
+
+    #[test]
+    fn test_r_counts_opcode_fetches() {
+        let mut cpu = Cpu::default();
+        // NOP; LD IX,0; RLC (IX+0); DD DD NOP; LD A,R
+        cpu.load_program(
+            0,
+            &[
+                0x00, 0xDD, 0x21, 0x00, 0x00, 0xDD, 0xCB, 0x00, 0x06, 0xDD, 0xDD, 0x00, 0xED, 0x5F,
+            ],
+        )
+        .unwrap();
+        let expected = [1, 3, 5, 8, 10];
+        for r in expected {
+            cpu.step().unwrap();
+            assert_eq!(cpu.get_r(), r);
+        }
+        // LD A,R sees the increments of its own two fetches
+        assert_eq!(cpu.get_a(), 10);
     }
 
     #[test]
@@ -454,6 +836,44 @@ mod tests {
         assert!(!frame_complete);
     }
 
+    #[test]
+    fn test_decode_at() {
+        let mut cpu = Cpu::default();
+        // NOP; SET 1,(IX+3)
+        cpu.load_program(0, &[0x00, 0xDD, 0xCB, 0x03, 0xCE])
+            .unwrap();
+
+        let decoded = cpu.decode_at(1).unwrap();
+        assert_eq!(decoded.address, 1);
+        assert_eq!(decoded.prefix, Prefix::DdCb);
+        assert_eq!(decoded.displacement, Some(3));
+        assert_eq!(decoded.opcode, 0xCE);
+        assert_eq!(decoded.length, 4);
+        assert_eq!(decoded.t_states, 23);
+
+        // Decoding has no side effects
+        assert_eq!(cpu.get_pc(), 0);
+        assert_eq!(cpu.get_t_states(), 0);
+    }
+
+    #[test]
+    fn test_operand_fetch_and_branches() {
+        let mut cpu = Cpu::default();
+        // LD A,0x12; JP 0x0010; ...; 0x0010: JR +2; ...; 0x0014: CALL 0x0100
+        cpu.load_program(0, &[0x3E, 0x12, 0xC3, 0x10, 0x00])
+            .unwrap();
+        cpu.load_program(0x0010, &[0x18, 0x02]).unwrap();
+        cpu.load_program(0x0014, &[0xCD, 0x00, 0x01]).unwrap();
+
+        for (pc, t_states) in [(0x0002, 7), (0x0010, 17), (0x0014, 29), (0x0100, 46)] {
+            cpu.step().unwrap();
+            assert_eq!(cpu.get_pc(), pc);
+            assert_eq!(cpu.get_t_states(), t_states);
+        }
+        assert_eq!(cpu.get_a(), 0x12);
+        assert_eq!(cpu.read_word(cpu.get_sp()).unwrap(), 0x0017);
+    }
+
     #[test]
     fn test_event_processing() {
         let mut cpu = Cpu::default();
@@ -510,4 +930,108 @@ mod tests {
             _ => panic!("Events not properly ordered by T-state"),
         }
     }
+
+    #[test]
+    fn test_8080_conditional_call_timing() {
+        // CNZ not taken, then CZ taken
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::I8080);
+        cpu.load_program(0, &[0xAF, 0xC4, 0x00, 0x10, 0xCC, 0x00, 0x10])
+            .unwrap();
+        cpu.sp = 0x8000;
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_pc(), 0x1000);
+        assert_eq!(cpu.get_t_states(), 4 + 11 + 17);
+    }
+
+    /// Runs a CP/M exerciser such as 8080EXM or CPUTEST, named by the
+    /// I8080_EXERCISER environment variable, in 8080 mode. BDOS console
+    /// output (functions 2 and 9) is collected and must not report errors.
+    /// The binaries are not distributed with the crate, so this only runs
+    /// under `--ignored`, and fails there if none is named.
+    #[test]
+    #[ignore = "needs an 8080 exerciser binary named by I8080_EXERCISER"]
+    fn test_8080_exerciser() {
+        let path = std::env::var("I8080_EXERCISER")
+            .expect("I8080_EXERCISER must name 8080EXM.COM or CPUTEST.COM");
+        let program = std::fs::read(&path).unwrap_or_else(|error| panic!("{path}: {error}"));
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::I8080);
+        cpu.load_program(0x0100, &program).unwrap();
+        // Warm boot halts, and the BDOS entry returns straight away
+        cpu.load_program(0x0000, &[0x76, 0x00, 0x00, 0x00, 0x00, 0xC9])
+            .unwrap();
+        cpu.pc = 0x0100;
+        cpu.sp = 0xF000;
+
+        // 8080EXM, the longest, runs about 24 billion cycles, so the limit
+        // only stops a run that has gone into a loop
+        let mut output = String::new();
+        for _ in 0..10_000_000_000u64 {
+            if cpu.is_halted() {
+                break;
+            }
+            if cpu.get_pc() == 0x0005 {
+                match cpu.c {
+                    2 => output.push(cpu.e as char),
+                    9 => {
+                        let mut address = cpu.get_de();
+                        loop {
+                            let byte = cpu.memory.read_byte(address).unwrap();
+                            if byte == b'$' {
+                                break;
+                            }
+                            output.push(byte as char);
+                            address = address.wrapping_add(1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            cpu.step().unwrap();
+        }
+        assert!(
+            cpu.is_halted(),
+            "no warm boot within the step limit: {output}"
+        );
+        assert!(!output.is_empty(), "the exerciser printed nothing");
+        assert!(!output.contains("ERROR"), "{output}");
+    }
+
+    /// Runs one of Blargg's cpu_instrs test ROMs, named by the
+    /// LR35902_TEST_ROM environment variable, in LR35902 mode. The result is
+    /// read from the serial port (SB at 0xFF01, SC at 0xFF02). The ROMs are
+    /// not distributed with the crate, so this only runs under `--ignored`,
+    /// and fails there if none is named.
+    #[test]
+    #[ignore = "needs a cpu_instrs ROM named by LR35902_TEST_ROM"]
+    fn test_lr35902_cpu_instrs() {
+        let path =
+            std::env::var("LR35902_TEST_ROM").expect("LR35902_TEST_ROM must name a cpu_instrs ROM");
+        let rom = std::fs::read(&path).unwrap_or_else(|error| panic!("{path}: {error}"));
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::Lr35902);
+        cpu.load_program(0x0000, &rom[..rom.len().min(0x8000)])
+            .unwrap();
+        cpu.pc = 0x0100;
+        cpu.sp = 0xFFFE;
+        // LY reads as the start of vertical blank, where the ROMs wait
+        cpu.memory.write_byte(0xFF44, 0x90).unwrap();
+
+        let mut output = String::new();
+        for _ in 0..100_000_000u32 {
+            cpu.step().unwrap();
+            if cpu.memory.read_byte(0xFF02).unwrap() == 0x81 {
+                output.push(cpu.memory.read_byte(0xFF01).unwrap() as char);
+                cpu.memory.write_byte(0xFF02, 0x00).unwrap();
+            }
+            if output.contains("Passed") || output.contains("Failed") {
+                break;
+            }
+        }
+        assert!(!output.contains("Failed"), "{output}");
+        assert!(
+            output.contains("Passed"),
+            "no result within the step limit: {output}"
+        );
+    }
 }
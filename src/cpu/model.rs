@@ -0,0 +1,150 @@
+//! Z80 silicon variants and the undocumented behaviour that differs between
+//! them.
+
+/// The Z80 part being emulated. Each model selects the set of undocumented
+/// behaviours of that chip.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CpuModel {
+    /// Original Zilog NMOS Z80
+    #[default]
+    ZilogNmos,
+    /// Zilog CMOS Z84C00
+    ZilogCmos,
+    /// NEC NMOS second source (μPD780)
+    NecNmos,
+    /// SGS-Thomson CMOS second source
+    StCmos,
+    /// Zilog Z80180 / Hitachi HD64180, a CMOS core with an MMU, on-chip
+    /// peripherals and extra ED opcodes
+    Z180,
+    /// Z80N core of the ZX Spectrum Next, with extra ED opcodes
+    Z80n,
+    /// Intel 8080, with no prefixed opcodes and the 8080's flags and timing
+    I8080,
+    /// Sharp LR35902 of the Game Boy, with its own opcode map and a Z/N/H/C
+    /// flag register
+    Lr35902,
+    /// ASCII R800 of the MSX turbo R, with MULUB/MULUW and its own cycle
+    /// timing in place of Z80 T-states
+    R800,
+    /// Zilog eZ80, with an ADL mode of 24-bit registers and addresses
+    /// alongside Z80 mode, and extra ED opcodes
+    Ez80,
+}
+
+/// Selects how SCF and CCF set the undocumented X and Y flags, which differs
+/// between manufacturers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScfCcfVariant {
+    /// Zilog and compatible parts: X and Y from `(Q ^ F) | A`
+    #[default]
+    Zilog,
+    /// NEC NMOS parts: X and Y from A alone
+    Nec,
+    /// ST CMOS parts: Y from `(Q ^ F) | A`, X from A alone
+    St,
+}
+
+impl CpuModel {
+    /// Returns true for NMOS parts
+    pub fn is_nmos(self) -> bool {
+        matches!(self, Self::ZilogNmos | Self::NecNmos | Self::I8080)
+    }
+
+    /// Returns true for the Intel 8080
+    pub fn is_8080(self) -> bool {
+        self == Self::I8080
+    }
+
+    /// Returns true for the Game Boy's LR35902
+    pub fn is_lr35902(self) -> bool {
+        self == Self::Lr35902
+    }
+
+    /// Returns true for the eZ80
+    pub fn is_ez80(self) -> bool {
+        self == Self::Ez80
+    }
+
+    /// Returns true for the MSX turbo R's R800
+    pub fn is_r800(self) -> bool {
+        self == Self::R800
+    }
+
+    /// Returns the opcode bytes that act as prefixes. The 8080 has none, as
+    /// CB, DD, ED and FD are aliases of JMP and CALL there, and the LR35902
+    /// only has CB.
+    pub fn prefixes(self) -> &'static [u8] {
+        match self {
+            Self::I8080 => &[],
+            Self::Lr35902 => &[0xCB],
+            _ => &[0xCB, 0xDD, 0xED, 0xFD],
+        }
+    }
+
+    /// Returns the opcode bytes that act as mode suffixes ahead of an
+    /// instruction: .SIS, .LIS, .SIL and .LIL on the eZ80, where they take
+    /// the place of LD B,B, LD C,C, LD D,D and LD E,E
+    pub fn suffixes(self) -> &'static [u8] {
+        match self {
+            Self::Ez80 => &[0x40, 0x49, 0x52, 0x5B],
+            _ => &[],
+        }
+    }
+
+    /// Returns how SCF and CCF derive X and Y on this part
+    pub fn scf_ccf_variant(self) -> ScfCcfVariant {
+        match self {
+            Self::ZilogNmos
+            | Self::ZilogCmos
+            | Self::Z180
+            | Self::Z80n
+            | Self::I8080
+            | Self::Lr35902
+            | Self::R800
+            | Self::Ez80 => ScfCcfVariant::Zilog,
+            Self::NecNmos => ScfCcfVariant::Nec,
+            Self::StCmos => ScfCcfVariant::St,
+        }
+    }
+
+    /// Returns the byte written by the undocumented OUT (C),0: zero on NMOS
+    /// parts and the Next, and 0xFF on CMOS parts
+    pub fn out_c_zero_value(self) -> u8 {
+        if self.is_nmos() || self == Self::Z80n {
+            0x00
+        } else {
+            0xFF
+        }
+    }
+
+    /// Returns true if an interrupt accepted straight after LD A,I or LD A,R
+    /// clears P/V instead of leaving the copy of IFF2, as on NMOS parts
+    pub fn has_ld_a_ir_bug(self) -> bool {
+        self.is_nmos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_quirks() {
+        assert_eq!(CpuModel::default(), CpuModel::ZilogNmos);
+        assert_eq!(CpuModel::ZilogNmos.out_c_zero_value(), 0x00);
+        assert_eq!(CpuModel::ZilogCmos.out_c_zero_value(), 0xFF);
+        assert_eq!(CpuModel::NecNmos.scf_ccf_variant(), ScfCcfVariant::Nec);
+        assert_eq!(CpuModel::StCmos.scf_ccf_variant(), ScfCcfVariant::St);
+        assert!(CpuModel::NecNmos.has_ld_a_ir_bug());
+        assert!(!CpuModel::ZilogCmos.has_ld_a_ir_bug());
+        assert!(!CpuModel::Z180.is_nmos());
+        assert_eq!(CpuModel::Z80n.out_c_zero_value(), 0x00);
+        assert_eq!(CpuModel::Z80n.prefixes().len(), 4);
+        assert!(CpuModel::I8080.prefixes().is_empty());
+        assert_eq!(CpuModel::Lr35902.prefixes(), &[0xCB]);
+        assert_eq!(CpuModel::R800.out_c_zero_value(), 0xFF);
+        assert_eq!(CpuModel::Ez80.suffixes().len(), 4);
+        assert!(CpuModel::ZilogNmos.suffixes().is_empty());
+    }
+}
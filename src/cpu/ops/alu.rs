@@ -0,0 +1,716 @@
+//! Arithmetic and logic instructions: 8-bit ALU, INC/DEC, 16-bit arithmetic,
+//! accumulator rotates and the accumulator/flag operations.
+
+use crate::cpu::instruction::FlagUtils;
+use crate::cpu::{Cpu, ScfCcfVariant};
+use crate::Result;
+
+impl Cpu {
+    /// Applies ALU operation `op` (ADD, ADC, SUB, SBC, AND, XOR, OR, CP) to A
+    pub(crate) fn alu(&mut self, op: u8, value: u8) {
+        let a = self.a;
+        match op & 0x07 {
+            0 => {
+                self.update_arithmetic_flags(a, value, false, true);
+                self.a = a.wrapping_add(value);
+            }
+            1 => {
+                let carry = self.flags.carry;
+                self.update_arithmetic_flags(a, value, carry, true);
+                self.a = a.wrapping_add(value).wrapping_add(carry as u8);
+            }
+            2 => {
+                self.update_arithmetic_flags(a, value, false, false);
+                self.a = a.wrapping_sub(value);
+            }
+            3 => {
+                let carry = self.flags.carry;
+                self.update_arithmetic_flags(a, value, carry, false);
+                self.a = a.wrapping_sub(value).wrapping_sub(carry as u8);
+            }
+            4 => self.update_logic_flags(a & value, true),
+            5 => self.update_logic_flags(a ^ value, false),
+            6 => self.update_logic_flags(a | value, false),
+            _ => {
+                // CP takes X and Y from the operand rather than the result
+                self.update_arithmetic_flags(a, value, false, false);
+                self.update_xy_flags(value);
+            }
+        }
+
+        if self.model.is_8080() {
+            match op & 0x07 {
+                0 | 1 => self.update_parity_flag(self.a),
+                2 | 3 => self.update_8080_subtract_flags(self.a),
+                4 => self.flags.half_carry = (a | value) & 0x08 != 0,
+                5 | 6 => {}
+                _ => self.update_8080_subtract_flags(a.wrapping_sub(value)),
+            }
+        }
+    }
+
+    /// Adjusts the flags of a subtraction for the 8080, which reports parity
+    /// in P/V and, subtracting by adding the complement, sets AC on no
+    /// half borrow
+    fn update_8080_subtract_flags(&mut self, result: u8) {
+        self.update_parity_flag(result);
+        self.flags.half_carry = !self.flags.half_carry;
+    }
+
+    /// Stores a logic result in A and sets flags for AND, XOR and OR
+    fn update_logic_flags(&mut self, result: u8, half_carry: bool) {
+        self.a = result;
+        self.update_szp_flags(result);
+        self.flags.half_carry = half_carry;
+        self.flags.add_subtract = false;
+        self.flags.carry = false;
+    }
+
+    /// Sets H, clears N and copies X and Y from A, as the single-byte
+    /// accumulator instructions do
+    fn update_accumulator_flags(&mut self, half_carry: bool) {
+        // The 8080 rotates and CMA leave AC alone
+        if self.model.is_8080() {
+            return;
+        }
+        self.flags.half_carry = half_carry;
+        self.flags.add_subtract = false;
+        self.update_xy_flags(self.a);
+    }
+
+    /// Sets the flags of RLCA, RRCA, RLA and RRA, which on the LR35902 also
+    /// clear Z
+    fn update_accumulator_rotate_flags(&mut self) {
+        self.update_accumulator_flags(false);
+        if self.model.is_lr35902() {
+            self.flags.zero = false;
+        }
+    }
+
+    /// Sets H, clears N and derives X and Y for SCF and CCF, which depend on
+    /// whether the previous instruction changed the flags (Q)
+    fn update_carry_flag_op_flags(&mut self, half_carry: bool) {
+        // STC and CMC on the 8080 only change the carry
+        if self.model.is_8080() {
+            return;
+        }
+        let q_flags = self.q ^ self.flags.to_byte();
+        let (x_source, y_source) = match self.model.scf_ccf_variant() {
+            ScfCcfVariant::Zilog => (q_flags | self.a, q_flags | self.a),
+            ScfCcfVariant::Nec => (self.a, self.a),
+            ScfCcfVariant::St => (self.a, q_flags | self.a),
+        };
+        // The LR35902 always clears H
+        self.flags.half_carry = half_carry && !self.model.is_lr35902();
+        self.flags.add_subtract = false;
+        self.flags.x = x_source & 0x08 != 0;
+        self.flags.y = y_source & 0x20 != 0;
+    }
+
+    /// Sets flags from A after RRD or RLD, leaving carry alone
+    fn update_decimal_rotate_flags(&mut self) {
+        self.update_szp_flags(self.a);
+        self.flags.half_carry = false;
+        self.flags.add_subtract = false;
+    }
+
+    /// Increments an 8-bit value, updating all flags except carry
+    pub(crate) fn inc8(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.update_sz_flags(result);
+        self.flags.half_carry = (value & 0x0F) == 0x0F;
+        self.flags.parity = value == 0x7F;
+        self.flags.add_subtract = false;
+        if self.model.is_8080() {
+            self.update_parity_flag(result);
+        }
+        result
+    }
+
+    /// Decrements an 8-bit value, updating all flags except carry
+    pub(crate) fn dec8(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.update_sz_flags(result);
+        self.flags.half_carry = (value & 0x0F) == 0x00;
+        self.flags.parity = value == 0x80;
+        self.flags.add_subtract = true;
+        if self.model.is_8080() {
+            self.update_8080_subtract_flags(result);
+        }
+        result
+    }
+
+    /// Adds two 16-bit values as ADD HL,rr does (only H, N, C and the
+    /// undocumented X and Y, taken from the high byte, are affected)
+    pub(crate) fn add16(&mut self, a: u16, b: u16) -> u16 {
+        let result = a as u32 + b as u32;
+        // DAD on the 8080 only changes the carry
+        if !self.model.is_8080() {
+            self.flags.half_carry = ((a & 0x0FFF) + (b & 0x0FFF)) > 0x0FFF;
+        }
+        self.flags.add_subtract = false;
+        self.flags.carry = result > 0xFFFF;
+        self.update_xy_flags((result >> 8) as u8);
+        result as u16
+    }
+
+    /// Adds or subtracts two 16-bit values with carry as ADC HL,rr and
+    /// SBC HL,rr do, setting every flag from the 16-bit result
+    pub(crate) fn adc_sbc16(&mut self, a: u16, b: u16, subtract: bool) -> u16 {
+        let carry = self.flags.carry as u32;
+        let (result, half_carry, carry_out) = if subtract {
+            let result = (a as u32).wrapping_sub(b as u32).wrapping_sub(carry);
+            let half = (a & 0x0FFF) < (b & 0x0FFF) + carry as u16;
+            (result as u16, half, result > 0xFFFF)
+        } else {
+            let result = a as u32 + b as u32 + carry;
+            let half = (a & 0x0FFF) + (b & 0x0FFF) + carry as u16 > 0x0FFF;
+            (result as u16, half, result > 0xFFFF)
+        };
+
+        // Overflow when the operands' signs (after negating b for subtraction)
+        // agree and the result's sign differs
+        let b_sign = if subtract { !b } else { b };
+        let overflow = (a ^ b_sign) & 0x8000 == 0 && (a ^ result) & 0x8000 != 0;
+
+        self.flags.sign = result & 0x8000 != 0;
+        self.flags.zero = result == 0;
+        self.flags.half_carry = half_carry;
+        self.flags.parity = overflow;
+        self.flags.add_subtract = subtract;
+        self.flags.carry = carry_out;
+        self.update_xy_flags((result >> 8) as u8);
+        result
+    }
+}
+
+/// ADD/ADC/SUB/SBC/AND/XOR/OR/CP A, r
+pub fn alu_a_r(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.reg8(cpu.opcode)?;
+    cpu.alu(cpu.opcode >> 3, value);
+    Ok(())
+}
+
+/// ADD/ADC/SUB/SBC/AND/XOR/OR/CP A, n
+pub fn alu_a_n(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.fetch_byte()?;
+    cpu.alu(cpu.opcode >> 3, value);
+    Ok(())
+}
+
+/// INC r
+pub fn inc_r(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 3;
+    let value = cpu.reg8(index)?;
+    let result = cpu.inc8(value);
+    cpu.set_reg8(index, result)
+}
+
+/// DEC r
+pub fn dec_r(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 3;
+    let value = cpu.reg8(index)?;
+    let result = cpu.dec8(value);
+    cpu.set_reg8(index, result)
+}
+
+/// INC rr
+pub fn inc_rr(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 4;
+    cpu.set_reg16(index, cpu.reg16(index).wrapping_add(1));
+    Ok(())
+}
+
+/// DEC rr
+pub fn dec_rr(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 4;
+    cpu.set_reg16(index, cpu.reg16(index).wrapping_sub(1));
+    Ok(())
+}
+
+/// ADD HL, rr (also ADD IX, rr and ADD IY, rr)
+pub fn add_hl_rr(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.reg16(cpu.opcode >> 4);
+    cpu.wz = cpu.index_register().wrapping_add(1);
+    let result = cpu.add16(cpu.index_register(), value);
+    cpu.set_index_register(result);
+    Ok(())
+}
+
+/// ADC HL, rr
+pub fn adc_hl_rr(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.reg16(cpu.opcode >> 4);
+    cpu.wz = cpu.get_hl().wrapping_add(1);
+    let result = cpu.adc_sbc16(cpu.get_hl(), value, false);
+    cpu.set_hl(result);
+    Ok(())
+}
+
+/// SBC HL, rr
+pub fn sbc_hl_rr(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.reg16(cpu.opcode >> 4);
+    cpu.wz = cpu.get_hl().wrapping_add(1);
+    let result = cpu.adc_sbc16(cpu.get_hl(), value, true);
+    cpu.set_hl(result);
+    Ok(())
+}
+
+/// NEG
+pub fn neg(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.a;
+    cpu.a = 0;
+    cpu.alu(2, value);
+    Ok(())
+}
+
+/// RRD
+pub fn rrd(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.get_hl();
+    cpu.wz = address.wrapping_add(1);
+    let value = cpu.memory.read_byte(address)?;
+    cpu.memory
+        .write_byte(address, (cpu.a << 4) | (value >> 4))?;
+    cpu.a = (cpu.a & 0xF0) | (value & 0x0F);
+    cpu.update_decimal_rotate_flags();
+    Ok(())
+}
+
+/// RLD
+pub fn rld(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.get_hl();
+    cpu.wz = address.wrapping_add(1);
+    let value = cpu.memory.read_byte(address)?;
+    cpu.memory
+        .write_byte(address, (value << 4) | (cpu.a & 0x0F))?;
+    cpu.a = (cpu.a & 0xF0) | (value >> 4);
+    cpu.update_decimal_rotate_flags();
+    Ok(())
+}
+
+/// RLCA
+pub fn rlca(cpu: &mut Cpu) -> Result<()> {
+    cpu.flags.carry = cpu.a & 0x80 != 0;
+    cpu.a = cpu.a.rotate_left(1);
+    cpu.update_accumulator_rotate_flags();
+    Ok(())
+}
+
+/// RRCA
+pub fn rrca(cpu: &mut Cpu) -> Result<()> {
+    cpu.flags.carry = cpu.a & 0x01 != 0;
+    cpu.a = cpu.a.rotate_right(1);
+    cpu.update_accumulator_rotate_flags();
+    Ok(())
+}
+
+/// RLA
+pub fn rla(cpu: &mut Cpu) -> Result<()> {
+    let carry_in = cpu.flags.carry as u8;
+    cpu.flags.carry = cpu.a & 0x80 != 0;
+    cpu.a = (cpu.a << 1) | carry_in;
+    cpu.update_accumulator_rotate_flags();
+    Ok(())
+}
+
+/// RRA
+pub fn rra(cpu: &mut Cpu) -> Result<()> {
+    let carry_in = (cpu.flags.carry as u8) << 7;
+    cpu.flags.carry = cpu.a & 0x01 != 0;
+    cpu.a = (cpu.a >> 1) | carry_in;
+    cpu.update_accumulator_rotate_flags();
+    Ok(())
+}
+
+/// DAA
+///
+/// Adds or subtracts 0x06/0x60 corrections according to C, H and the digits
+/// of A. After a subtraction H is only kept when the low digit borrows again.
+/// The 8080 has no N flag and always corrects as if after an addition.
+pub fn daa(cpu: &mut Cpu) -> Result<()> {
+    if cpu.model.is_lr35902() {
+        return daa_lr35902(cpu);
+    }
+
+    let a = cpu.a;
+    let mut correction = 0u8;
+    let mut carry = cpu.flags.carry;
+
+    if cpu.flags.half_carry || (a & 0x0F) > 0x09 {
+        correction |= 0x06;
+    }
+    if carry || a > 0x99 {
+        correction |= 0x60;
+        carry = true;
+    }
+
+    let result = if cpu.flags.add_subtract && !cpu.model.is_8080() {
+        cpu.flags.half_carry = cpu.flags.half_carry && (a & 0x0F) < 0x06;
+        a.wrapping_sub(correction)
+    } else {
+        cpu.flags.half_carry = (a & 0x0F) > 0x09;
+        a.wrapping_add(correction)
+    };
+
+    cpu.a = result;
+    cpu.flags.carry = carry;
+    cpu.update_szp_flags(result);
+    Ok(())
+}
+
+/// DAA on the LR35902, which corrects after a subtraction from C and H
+/// alone and always clears H
+fn daa_lr35902(cpu: &mut Cpu) -> Result<()> {
+    let mut a = cpu.a;
+    let mut carry = cpu.flags.carry;
+    if cpu.flags.add_subtract {
+        if carry {
+            a = a.wrapping_sub(0x60);
+        }
+        if cpu.flags.half_carry {
+            a = a.wrapping_sub(0x06);
+        }
+    } else {
+        if carry || a > 0x99 {
+            a = a.wrapping_add(0x60);
+            carry = true;
+        }
+        if cpu.flags.half_carry || (a & 0x0F) > 0x09 {
+            a = a.wrapping_add(0x06);
+        }
+    }
+
+    cpu.a = a;
+    cpu.flags.zero = a == 0;
+    cpu.flags.half_carry = false;
+    cpu.flags.carry = carry;
+    Ok(())
+}
+
+/// CPL
+pub fn cpl(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = !cpu.a;
+    cpu.update_accumulator_flags(true);
+    cpu.flags.add_subtract = true;
+    Ok(())
+}
+
+/// SCF
+pub fn scf(cpu: &mut Cpu) -> Result<()> {
+    cpu.update_carry_flag_op_flags(false);
+    cpu.flags.carry = true;
+    Ok(())
+}
+
+/// CCF
+pub fn ccf(cpu: &mut Cpu) -> Result<()> {
+    let carry = cpu.flags.carry;
+    cpu.update_carry_flag_op_flags(carry);
+    cpu.flags.carry = !carry;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{Cpu, CpuModel};
+    use crate::memory::Memory;
+
+    fn run(program: &[u8], steps: usize) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, program).unwrap();
+        for _ in 0..steps {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_add_and_sub() {
+        let cpu = run(&[0x3E, 0x42, 0x06, 0x10, 0x80], 3); // LD A,0x42; LD B,0x10; ADD A,B
+        assert_eq!(cpu.a, 0x52);
+        assert!(!cpu.flags.carry);
+
+        let cpu = run(&[0x3E, 0x10, 0xD6, 0x20], 2); // LD A,0x10; SUB 0x20
+        assert_eq!(cpu.a, 0xF0);
+        assert!(cpu.flags.carry);
+        assert!(cpu.flags.sign);
+        assert!(cpu.flags.add_subtract);
+        assert!(!cpu.flags.half_carry);
+    }
+
+    #[test]
+    fn test_sub_half_borrow() {
+        let cpu = run(&[0x3E, 0x10, 0xD6, 0x01], 2); // LD A,0x10; SUB 0x01
+        assert_eq!(cpu.a, 0x0F);
+        assert!(cpu.flags.half_carry);
+        assert!(!cpu.flags.carry);
+    }
+
+    #[test]
+    fn test_compare_preserves_a() {
+        let cpu = run(&[0x3E, 0x42, 0xFE, 0x42], 2); // LD A,0x42; CP 0x42
+        assert_eq!(cpu.a, 0x42);
+        assert!(cpu.flags.zero);
+        assert!(cpu.flags.add_subtract);
+    }
+
+    #[test]
+    fn test_logic_flags() {
+        let cpu = run(&[0x3E, 0xF0, 0xE6, 0x0F], 2); // LD A,0xF0; AND 0x0F
+        assert_eq!(cpu.a, 0x00);
+        assert!(cpu.flags.zero);
+        assert!(cpu.flags.half_carry);
+        assert!(cpu.flags.parity);
+
+        let cpu = run(&[0x3E, 0x01, 0xF6, 0x02], 2); // LD A,0x01; OR 0x02
+        assert_eq!(cpu.a, 0x03);
+        assert!(!cpu.flags.half_carry);
+        assert!(cpu.flags.parity);
+    }
+
+    #[test]
+    fn test_inc_dec_flags() {
+        let cpu = run(&[0x3E, 0xFF, 0x3C], 2); // LD A,0xFF; INC A
+        assert_eq!(cpu.a, 0x00);
+        assert!(cpu.flags.zero);
+        assert!(cpu.flags.half_carry);
+        assert!(!cpu.flags.carry);
+
+        let cpu = run(&[0x06, 0x80, 0x05], 2); // LD B,0x80; DEC B
+        assert_eq!(cpu.b, 0x7F);
+        assert!(cpu.flags.parity);
+        assert!(cpu.flags.half_carry);
+    }
+
+    #[test]
+    fn test_add_hl() {
+        let cpu = run(&[0x21, 0x00, 0x8F, 0x01, 0x00, 0x71, 0x09], 3); // ADD HL,BC
+        assert_eq!(cpu.get_hl(), 0x0000);
+        assert!(cpu.flags.carry);
+        assert!(cpu.flags.half_carry);
+    }
+
+    #[test]
+    fn test_accumulator_rotates() {
+        let cpu = run(&[0x3E, 0x81, 0x07], 2); // RLCA
+        assert_eq!(cpu.a, 0x03);
+        assert!(cpu.flags.carry);
+
+        let cpu = run(&[0x3E, 0x01, 0x37, 0x1F], 3); // SCF; RRA
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.flags.carry);
+    }
+
+    #[test]
+    fn test_daa_after_add() {
+        let cpu = run(&[0x3E, 0x19, 0xC6, 0x28, 0x27], 3); // 19 + 28 = 47 (BCD)
+        assert_eq!(cpu.a, 0x47);
+        assert!(!cpu.flags.carry);
+    }
+
+    #[test]
+    fn test_cpl_and_ccf() {
+        let cpu = run(&[0x3E, 0x5A, 0x2F, 0x37, 0x3F], 4); // CPL; SCF; CCF
+        assert_eq!(cpu.a, 0xA5);
+        assert!(!cpu.flags.carry);
+        assert!(cpu.flags.half_carry);
+    }
+
+    #[test]
+    fn test_adc_sbc_hl() {
+        // LD HL,0x7FFF; LD BC,0x0000; SCF; ADC HL,BC
+        let cpu = run(&[0x21, 0xFF, 0x7F, 0x01, 0x00, 0x00, 0x37, 0xED, 0x4A], 4);
+        assert_eq!(cpu.get_hl(), 0x8000);
+        assert!(cpu.flags.parity);
+        assert!(cpu.flags.sign);
+        assert!(cpu.flags.half_carry);
+
+        // LD HL,0x1000; LD DE,0x1000; SBC HL,DE
+        let cpu = run(&[0x21, 0x00, 0x10, 0x11, 0x00, 0x10, 0xED, 0x52], 3);
+        assert_eq!(cpu.get_hl(), 0x0000);
+        assert!(cpu.flags.zero);
+        assert!(cpu.flags.add_subtract);
+        assert!(!cpu.flags.carry);
+        assert_eq!(cpu.get_t_states(), 10 + 10 + 15);
+    }
+
+    #[test]
+    fn test_neg() {
+        let cpu = run(&[0x3E, 0x01, 0xED, 0x44], 2); // LD A,1; NEG
+        assert_eq!(cpu.a, 0xFF);
+        assert!(cpu.flags.carry);
+        assert!(cpu.flags.add_subtract);
+
+        let cpu = run(&[0x3E, 0x80, 0xED, 0x7C], 2); // LD A,0x80; NEG (mirror)
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.flags.parity);
+    }
+
+    #[test]
+    fn test_rrd_rld() {
+        // LD HL,0x8000; LD (HL),0x34; LD A,0x12; RRD
+        let cpu = run(&[0x21, 0x00, 0x80, 0x36, 0x34, 0x3E, 0x12, 0xED, 0x67], 4);
+        assert_eq!(cpu.a, 0x14);
+        assert_eq!(cpu.memory.read_byte(0x8000).unwrap(), 0x23);
+
+        // LD HL,0x8000; LD (HL),0x34; LD A,0x12; RLD
+        let cpu = run(&[0x21, 0x00, 0x80, 0x36, 0x34, 0x3E, 0x12, 0xED, 0x6F], 4);
+        assert_eq!(cpu.a, 0x13);
+        assert_eq!(cpu.memory.read_byte(0x8000).unwrap(), 0x42);
+    }
+
+    #[test]
+    fn test_undocumented_xy_flags() {
+        let cpu = run(&[0x3E, 0x20, 0xC6, 0x08], 2); // LD A,0x20; ADD A,0x08
+        assert!(cpu.flags.x && cpu.flags.y);
+
+        // CP copies X and Y from the operand
+        let cpu = run(&[0x3E, 0x00, 0xFE, 0x28], 2); // LD A,0; CP 0x28
+        assert!(cpu.flags.x && cpu.flags.y);
+        let cpu = run(&[0x3E, 0x28, 0xFE, 0x00], 2); // LD A,0x28; CP 0
+        assert!(!cpu.flags.x && !cpu.flags.y);
+
+        // ADD HL takes them from the high byte of the result
+        let cpu = run(&[0x21, 0x00, 0x28, 0x29], 2); // LD HL,0x2800; ADD HL,HL
+        assert_eq!(cpu.get_hl(), 0x5000);
+        assert!(!cpu.flags.x && !cpu.flags.y);
+        let cpu = run(&[0x21, 0x00, 0x14, 0x29], 2); // LD HL,0x1400; ADD HL,HL
+        assert!(cpu.flags.x && cpu.flags.y);
+
+        let cpu = run(&[0x3E, 0x28, 0x37], 2); // LD A,0x28; SCF
+        assert!(cpu.flags.x && cpu.flags.y);
+    }
+
+    #[test]
+    fn test_scf_ccf_q_register() {
+        // LD BC,0x0028; PUSH BC; POP AF leaves F = 0x28 and Q = F
+        let setup = [0x01, 0x28, 0x00, 0xC5, 0xF1];
+
+        // Straight after POP AF, Q ^ F is zero, so X and Y come from A alone
+        let mut program = setup.to_vec();
+        program.push(0x37); // SCF
+        let cpu = run(&program, 4);
+        assert!(!cpu.flags.x && !cpu.flags.y);
+
+        // After a NOP, Q is zero and the old X and Y survive
+        let mut program = setup.to_vec();
+        program.extend([0x00, 0x3F]); // NOP; CCF
+        let cpu = run(&program, 5);
+        assert!(cpu.flags.x && cpu.flags.y);
+        assert!(cpu.flags.carry);
+
+        // NEC parts take X and Y from A only
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::NecNmos);
+        program = setup.to_vec();
+        program.extend([0x00, 0x37]);
+        cpu.load_program(0, &program).unwrap();
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert!(!cpu.flags.x && !cpu.flags.y);
+    }
+
+    /// Reference DAA from the correction and flag tables in "The Undocumented
+    /// Z80 Documented", returning (A, C, H)
+    fn daa_reference(a: u8, carry: bool, half: bool, subtract: bool) -> (u8, bool, bool) {
+        let (hi, lo) = (a >> 4, a & 0x0F);
+        let diff = match (carry, hi, half, lo) {
+            (false, 0..=9, false, 0..=9) => 0x00,
+            (false, 0..=9, true, 0..=9) => 0x06,
+            (false, 0..=8, _, 0xA..=0xF) => 0x06,
+            (false, 0xA..=0xF, false, 0..=9) => 0x60,
+            (true, _, false, 0..=9) => 0x60,
+            _ => 0x66,
+        };
+        let carry_out = carry || matches!((hi, lo), (9..=0xF, 0xA..=0xF) | (0xA..=0xF, 0..=9));
+        let half_out = if subtract { half && lo <= 5 } else { lo >= 0xA };
+        let result = if subtract {
+            a.wrapping_sub(diff)
+        } else {
+            a.wrapping_add(diff)
+        };
+        (result, carry_out, half_out)
+    }
+
+    #[test]
+    fn test_daa_all_combinations() {
+        let mut cpu = Cpu::default();
+        for a in 0..=0xFFu8 {
+            for bits in 0..8u8 {
+                let (carry, half, subtract) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0);
+                cpu.a = a;
+                cpu.flags.carry = carry;
+                cpu.flags.half_carry = half;
+                cpu.flags.add_subtract = subtract;
+                super::daa(&mut cpu).unwrap();
+
+                let (result, carry_out, half_out) = daa_reference(a, carry, half, subtract);
+                let case = format!("A={a:02X} C={carry} H={half} N={subtract}");
+                assert_eq!(cpu.a, result, "{case}");
+                assert_eq!(cpu.flags.carry, carry_out, "{case}");
+                assert_eq!(cpu.flags.half_carry, half_out, "{case}");
+                assert_eq!(cpu.flags.add_subtract, subtract, "{case}");
+                assert_eq!(cpu.flags.zero, result == 0, "{case}");
+                assert_eq!(cpu.flags.sign, result & 0x80 != 0, "{case}");
+                assert_eq!(cpu.flags.parity, result.count_ones() % 2 == 0, "{case}");
+                assert_eq!(cpu.flags.x, result & 0x08 != 0, "{case}");
+                assert_eq!(cpu.flags.y, result & 0x20 != 0, "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_daa_after_sub() {
+        let cpu = run(&[0x3E, 0x47, 0xD6, 0x28, 0x27], 3); // 47 - 28 = 19 (BCD)
+        assert_eq!(cpu.a, 0x19);
+        assert!(!cpu.flags.carry);
+
+        let cpu = run(&[0x3E, 0x10, 0xD6, 0x20, 0x27], 3); // 10 - 20 = 90, borrow
+        assert_eq!(cpu.a, 0x90);
+        assert!(cpu.flags.carry);
+    }
+
+    #[test]
+    fn test_8080_flags() {
+        let run_8080 = |program: &[u8], steps: usize| {
+            let mut cpu = Cpu::new(Memory::default(), CpuModel::I8080);
+            cpu.load_program(0, program).unwrap();
+            for _ in 0..steps {
+                cpu.step().unwrap();
+            }
+            cpu
+        };
+
+        // ADI 0x01 to 0x7F: P/V holds the parity of 0x80, not overflow
+        let cpu = run_8080(&[0x3E, 0x7F, 0xC6, 0x01], 2);
+        assert!(!cpu.flags.parity);
+        // SUI 0x01 from 0x10 borrows from bit 4, so AC is clear
+        let cpu = run_8080(&[0x3E, 0x10, 0xD6, 0x01], 2);
+        assert!(!cpu.flags.half_carry);
+        assert!(cpu.flags.parity);
+        // ANI takes AC from bit 3 of the operands
+        let cpu = run_8080(&[0x3E, 0x08, 0xE6, 0x00], 2);
+        assert!(cpu.flags.half_carry);
+        // DCR B from 0x81 sets AC and the parity of 0x80; STC and RLC keep AC
+        let cpu = run_8080(&[0x06, 0x81, 0x05, 0x37, 0x07], 3);
+        assert!(!cpu.flags.parity && cpu.flags.half_carry);
+        let cpu = run_8080(&[0x06, 0x81, 0x05, 0x37, 0x07], 5);
+        assert!(cpu.flags.half_carry);
+
+        // PUSH PSW stores bit 1 set and bits 3 and 5 clear
+        let cpu = run_8080(&[0x3E, 0xFF, 0xB7, 0x37], 3);
+        assert_eq!(cpu.get_af() & 0x2A, 0x02);
+        assert_eq!(cpu.get_af() as u8, 0x87);
+    }
+
+    #[test]
+    fn test_8080_daa_after_subtraction() {
+        // SUI 0x01 from 0x10, then DAA corrects as if after an addition
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::I8080);
+        cpu.load_program(0, &[0x3E, 0x10, 0xD6, 0x01, 0x27])
+            .unwrap();
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.a, 0x15);
+    }
+}
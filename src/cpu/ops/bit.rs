@@ -0,0 +1,281 @@
+//! CB-prefixed rotate, shift and bit manipulation instructions.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+impl Cpu {
+    /// Applies rotate/shift operation `op` (RLC, RRC, RL, RR, SLA, SRA, SLL, SRL)
+    /// to a value, setting S, Z, P and C from the result and clearing H and N
+    pub(crate) fn rotate_shift(&mut self, op: u8, value: u8) -> u8 {
+        let carry_in = self.flags.carry as u8;
+        let (result, carry) = match op & 0x07 {
+            0 => (value.rotate_left(1), value & 0x80 != 0),
+            1 => (value.rotate_right(1), value & 0x01 != 0),
+            2 => ((value << 1) | carry_in, value & 0x80 != 0),
+            3 => ((value >> 1) | (carry_in << 7), value & 0x01 != 0),
+            4 => (value << 1, value & 0x80 != 0),
+            5 => ((value >> 1) | (value & 0x80), value & 0x01 != 0),
+            6 => ((value << 1) | 0x01, value & 0x80 != 0),
+            _ => (value >> 1, value & 0x01 != 0),
+        };
+
+        self.update_szp_flags(result);
+        self.flags.half_carry = false;
+        self.flags.add_subtract = false;
+        self.flags.carry = carry;
+        result
+    }
+
+    /// Tests bit `bit` of a value as BIT does, leaving the carry flag alone.
+    /// X and Y are copied from `xy_source`, which depends on the addressing
+    /// mode.
+    pub(crate) fn test_bit(&mut self, bit: u8, value: u8, xy_source: u8) {
+        let set = value & (1 << (bit & 0x07)) != 0;
+        self.flags.zero = !set;
+        self.flags.parity = !set;
+        self.flags.sign = bit & 0x07 == 7 && set;
+        self.flags.half_carry = true;
+        self.flags.add_subtract = false;
+        self.update_xy_flags(xy_source);
+    }
+
+    /// Stores the result of a DDCB/FDCB operation. Every encoding other than
+    /// the documented (z=6) one also copies the result into a register.
+    fn store_indexed_bit_result(&mut self, address: u16, value: u8) -> Result<()> {
+        self.memory.write_byte(address, value)?;
+        if self.opcode & 0x07 != 6 {
+            self.set_main_reg8(self.opcode, value);
+        }
+        Ok(())
+    }
+}
+
+/// RLC/RRC/RL/RR/SLA/SRA/SLL/SRL r
+pub fn rot_r(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode;
+    let value = cpu.reg8(index)?;
+    let result = cpu.rotate_shift(cpu.opcode >> 3, value);
+    cpu.set_reg8(index, result)
+}
+
+/// BIT b, r. For BIT b, (HL) X and Y leak from the high byte of MEMPTR.
+pub fn bit_r(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.reg8(cpu.opcode)?;
+    let xy_source = match cpu.opcode & 0x07 {
+        6 => (cpu.wz >> 8) as u8,
+        _ => value,
+    };
+    cpu.test_bit(cpu.opcode >> 3, value, xy_source);
+    Ok(())
+}
+
+/// RES b, r
+pub fn res_r(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode;
+    let value = cpu.reg8(index)? & !(1 << ((cpu.opcode >> 3) & 0x07));
+    cpu.set_reg8(index, value)
+}
+
+/// SET b, r
+pub fn set_r(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode;
+    let value = cpu.reg8(index)? | (1 << ((cpu.opcode >> 3) & 0x07));
+    cpu.set_reg8(index, value)
+}
+
+/// RLC/RRC/RL/RR/SLA/SRA/SLL/SRL (IX+d), with optional register copy
+pub fn rot_indexed(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.hl_address()?;
+    let value = cpu.memory.read_byte(address)?;
+    let result = cpu.rotate_shift(cpu.opcode >> 3, value);
+    cpu.store_indexed_bit_result(address, result)
+}
+
+/// BIT b, (IX+d); all eight register encodings behave the same. X and Y
+/// come from the high byte of the computed address, now in MEMPTR.
+pub fn bit_indexed(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.hl_address()?;
+    let value = cpu.memory.read_byte(address)?;
+    cpu.test_bit(cpu.opcode >> 3, value, (address >> 8) as u8);
+    Ok(())
+}
+
+/// RES b, (IX+d), with optional register copy
+pub fn res_indexed(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.hl_address()?;
+    let value = cpu.memory.read_byte(address)? & !(1 << ((cpu.opcode >> 3) & 0x07));
+    cpu.store_indexed_bit_result(address, value)
+}
+
+/// SET b, (IX+d), with optional register copy
+pub fn set_indexed(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.hl_address()?;
+    let value = cpu.memory.read_byte(address)? | (1 << ((cpu.opcode >> 3) & 0x07));
+    cpu.store_indexed_bit_result(address, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+
+    fn run(program: &[u8], steps: usize) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, program).unwrap();
+        for _ in 0..steps {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_rotates() {
+        let cpu = run(&[0x06, 0x81, 0xCB, 0x00], 2); // LD B,0x81; RLC B
+        assert_eq!(cpu.b, 0x03);
+        assert!(cpu.flags.carry);
+        assert!(cpu.flags.parity);
+
+        let cpu = run(&[0x0E, 0x01, 0xCB, 0x19], 2); // LD C,0x01; RR C
+        assert_eq!(cpu.c, 0x00);
+        assert!(cpu.flags.carry);
+        assert!(cpu.flags.zero);
+    }
+
+    #[test]
+    fn test_shifts() {
+        let cpu = run(&[0x3E, 0x81, 0xCB, 0x2F], 2); // LD A,0x81; SRA A
+        assert_eq!(cpu.a, 0xC0);
+        assert!(cpu.flags.carry);
+        assert!(cpu.flags.sign);
+
+        let cpu = run(&[0x3E, 0x81, 0xCB, 0x3F], 2); // LD A,0x81; SRL A
+        assert_eq!(cpu.a, 0x40);
+        assert!(!cpu.flags.sign);
+
+        let cpu = run(&[0x3E, 0x80, 0xCB, 0x37], 2); // LD A,0x80; SLL A
+        assert_eq!(cpu.a, 0x01);
+        assert!(cpu.flags.carry);
+    }
+
+    #[test]
+    fn test_bit_flags() {
+        let cpu = run(&[0x3E, 0x80, 0xCB, 0x7F], 2); // LD A,0x80; BIT 7,A
+        assert!(!cpu.flags.zero);
+        assert!(cpu.flags.sign);
+        assert!(cpu.flags.half_carry);
+
+        let cpu = run(&[0x3E, 0x80, 0xCB, 0x47], 2); // LD A,0x80; BIT 0,A
+        assert!(cpu.flags.zero);
+        assert!(cpu.flags.parity);
+        assert!(!cpu.flags.sign);
+    }
+
+    #[test]
+    fn test_res_set_memory() {
+        // LD HL,0x8000; SET 3,(HL); SET 0,(HL); RES 3,(HL)
+        let program = [0x21, 0x00, 0x80, 0xCB, 0xDE, 0xCB, 0xC6, 0xCB, 0x9E];
+        let cpu = run(&program, 4);
+        assert_eq!(cpu.memory.read_byte(0x8000).unwrap(), 0x01);
+        assert_eq!(cpu.get_pc(), 9);
+    }
+
+    #[test]
+    fn test_cb_timing() {
+        let cpu = run(&[0xCB, 0x00], 1); // RLC B
+        assert_eq!(cpu.get_t_states(), 8);
+        assert_eq!(cpu.get_pc(), 2);
+
+        let cpu = run(&[0xCB, 0x06], 1); // RLC (HL)
+        assert_eq!(cpu.get_t_states(), 15);
+
+        let cpu = run(&[0xCB, 0x46], 1); // BIT 0,(HL)
+        assert_eq!(cpu.get_t_states(), 12);
+    }
+
+    #[test]
+    fn test_indexed_rotate() {
+        let mut cpu = Cpu {
+            ix: 0x8000,
+            ..Default::default()
+        };
+        cpu.memory.write_byte(0x8005, 0x81).unwrap();
+        cpu.load_program(0, &[0xDD, 0xCB, 0x05, 0x06]).unwrap(); // RLC (IX+5)
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.memory.read_byte(0x8005).unwrap(), 0x03);
+        assert!(cpu.flags.carry);
+        assert_eq!(cpu.get_pc(), 4);
+        assert_eq!(cpu.get_t_states(), 23);
+    }
+
+    #[test]
+    fn test_indexed_negative_displacement() {
+        let mut cpu = Cpu {
+            iy: 0x8000,
+            ..Default::default()
+        };
+        cpu.load_program(0, &[0xFD, 0xCB, 0xFE, 0xFE]).unwrap(); // SET 7,(IY-2)
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.memory.read_byte(0x7FFE).unwrap(), 0x80);
+        assert_eq!(cpu.get_t_states(), 23);
+    }
+
+    #[test]
+    fn test_indexed_register_copy() {
+        let mut cpu = Cpu {
+            ix: 0x8000,
+            ..Default::default()
+        };
+        cpu.memory.write_byte(0x8001, 0x80).unwrap();
+        // RLC (IX+1),B then RES 7,(IX+1),H
+        cpu.load_program(0, &[0xDD, 0xCB, 0x01, 0x00, 0xDD, 0xCB, 0x01, 0xBC])
+            .unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.b, 0x01);
+        assert_eq!(cpu.memory.read_byte(0x8001).unwrap(), 0x01);
+
+        cpu.memory.write_byte(0x8001, 0xFF).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.h, 0x7F);
+        assert_eq!(cpu.ix, 0x8000);
+        assert_eq!(cpu.memory.read_byte(0x8001).unwrap(), 0x7F);
+    }
+
+    #[test]
+    fn test_indexed_bit() {
+        let mut cpu = Cpu {
+            ix: 0x8000,
+            ..Default::default()
+        };
+        cpu.memory.write_byte(0x8000, 0x10).unwrap();
+        cpu.load_program(0, &[0xDD, 0xCB, 0x00, 0x61]).unwrap(); // BIT 4,(IX+0)
+        cpu.step().unwrap();
+
+        assert!(!cpu.flags.zero);
+        assert_eq!(cpu.c, 0x00);
+        assert_eq!(cpu.get_t_states(), 20);
+    }
+
+    #[test]
+    fn test_bit_undocumented_flags() {
+        let cpu = run(&[0x3E, 0x28, 0xCB, 0x47], 2); // LD A,0x28; BIT 0,A
+        assert!(cpu.flags.x && cpu.flags.y);
+
+        // BIT n,(IX+d) takes X and Y from the high byte of IX+d
+        let mut cpu = Cpu {
+            ix: 0x2800,
+            ..Default::default()
+        };
+        cpu.load_program(0, &[0xDD, 0xCB, 0x00, 0x46]).unwrap(); // BIT 0,(IX+0)
+        cpu.step().unwrap();
+        assert!(cpu.flags.x && cpu.flags.y);
+    }
+
+    #[test]
+    fn test_bit_hl_uses_memptr() {
+        // LD A,(0x2800); LD HL,0x8000; BIT 0,(HL): MEMPTR is 0x2801
+        let cpu = run(&[0x3A, 0x00, 0x28, 0x21, 0x00, 0x80, 0xCB, 0x46], 3);
+        assert_eq!(cpu.get_memptr(), 0x2801);
+        assert!(cpu.flags.x && cpu.flags.y);
+    }
+}
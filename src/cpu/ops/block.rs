@@ -0,0 +1,346 @@
+//! Block transfer, search and I/O instructions (LDI, CPI, INI, OUTI and
+//! their decrementing and repeating forms).
+//!
+//! All sixteen are registered with the 16 T-states of a single pass. The
+//! repeating forms move PC back onto the ED prefix and add the extra T-states
+//! themselves while the loop continues, so every iteration is a separate
+//! instruction that an interrupt can follow. The repeating instructions of
+//! the Z180 and Z80N loop the same way through `rewind_block`.
+
+use crate::cpu::instruction::FlagUtils;
+use crate::cpu::{Cpu, Ez80};
+use crate::Result;
+
+/// Extra T-states for an iteration that repeats (21 vs 16)
+const REPEAT_T_STATES: u32 = 5;
+/// Extra cycles for an R800 iteration that repeats (5 vs 4)
+const R800_REPEAT_T_STATES: u32 = 1;
+
+impl Cpu {
+    /// Returns the address step of a block instruction: +1 for the
+    /// incrementing forms and -1 for the decrementing (bit 3 set) forms
+    pub(crate) fn block_step(&self) -> u16 {
+        if self.opcode & 0x08 != 0 {
+            0xFFFF
+        } else {
+            0x0001
+        }
+    }
+
+    /// Moves PC back onto the current block instruction so that it runs
+    /// again, charging `t_states` for the repeat, and returns the new PC.
+    /// An eZ80 mode suffix is part of the instruction and repeats with it.
+    pub(crate) fn rewind_block(&mut self, t_states: u32) -> u16 {
+        let length = 2 + u16::from(self.ez80.as_ref().is_some_and(Ez80::suffixed));
+        self.advance_pc(length.wrapping_neg());
+        self.t_states += t_states;
+        self.pc
+    }
+
+    /// Repeats the current block instruction if it is a repeating (bit 4
+    /// set) form and `again` holds, returning whether it repeats. A repeating
+    /// iteration copies bits 11 and 13 of the rewound PC into X and Y.
+    fn repeat_block(&mut self, again: bool) -> bool {
+        let repeats = self.opcode & 0x10 != 0 && again;
+        if repeats {
+            let pc = self.rewind_block(if self.model.is_r800() {
+                R800_REPEAT_T_STATES
+            } else {
+                REPEAT_T_STATES
+            });
+            self.update_xy_flags((pc >> 8) as u8);
+            // LDIR/LDDR/CPIR/CPDR leave MEMPTR one past the instruction's ED byte
+            if self.opcode & 0x02 == 0 {
+                self.wz = pc.wrapping_add(1);
+            }
+        }
+        repeats
+    }
+
+    /// Sets the flags of LDI/LDD from the copied byte and whether the count
+    /// is still nonzero, and repeats LDIR/LDDR while it is
+    pub(crate) fn finish_ldi(&mut self, value: u8, remaining: bool) {
+        // X and Y come from bits 3 and 1 of the copied byte plus A
+        let n = value.wrapping_add(self.a);
+        self.flags.half_carry = false;
+        self.flags.add_subtract = false;
+        self.flags.parity = remaining;
+        self.update_xy_flags((n & 0x08) | ((n & 0x02) << 4));
+        self.repeat_block(remaining);
+    }
+
+    /// Sets the flags of CPI/CPD from the compared byte and whether the
+    /// count is still nonzero, and repeats CPIR/CPDR until a match
+    pub(crate) fn finish_cpi(&mut self, value: u8, remaining: bool) {
+        let result = self.a.wrapping_sub(value);
+        let half_carry = (self.a & 0x0F) < (value & 0x0F);
+        self.update_sz_flags(result);
+        self.flags.half_carry = half_carry;
+        self.flags.add_subtract = true;
+        self.flags.parity = remaining;
+
+        // X and Y come from bits 3 and 1 of the result less the half borrow
+        let n = result.wrapping_sub(half_carry as u8);
+        self.update_xy_flags((n & 0x08) | ((n & 0x02) << 4));
+        self.repeat_block(remaining && result != 0);
+    }
+
+    /// Sets the flags of INI/IND/OUTI/OUTD from the transferred byte and
+    /// `k`, the byte added to it (the adjusted C for input, L for output)
+    fn update_block_io_flags(&mut self, value: u8, k: u8) {
+        let sum = u16::from(value) + u16::from(k);
+        self.update_sz_flags(self.b);
+        self.flags.add_subtract = value & 0x80 != 0;
+        self.flags.half_carry = sum > 0xFF;
+        self.flags.carry = sum > 0xFF;
+        self.update_parity_flag((sum as u8 & 0x07) ^ self.b);
+    }
+
+    /// Adjusts H and P/V when INIR/INDR/OTIR/OTDR repeats, as the CPU
+    /// starts decrementing B again for the next iteration
+    fn update_block_io_repeat_flags(&mut self, value: u8) {
+        let parity = |byte: u8| byte.count_ones().is_multiple_of(2);
+        if self.flags.carry {
+            let b = if value & 0x80 != 0 {
+                self.flags.half_carry = self.b & 0x0F == 0x00;
+                self.b.wrapping_sub(1)
+            } else {
+                self.flags.half_carry = self.b & 0x0F == 0x0F;
+                self.b.wrapping_add(1)
+            };
+            self.flags.parity ^= !parity(b & 0x07);
+        } else {
+            self.flags.parity ^= !parity(self.b & 0x07);
+        }
+    }
+}
+
+/// LDI, LDD, LDIR and LDDR
+pub fn ldi(cpu: &mut Cpu) -> Result<()> {
+    let step = cpu.block_step();
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.memory.write_byte(cpu.get_de(), value)?;
+    cpu.set_hl(cpu.get_hl().wrapping_add(step));
+    cpu.set_de(cpu.get_de().wrapping_add(step));
+    cpu.set_bc(cpu.get_bc().wrapping_sub(1));
+    cpu.finish_ldi(value, cpu.get_bc() != 0);
+    Ok(())
+}
+
+/// CPI, CPD, CPIR and CPDR. The repeating forms stop on a match.
+pub fn cpi(cpu: &mut Cpu) -> Result<()> {
+    let step = cpu.block_step();
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.set_hl(cpu.get_hl().wrapping_add(step));
+    cpu.set_bc(cpu.get_bc().wrapping_sub(1));
+
+    cpu.wz = cpu.wz.wrapping_add(step);
+    cpu.finish_cpi(value, cpu.get_bc() != 0);
+    Ok(())
+}
+
+/// INI, IND, INIR and INDR
+pub fn ini(cpu: &mut Cpu) -> Result<()> {
+    let step = cpu.block_step();
+    let value = cpu.port_in(cpu.get_bc());
+    cpu.wz = cpu.get_bc().wrapping_add(step);
+    cpu.memory.write_byte(cpu.get_hl(), value)?;
+    cpu.set_hl(cpu.get_hl().wrapping_add(step));
+    cpu.b = cpu.b.wrapping_sub(1);
+
+    cpu.update_block_io_flags(value, cpu.c.wrapping_add(step as u8));
+    if cpu.repeat_block(cpu.b != 0) {
+        cpu.update_block_io_repeat_flags(value);
+    }
+    Ok(())
+}
+
+/// OUTI, OUTD, OTIR and OTDR. B is decremented before it goes on the
+/// address bus.
+pub fn outi(cpu: &mut Cpu) -> Result<()> {
+    let step = cpu.block_step();
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.b = cpu.b.wrapping_sub(1);
+    cpu.port_out(cpu.get_bc(), value);
+    cpu.wz = cpu.get_bc().wrapping_add(step);
+    cpu.set_hl(cpu.get_hl().wrapping_add(step));
+
+    cpu.update_block_io_flags(value, cpu.l);
+    if cpu.repeat_block(cpu.b != 0) {
+        cpu.update_block_io_repeat_flags(value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+    use crate::io::IoDevice;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn load(program: &[u8]) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, program).unwrap();
+        cpu
+    }
+
+    /// Steps until PC reaches `end`
+    fn run_to(cpu: &mut Cpu, end: u16) {
+        while cpu.get_pc() != end {
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ldir_copies_and_times() {
+        // LD HL,0x8000; LD DE,0x9000; LD BC,3; LDIR
+        let mut cpu = load(&[
+            0x21, 0x00, 0x80, 0x11, 0x00, 0x90, 0x01, 0x03, 0x00, 0xED, 0xB0,
+        ]);
+        cpu.load_program(0x8000, &[0x11, 0x22, 0x33]).unwrap();
+        run_to(&mut cpu, 0x000B);
+
+        assert_eq!(cpu.memory.read_byte(0x9002).unwrap(), 0x33);
+        assert_eq!(cpu.get_hl(), 0x8003);
+        assert_eq!(cpu.get_de(), 0x9003);
+        assert_eq!(cpu.get_bc(), 0);
+        assert!(!cpu.flags.parity);
+        assert_eq!(cpu.get_t_states(), 30 + 21 + 21 + 16);
+    }
+
+    #[test]
+    fn test_ldir_rewinds_between_iterations() {
+        // LD BC,2; LDIR
+        let mut cpu = load(&[0x01, 0x02, 0x00, 0xED, 0xB0]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert!(cpu.flags.parity);
+        assert_eq!(cpu.get_t_states(), 10 + 21);
+    }
+
+    #[test]
+    fn test_lddr() {
+        // LD HL,0x8001; LD DE,0x9001; LD BC,2; LDDR
+        let mut cpu = load(&[
+            0x21, 0x01, 0x80, 0x11, 0x01, 0x90, 0x01, 0x02, 0x00, 0xED, 0xB8,
+        ]);
+        cpu.load_program(0x8000, &[0xAA, 0xBB]).unwrap();
+        run_to(&mut cpu, 0x000B);
+
+        assert_eq!(cpu.memory.read_byte(0x9000).unwrap(), 0xAA);
+        assert_eq!(cpu.memory.read_byte(0x9001).unwrap(), 0xBB);
+        assert_eq!(cpu.get_hl(), 0x7FFF);
+    }
+
+    #[test]
+    fn test_cpir_stops_on_match() {
+        // LD HL,0x8000; LD BC,5; LD A,0x33; CPIR
+        let mut cpu = load(&[0x21, 0x00, 0x80, 0x01, 0x05, 0x00, 0x3E, 0x33, 0xED, 0xB1]);
+        cpu.load_program(0x8000, &[0x11, 0x22, 0x33, 0x44]).unwrap();
+        run_to(&mut cpu, 0x000A);
+
+        assert!(cpu.flags.zero);
+        assert!(cpu.flags.parity);
+        assert!(cpu.flags.add_subtract);
+        assert_eq!(cpu.get_hl(), 0x8003);
+        assert_eq!(cpu.get_bc(), 2);
+        assert_eq!(cpu.get_t_states(), 10 + 10 + 7 + 21 + 21 + 16);
+    }
+
+    /// Answers reads with an incrementing counter and records writes
+    struct Port {
+        next: u8,
+        writes: Rc<RefCell<Vec<(u16, u8)>>>,
+    }
+
+    impl IoDevice for Port {
+        fn read_port(&mut self, _port: u16) -> u8 {
+            self.next = self.next.wrapping_add(1);
+            self.next
+        }
+
+        fn write_port(&mut self, port: u16, value: u8) {
+            self.writes.borrow_mut().push((port, value));
+        }
+    }
+
+    #[test]
+    fn test_inir_and_otir() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        // LD HL,0x8000; LD BC,0x0210; INIR; LD HL,0x8000; LD B,2; OTIR
+        let mut cpu = load(&[
+            0x21, 0x00, 0x80, 0x01, 0x10, 0x02, 0xED, 0xB2, 0x21, 0x00, 0x80, 0x06, 0x02, 0xED,
+            0xB3,
+        ]);
+        cpu.set_io_device(Box::new(Port {
+            next: 0,
+            writes: writes.clone(),
+        }));
+        run_to(&mut cpu, 0x0008);
+
+        assert_eq!(cpu.memory.read_byte(0x8000).unwrap(), 0x01);
+        assert_eq!(cpu.memory.read_byte(0x8001).unwrap(), 0x02);
+        assert_eq!(cpu.b, 0);
+        assert!(cpu.flags.zero);
+
+        run_to(&mut cpu, 0x000F);
+        // B is decremented before each OUT
+        assert_eq!(*writes.borrow(), vec![(0x0110, 0x01), (0x0010, 0x02)]);
+        assert_eq!(cpu.get_hl(), 0x8002);
+    }
+
+    #[test]
+    fn test_block_io_undocumented_flags() {
+        // LD HL,0x8000; LD (HL),0x80; LD BC,0x01FF; OUTI
+        let mut cpu = load(&[0x21, 0x00, 0x80, 0x36, 0x80, 0x01, 0xFF, 0x01, 0xED, 0xA3]);
+        run_to(&mut cpu, 0x000A);
+
+        // N copies bit 7 of the byte; H and C from 0x80 + L (0x01)
+        assert!(cpu.flags.add_subtract);
+        assert!(!cpu.flags.carry);
+        assert!(!cpu.flags.half_carry);
+        assert!(cpu.flags.zero);
+        // (0x81 & 7) ^ B is 1, which has odd parity
+        assert!(!cpu.flags.parity);
+    }
+
+    #[test]
+    fn test_block_undocumented_xy_flags() {
+        // LD HL,0x8000; LD DE,0x9000; LD BC,1; LD A,0x20; LDI with (HL)=0x08
+        let mut cpu = load(&[
+            0x21, 0x00, 0x80, 0x11, 0x00, 0x90, 0x01, 0x01, 0x00, 0x3E, 0x20, 0xED, 0xA0,
+        ]);
+        cpu.memory.write_byte(0x8000, 0x08).unwrap();
+        run_to(&mut cpu, 0x000D);
+        // A + (HL) = 0x28: X is bit 3, Y is bit 1
+        assert!(cpu.flags.x);
+        assert!(!cpu.flags.y);
+
+        // A repeating LDIR at 0x2800 takes X and Y from the PC high byte
+        let mut cpu = Cpu::default();
+        cpu.load_program(0x2800, &[0xED, 0xB0]).unwrap();
+        cpu.set_bc(2);
+        cpu.pc = 0x2800;
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x2800);
+        assert!(cpu.flags.x && cpu.flags.y);
+    }
+
+    #[test]
+    fn test_block_memptr() {
+        // LD BC,2; CPIR at 0x0003 repeats, leaving MEMPTR at 0x0004
+        let mut cpu = load(&[0x01, 0x02, 0x00, 0xED, 0xB1]);
+        cpu.set_hl(0x8000);
+        cpu.memory.write_byte(0x8000, 0x55).unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert_eq!(cpu.get_memptr(), 0x0004);
+
+        // CPI then increments MEMPTR
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_memptr(), 0x0005);
+    }
+}
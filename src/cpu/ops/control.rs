@@ -0,0 +1,114 @@
+//! CPU control instructions.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+/// HALT
+///
+/// Enters the halted state with PC on the following instruction, which is
+/// the address pushed when an interrupt wakes the CPU.
+pub fn halt(cpu: &mut Cpu) -> Result<()> {
+    cpu.halted = true;
+    Ok(())
+}
+
+/// DI
+pub fn di(cpu: &mut Cpu) -> Result<()> {
+    cpu.iff1 = false;
+    cpu.iff2 = false;
+    Ok(())
+}
+
+/// EI. Maskable interrupts are not accepted until after the next
+/// instruction.
+pub fn ei(cpu: &mut Cpu) -> Result<()> {
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+    cpu.ei_delay = true;
+    Ok(())
+}
+
+/// IM 0, IM 1 and IM 2, including the undocumented mirrors
+pub fn im(cpu: &mut Cpu) -> Result<()> {
+    cpu.im = match (cpu.opcode >> 3) & 0x03 {
+        2 => 1,
+        3 => 2,
+        _ => 0,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+
+    #[test]
+    fn test_halt_holds_pc() {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, &[0x76]).unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.is_halted());
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0001);
+        assert_eq!(cpu.get_t_states(), 12);
+        // One refresh for the HALT fetch and one per NOP cycle
+        assert_eq!(cpu.get_r(), 3);
+    }
+
+    #[test]
+    fn test_interrupt_wakes_halt() {
+        // IM 1; EI; HALT; NOP
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, &[0xED, 0x56, 0xFB, 0x76, 0x00])
+            .unwrap();
+        cpu.sp = 0x8000;
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.get_pc(), 0x0004);
+
+        cpu.set_int_line(true);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.get_pc(), 0x0038);
+        // Returning resumes after the HALT
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0004);
+    }
+
+    #[test]
+    fn test_nmi_wakes_halt() {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, &[0x76]).unwrap();
+        cpu.step().unwrap();
+        cpu.request_nmi();
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.get_pc(), 0x0066);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0001);
+    }
+
+    #[test]
+    fn test_di_ei() {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, &[0xFB, 0xF3]).unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.iff1 && cpu.iff2);
+        cpu.step().unwrap();
+        assert!(!cpu.iff1 && !cpu.iff2);
+    }
+
+    #[test]
+    fn test_interrupt_modes() {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, &[0xED, 0x5E, 0xED, 0x76, 0xED, 0x4E])
+            .unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_im(), 2);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_im(), 1);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_im(), 0);
+    }
+}
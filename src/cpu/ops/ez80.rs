@@ -0,0 +1,762 @@
+//! eZ80 instructions: width-aware versions of the Z80 instructions that
+//! handle addresses and register pairs, which work on 24 bits in ADL mode
+//! or with an .L suffix, and the eZ80's own LEA, PEA and MBASE loads.
+//!
+//! Z80 handlers that reach memory through (HL), (IX+d) or (IY+d) run
+//! unchanged, as the bus places HLU, the upper byte of the index address
+//! or MBASE in front of their 16-bit addresses.
+
+use crate::cpu::ops::alu;
+use crate::cpu::ops::block;
+use crate::cpu::ops::load;
+use crate::cpu::{Cpu, Ez80, IndexMode};
+use crate::Result;
+
+/// Mask of a 24-bit address or register
+const LONG_MASK: u32 = 0xFF_FFFF;
+
+impl Cpu {
+    fn ez80_state(&self) -> &Ez80 {
+        self.ez80
+            .as_ref()
+            .expect("eZ80 instruction on another model")
+    }
+
+    fn ez80_state_mut(&mut self) -> &mut Ez80 {
+        self.ez80
+            .as_mut()
+            .expect("eZ80 instruction on another model")
+    }
+
+    /// Returns true if the executing instruction works on 24 bits
+    fn long_data(&self) -> bool {
+        self.ez80_state().long_data()
+    }
+
+    /// Returns the bytes in a register pair or stack entry: 3 or 2
+    fn width(&self) -> u32 {
+        if self.long_data() {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Returns the bus address of a register or immediate address: all 24
+    /// bits when long, else the low 16 bits behind MBASE
+    fn bus_address(&self, address: u32) -> u32 {
+        if self.long_data() {
+            address & LONG_MASK
+        } else {
+            (u32::from(self.ez80_state().mb) << 16) | (address & 0xFFFF)
+        }
+    }
+
+    /// Reads a 16- or 24-bit little-endian value
+    fn read_value(&self, address: u32) -> u32 {
+        (0..self.width()).fold(0, |value, i| {
+            let byte = self.memory.read_physical(self.bus_address(address + i));
+            value | (u32::from(byte) << (8 * i))
+        })
+    }
+
+    /// Writes a 16- or 24-bit little-endian value
+    fn write_value(&mut self, address: u32, value: u32) {
+        for i in 0..self.width() {
+            let address = self.bus_address(address + i);
+            self.memory
+                .write_physical(address, (value >> (8 * i)) as u8);
+        }
+    }
+
+    /// Fetches a 16- or 24-bit immediate. A 16-bit immediate loaded into a
+    /// 24-bit register takes MBASE as its upper byte.
+    fn fetch_immediate(&mut self) -> Result<u32> {
+        let low = u32::from(self.fetch_word()?);
+        let ez80 = self.ez80_state();
+        let (long_immediate, long_data, mb) = (ez80.long_immediate(), ez80.long_data(), ez80.mb);
+        Ok(if long_immediate {
+            low | (u32::from(self.fetch_byte()?) << 16)
+        } else if long_data {
+            low | (u32::from(mb) << 16)
+        } else {
+            low
+        })
+    }
+
+    /// Returns the upper byte of BC, DE or HL by its opcode encoding,
+    /// where HL follows the index prefix
+    fn upper(&self, index: u8) -> u8 {
+        let ez80 = self.ez80_state();
+        match (index & 0x03, self.index_mode) {
+            (0, _) => ez80.bcu,
+            (1, _) => ez80.deu,
+            (_, IndexMode::Ix) => ez80.ixu,
+            (_, IndexMode::Iy) => ez80.iyu,
+            _ => ez80.hlu,
+        }
+    }
+
+    fn upper_mut(&mut self, index: u8) -> &mut u8 {
+        let index_mode = self.index_mode;
+        let ez80 = self.ez80_state_mut();
+        match (index & 0x03, index_mode) {
+            (0, _) => &mut ez80.bcu,
+            (1, _) => &mut ez80.deu,
+            (_, IndexMode::Ix) => &mut ez80.ixu,
+            (_, IndexMode::Iy) => &mut ez80.iyu,
+            _ => &mut ez80.hlu,
+        }
+    }
+
+    /// Reads a register pair by its opcode encoding (BC, DE, HL, SP) at the
+    /// width of the executing instruction. SP is SPL when long, else SPS.
+    fn pair(&self, index: u8) -> u32 {
+        match (index & 0x03, self.long_data()) {
+            (3, true) => self.ez80_state().spl,
+            (_, true) => (u32::from(self.upper(index)) << 16) | u32::from(self.reg16(index)),
+            _ => u32::from(self.reg16(index)),
+        }
+    }
+
+    /// Writes a register pair at the width of the executing instruction. A
+    /// 16-bit write leaves the upper byte alone.
+    fn set_pair(&mut self, index: u8, value: u32) {
+        match (index & 0x03, self.long_data()) {
+            (3, true) => self.ez80_state_mut().spl = value & LONG_MASK,
+            (_, true) => {
+                self.set_reg16(index, value as u16);
+                *self.upper_mut(index) = (value >> 16) as u8;
+            }
+            _ => self.set_reg16(index, value as u16),
+        }
+    }
+
+    /// Returns IX or IY plus a signed displacement
+    fn index_offset(&mut self, index_mode: IndexMode, offset: i8) -> u32 {
+        self.index_mode = index_mode;
+        self.pair(2).wrapping_add(offset as u32)
+    }
+
+    fn push_value(&mut self, value: u32) -> Result<()> {
+        let sp = self.pair(3).wrapping_sub(self.width());
+        self.set_pair(3, sp);
+        self.write_value(sp, value);
+        Ok(())
+    }
+
+    fn pop_value(&mut self) -> Result<u32> {
+        let sp = self.pair(3);
+        let value = self.read_value(sp);
+        self.set_pair(3, sp + self.width());
+        Ok(value)
+    }
+
+    /// Returns the address of the next instruction, with PCU in ADL mode
+    fn return_address(&self) -> u32 {
+        let ez80 = self.ez80_state();
+        if ez80.adl {
+            (u32::from(ez80.pcu) << 16) | u32::from(self.pc)
+        } else {
+            u32::from(self.pc)
+        }
+    }
+
+    /// Continues at `address`, whose upper byte becomes PCU in ADL mode. A
+    /// jump with a suffix first switches to ADL mode for .IL and to Z80
+    /// mode for .IS.
+    fn jump_to(&mut self, address: u32) {
+        let ez80 = self.ez80_state_mut();
+        if ez80.suffixed() {
+            ez80.adl = ez80.long_immediate();
+        }
+        if ez80.adl {
+            ez80.pcu = (address >> 16) as u8;
+        }
+        self.pc = address as u16;
+        self.wz = address as u16;
+    }
+
+    /// Pushes the return address at the width of the executing instruction
+    /// and continues at `address`
+    pub(crate) fn call_long(&mut self, address: u32) -> Result<()> {
+        self.push_value(self.return_address())?;
+        self.jump_to(address);
+        Ok(())
+    }
+
+    /// Points 16-bit data addresses at the bank of IX+d or IY+d, which can
+    /// carry into the upper byte when long
+    pub(crate) fn select_index_bank(&mut self, offset: i8) {
+        if self.ez80.as_ref().is_some_and(Ez80::long_data) {
+            let address = self.pair(2).wrapping_add(offset as u32);
+            self.memory.set_address_base((address >> 16) as u8);
+        }
+    }
+
+    /// Adds or subtracts two 24-bit values with carry as ADC HL,rr and
+    /// SBC HL,rr do in ADL mode, setting every flag from the result
+    fn adc_sbc24(&mut self, a: u32, b: u32, subtract: bool) -> u32 {
+        let carry = self.flags.carry as u32;
+        let (result, half_carry) = if subtract {
+            let half = (a & 0x0FFF) < (b & 0x0FFF) + carry;
+            (a.wrapping_sub(b).wrapping_sub(carry), half)
+        } else {
+            let half = (a & 0x0FFF) + (b & 0x0FFF) + carry > 0x0FFF;
+            (a + b + carry, half)
+        };
+
+        let b_sign = if subtract { !b } else { b };
+        let overflow = (a ^ b_sign) & 0x80_0000 == 0 && (a ^ result) & 0x80_0000 != 0;
+
+        self.flags.sign = result & 0x80_0000 != 0;
+        self.flags.zero = result & LONG_MASK == 0;
+        self.flags.half_carry = half_carry;
+        self.flags.parity = overflow;
+        self.flags.add_subtract = subtract;
+        self.flags.carry = result > LONG_MASK;
+        result & LONG_MASK
+    }
+}
+
+/// LD rr, nn
+pub fn ld_rr_nn(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.fetch_immediate()?;
+    cpu.set_pair(cpu.opcode >> 4, value);
+    Ok(())
+}
+
+/// LD (BC), A and LD (DE), A
+pub fn ld_rr_ind_a(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.pair(cpu.opcode >> 4);
+    cpu.store_a_memptr(address as u16);
+    cpu.memory.write_physical(cpu.bus_address(address), cpu.a);
+    Ok(())
+}
+
+/// LD A, (BC) and LD A, (DE)
+pub fn ld_a_rr_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.pair(cpu.opcode >> 4);
+    cpu.wz = (address as u16).wrapping_add(1);
+    cpu.a = cpu.memory.read_physical(cpu.bus_address(address));
+    Ok(())
+}
+
+/// LD (nn), A
+pub fn ld_nn_ind_a(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.store_a_memptr(address as u16);
+    cpu.memory.write_physical(cpu.bus_address(address), cpu.a);
+    Ok(())
+}
+
+/// LD A, (nn)
+pub fn ld_a_nn_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.wz = (address as u16).wrapping_add(1);
+    cpu.a = cpu.memory.read_physical(cpu.bus_address(address));
+    Ok(())
+}
+
+/// LD (nn), HL (also IX and IY) and LD (nn), rr
+pub fn ld_nn_ind_rr(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.wz = (address as u16).wrapping_add(1);
+    let index = if cpu.opcode & 0xC0 == 0 {
+        2
+    } else {
+        cpu.opcode >> 4
+    };
+    cpu.write_value(address, cpu.pair(index));
+    Ok(())
+}
+
+/// LD HL, (nn) (also IX and IY) and LD rr, (nn)
+pub fn ld_rr_nn_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.wz = (address as u16).wrapping_add(1);
+    let index = if cpu.opcode & 0xC0 == 0 {
+        2
+    } else {
+        cpu.opcode >> 4
+    };
+    let value = cpu.read_value(address);
+    cpu.set_pair(index, value);
+    Ok(())
+}
+
+/// INC rr
+pub fn inc_rr(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 4;
+    cpu.set_pair(index, cpu.pair(index) + 1);
+    Ok(())
+}
+
+/// DEC rr
+pub fn dec_rr(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 4;
+    cpu.set_pair(index, cpu.pair(index).wrapping_sub(1));
+    Ok(())
+}
+
+/// ADD HL, rr (also ADD IX, rr and ADD IY, rr). The 24-bit form carries
+/// out of bit 23 and leaves X and Y alone.
+pub fn add_hl_rr(cpu: &mut Cpu) -> Result<()> {
+    if !cpu.long_data() {
+        return alu::add_hl_rr(cpu);
+    }
+    let (a, b) = (cpu.pair(2), cpu.pair(cpu.opcode >> 4));
+    let result = a + b;
+    cpu.flags.half_carry = (a & 0x0FFF) + (b & 0x0FFF) > 0x0FFF;
+    cpu.flags.add_subtract = false;
+    cpu.flags.carry = result > LONG_MASK;
+    cpu.set_pair(2, result);
+    Ok(())
+}
+
+/// ADC HL, rr
+pub fn adc_hl_rr(cpu: &mut Cpu) -> Result<()> {
+    if !cpu.long_data() {
+        return alu::adc_hl_rr(cpu);
+    }
+    let result = cpu.adc_sbc24(cpu.pair(2), cpu.pair(cpu.opcode >> 4), false);
+    cpu.set_pair(2, result);
+    Ok(())
+}
+
+/// SBC HL, rr
+pub fn sbc_hl_rr(cpu: &mut Cpu) -> Result<()> {
+    if !cpu.long_data() {
+        return alu::sbc_hl_rr(cpu);
+    }
+    let result = cpu.adc_sbc24(cpu.pair(2), cpu.pair(cpu.opcode >> 4), true);
+    cpu.set_pair(2, result);
+    Ok(())
+}
+
+/// LD SP, HL (also IX and IY)
+pub fn ld_sp_hl(cpu: &mut Cpu) -> Result<()> {
+    cpu.set_pair(3, cpu.pair(2));
+    Ok(())
+}
+
+/// PUSH qq (BC, DE, HL, AF). AF takes a zero upper byte when long.
+pub fn push_qq(cpu: &mut Cpu) -> Result<()> {
+    let value = match (cpu.opcode >> 4) & 0x03 {
+        3 => u32::from(cpu.get_af()),
+        index => cpu.pair(index),
+    };
+    cpu.push_value(value)
+}
+
+/// POP qq (BC, DE, HL, AF)
+pub fn pop_qq(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.pop_value()?;
+    match (cpu.opcode >> 4) & 0x03 {
+        3 => cpu.set_af(value as u16),
+        index => cpu.set_pair(index, value),
+    }
+    Ok(())
+}
+
+/// EXX, which also exchanges the upper bytes
+pub fn exx(cpu: &mut Cpu) -> Result<()> {
+    cpu.ez80_state_mut().exchange_upper();
+    load::exx(cpu)
+}
+
+/// EX DE, HL
+pub fn ex_de_hl(cpu: &mut Cpu) -> Result<()> {
+    if cpu.long_data() {
+        let ez80 = cpu.ez80_state_mut();
+        std::mem::swap(&mut ez80.deu, &mut ez80.hlu);
+    }
+    load::ex_de_hl(cpu)
+}
+
+/// EX (SP), HL (also IX and IY)
+pub fn ex_sp_ind_hl(cpu: &mut Cpu) -> Result<()> {
+    let sp = cpu.pair(3);
+    let value = cpu.read_value(sp);
+    cpu.write_value(sp, cpu.pair(2));
+    cpu.set_pair(2, value);
+    cpu.wz = value as u16;
+    Ok(())
+}
+
+/// JP nn
+pub fn jp_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.jump_to(address);
+    Ok(())
+}
+
+/// JP cc, nn
+pub fn jp_cc_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.wz = address as u16;
+    if cpu.condition(cpu.opcode >> 3) {
+        cpu.jump_to(address);
+        cpu.t_states += cpu.taken_timing().jp;
+    }
+    Ok(())
+}
+
+/// JP (HL) (also JP (IX) and JP (IY))
+pub fn jp_hl(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.pair(2);
+    let wz = cpu.wz;
+    cpu.jump_to(address);
+    cpu.wz = wz;
+    Ok(())
+}
+
+/// CALL nn
+pub fn call_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.call_long(address)
+}
+
+/// CALL cc, nn
+pub fn call_cc_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_immediate()?;
+    cpu.wz = address as u16;
+    if cpu.condition(cpu.opcode >> 3) {
+        cpu.call_long(address)?;
+        cpu.t_states += cpu.taken_timing().call;
+    }
+    Ok(())
+}
+
+/// RET
+pub fn ret(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.pop_value()?;
+    cpu.jump_to(address);
+    Ok(())
+}
+
+/// RET cc
+pub fn ret_cc(cpu: &mut Cpu) -> Result<()> {
+    if cpu.condition(cpu.opcode >> 3) {
+        ret(cpu)?;
+        cpu.t_states += cpu.taken_timing().ret;
+    }
+    Ok(())
+}
+
+/// RETN and RETI
+pub fn retn(cpu: &mut Cpu) -> Result<()> {
+    cpu.iff1 = cpu.iff2;
+    ret(cpu)
+}
+
+/// RST p, which in ADL mode restarts in the first 64 KB
+pub fn rst(cpu: &mut Cpu) -> Result<()> {
+    cpu.call_long(u32::from(cpu.opcode & 0x38))
+}
+
+/// LDI, LDD, LDIR and LDDR, with 24-bit pointers and count when long
+pub fn ldi(cpu: &mut Cpu) -> Result<()> {
+    if !cpu.long_data() {
+        return block::ldi(cpu);
+    }
+    let step = cpu.block_step() as i16 as u32;
+    let (hl, de) = (cpu.pair(2), cpu.pair(1));
+    let value = cpu.memory.read_physical(hl);
+    cpu.memory.write_physical(de, value);
+    cpu.set_pair(2, hl.wrapping_add(step));
+    cpu.set_pair(1, de.wrapping_add(step));
+    let bc = cpu.pair(0).wrapping_sub(1) & LONG_MASK;
+    cpu.set_pair(0, bc);
+    cpu.finish_ldi(value, bc != 0);
+    Ok(())
+}
+
+/// CPI, CPD, CPIR and CPDR, with a 24-bit pointer and count when long
+pub fn cpi(cpu: &mut Cpu) -> Result<()> {
+    if !cpu.long_data() {
+        return block::cpi(cpu);
+    }
+    let step = cpu.block_step();
+    let hl = cpu.pair(2);
+    let value = cpu.memory.read_physical(hl);
+    cpu.set_pair(2, hl.wrapping_add(step as i16 as u32));
+    let bc = cpu.pair(0).wrapping_sub(1) & LONG_MASK;
+    cpu.set_pair(0, bc);
+    cpu.wz = cpu.wz.wrapping_add(step);
+    cpu.finish_cpi(value, bc != 0);
+    Ok(())
+}
+
+/// LEA rr, IX+d and LEA rr, IY+d: loads a register pair with an index
+/// register plus a displacement, leaving the flags alone
+pub fn lea(cpu: &mut Cpu) -> Result<()> {
+    let offset = cpu.fetch_byte()? as i8;
+    let (source, destination, index) = match cpu.opcode {
+        0x54 => (IndexMode::Iy, IndexMode::Ix, 2),
+        0x55 => (IndexMode::Ix, IndexMode::Iy, 2),
+        0x32 => (IndexMode::Ix, IndexMode::Ix, 2),
+        0x33 => (IndexMode::Iy, IndexMode::Iy, 2),
+        opcode if opcode & 0x01 == 0 => (IndexMode::Ix, IndexMode::Hl, opcode >> 4),
+        opcode => (IndexMode::Iy, IndexMode::Hl, opcode >> 4),
+    };
+    let value = cpu.index_offset(source, offset);
+    cpu.index_mode = destination;
+    cpu.set_pair(index, value);
+    Ok(())
+}
+
+/// PEA IX+d and PEA IY+d: pushes an index register plus a displacement
+pub fn pea(cpu: &mut Cpu) -> Result<()> {
+    let offset = cpu.fetch_byte()? as i8;
+    let source = if cpu.opcode == 0x65 {
+        IndexMode::Ix
+    } else {
+        IndexMode::Iy
+    };
+    let value = cpu.index_offset(source, offset);
+    cpu.push_value(value)
+}
+
+/// LD MB, A
+pub fn ld_mb_a(cpu: &mut Cpu) -> Result<()> {
+    cpu.ez80_state_mut().mb = cpu.a;
+    Ok(())
+}
+
+/// LD A, MB
+pub fn ld_a_mb(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = cpu.ez80_state().mb;
+    Ok(())
+}
+
+/// STMIX and RSMIX, which set and clear the mixed-memory mode flag
+pub fn stmix(cpu: &mut Cpu) -> Result<()> {
+    cpu.ez80_state_mut().madl = cpu.opcode == 0x7D;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::ops::fixture::{cpu_with, run};
+    use crate::cpu::{Cpu, CpuModel};
+
+    /// Creates an eZ80 in ADL or Z80 mode with `program` at address 0
+    fn ez80(program: &[u8], adl: bool) -> Cpu {
+        let mut cpu = cpu_with(CpuModel::Ez80, program);
+        cpu.ez80().unwrap().adl = adl;
+        cpu
+    }
+
+    #[test]
+    fn test_z80_mode_uses_mbase() {
+        // LD HL,0x1234; LD (HL),0x55; LD A,(0x1234)
+        let mut cpu = ez80(&[], false);
+        cpu.ez80().unwrap().mb = 0xD0;
+        cpu.memory
+            .load_physical(0xD0_0000, &[0x21, 0x34, 0x12, 0x36, 0x55, 0x3A, 0x34, 0x12])
+            .unwrap();
+        run(&mut cpu, 3);
+        assert_eq!(cpu.memory.read_physical(0xD0_1234), 0x55);
+        assert_eq!(cpu.get_a(), 0x55);
+        assert_eq!(cpu.get_pc(), 0x0008);
+    }
+
+    #[test]
+    fn test_adl_loads_and_stack() {
+        // LD HL,0x123456; LD SP,0xD40000; PUSH HL; POP DE
+        let mut cpu = ez80(
+            &[0x21, 0x56, 0x34, 0x12, 0x31, 0x00, 0x00, 0xD4, 0xE5, 0xD1],
+            true,
+        );
+        run(&mut cpu, 3);
+        assert_eq!(cpu.memory.read_physical(0xD3_FFFD), 0x56);
+        assert_eq!(cpu.memory.read_physical(0xD3_FFFF), 0x12);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_de(), 0x3456);
+        let state = cpu.ez80().unwrap();
+        assert_eq!(state.deu, 0x12);
+        assert_eq!(state.spl, 0xD4_0000);
+        assert_eq!(cpu.get_sp(), 0xFFFF);
+    }
+
+    #[test]
+    fn test_adl_call_and_return() {
+        // CALL 0x010000, where RET returns to the next bank-0 instruction
+        let mut cpu = ez80(&[0x31, 0x00, 0x00, 0xD4, 0xCD, 0x00, 0x00, 0x01], true);
+        cpu.memory.load_physical(0x01_0000, &[0xC9]).unwrap();
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_pc(), 0x0000);
+        assert_eq!(cpu.ez80().unwrap().pcu, 0x01);
+        assert_eq!(cpu.ez80().unwrap().spl, 0xD3_FFFD);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_pc(), 0x0008);
+        assert_eq!(cpu.ez80().unwrap().pcu, 0x00);
+    }
+
+    #[test]
+    fn test_suffixes_switch_modes() {
+        // JP.LIL 0x020000 from Z80 mode
+        let mut cpu = ez80(&[0x5B, 0xC3, 0x00, 0x00, 0x02], false);
+        // LD HL,0x1234 with .SIS keeps HLU; JP.SIS 0x0100
+        cpu.memory
+            .load_physical(0x02_0000, &[0x40, 0x21, 0x34, 0x12, 0x40, 0xC3, 0x00, 0x01])
+            .unwrap();
+        cpu.ez80().unwrap().hlu = 0x77;
+        run(&mut cpu, 1);
+        assert!(cpu.ez80().unwrap().adl);
+        let pc = cpu.get_pc();
+        assert_eq!(cpu.ez80().unwrap().code_address(pc), 0x02_0000);
+
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_hl(), 0x1234);
+        let state = cpu.ez80().unwrap();
+        assert_eq!(state.hlu, 0x77);
+        assert!(!state.adl);
+        assert_eq!(cpu.get_pc(), 0x0100);
+    }
+
+    #[test]
+    fn test_lea_and_pea() {
+        // LD SP,0xD40000; LD IX,0x0FFFF0; LEA HL,IX+0x20; PEA IX-0x10
+        let mut cpu = ez80(
+            &[
+                0x31, 0x00, 0x00, 0xD4, 0xDD, 0x21, 0xF0, 0xFF, 0x0F, 0xED, 0x22, 0x20, 0xED, 0x65,
+                0xF0,
+            ],
+            true,
+        );
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_hl(), 0x0010);
+        assert_eq!(cpu.ez80().unwrap().hlu, 0x10);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.memory.read_physical(0xD3_FFFD), 0xE0);
+        assert_eq!(cpu.memory.read_physical(0xD3_FFFE), 0xFF);
+        assert_eq!(cpu.memory.read_physical(0xD3_FFFF), 0x0F);
+    }
+
+    #[test]
+    fn test_ldir_crosses_banks() {
+        // LD HL,0x00FFFF; LD DE,0xD00000; LD BC,2; LDIR
+        let mut cpu = ez80(
+            &[
+                0x21, 0xFF, 0xFF, 0x00, 0x11, 0x00, 0x00, 0xD0, 0x01, 0x02, 0x00, 0x00, 0xED, 0xB0,
+            ],
+            true,
+        );
+        cpu.memory.load_physical(0x00_FFFF, &[0xAA, 0xBB]).unwrap();
+        run(&mut cpu, 5);
+        assert_eq!(cpu.memory.read_physical(0xD0_0000), 0xAA);
+        assert_eq!(cpu.memory.read_physical(0xD0_0001), 0xBB);
+        assert_eq!(cpu.get_hl(), 0x0001);
+        assert_eq!(cpu.ez80().unwrap().hlu, 0x01);
+        assert_eq!(cpu.get_bc(), 0);
+        assert_eq!(cpu.get_pc(), 0x000E);
+    }
+
+    #[test]
+    fn test_adl_code_runs_across_banks() {
+        // JP 0x01FFFD; INC A; LD BC,0x123456 straddling 0x020000; INC A;
+        // JR back to 0x01FFFD
+        for cached in [false, true] {
+            let mut cpu = ez80(&[0xC3, 0xFD, 0xFF, 0x01], true);
+            cpu.memory
+                .load_physical(0x01_FFFD, &[0x3C, 0x01, 0x56, 0x34, 0x12, 0x3C, 0x18, 0xF8])
+                .unwrap();
+            cpu.set_block_cache(cached);
+            run(&mut cpu, 3);
+            assert_eq!(cpu.get_bc(), 0x3456);
+            assert_eq!(cpu.ez80().unwrap().bcu, 0x12);
+            assert_eq!(cpu.get_pc(), 0x0002);
+            assert_eq!(cpu.ez80().unwrap().pcu, 0x02);
+            run(&mut cpu, 3);
+            assert_eq!(cpu.get_a(), 3);
+            assert_eq!(cpu.get_pc(), 0xFFFE);
+            assert_eq!(cpu.ez80().unwrap().pcu, 0x01);
+        }
+    }
+
+    #[test]
+    fn test_suffixed_ldir_repeats_with_suffix() {
+        // LDIR.LIL from Z80 mode copies with 24-bit HL, DE and BC on
+        // every pass
+        let mut cpu = ez80(&[0x5B, 0xED, 0xB0], false);
+        cpu.memory.load_physical(0x01_FFFF, &[0xAA, 0xBB]).unwrap();
+        cpu.set_hl(0xFFFF);
+        cpu.set_de(0x0000);
+        cpu.set_bc(0x0002);
+        let state = cpu.ez80().unwrap();
+        state.hlu = 0x01;
+        state.deu = 0xD0;
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_pc(), 0x0000);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.memory.read_physical(0xD0_0000), 0xAA);
+        assert_eq!(cpu.memory.read_physical(0xD0_0001), 0xBB);
+        assert_eq!(cpu.get_hl(), 0x0001);
+        assert_eq!(cpu.ez80().unwrap().hlu, 0x02);
+        assert_eq!(cpu.get_bc(), 0);
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert!(!cpu.ez80().unwrap().adl);
+    }
+
+    #[test]
+    fn test_indexed_access_carries_into_upper_byte() {
+        // LD IX,0x01FFFF; LD A,(IX+1); LD (HL),A with HL = 0xD00000
+        let mut cpu = ez80(
+            &[
+                0xDD, 0x21, 0xFF, 0xFF, 0x01, 0xDD, 0x7E, 0x01, 0x21, 0x00, 0x00, 0xD0, 0x77,
+            ],
+            true,
+        );
+        cpu.memory.load_physical(0x02_0000, &[0x99]).unwrap();
+        run(&mut cpu, 4);
+        assert_eq!(cpu.get_a(), 0x99);
+        assert_eq!(cpu.memory.read_physical(0xD0_0000), 0x99);
+    }
+
+    #[test]
+    fn test_mbase_and_z180_opcodes() {
+        // LD A,0xD0; LD MB,A; LD BC,0x0C0A; MLT BC; LD A,MB
+        let mut cpu = ez80(
+            &[
+                0x3E, 0xD0, 0xED, 0x6D, 0x01, 0x0A, 0x0C, 0x00, 0xED, 0x4C, 0xED, 0x6E,
+            ],
+            true,
+        );
+        run(&mut cpu, 5);
+        assert_eq!(cpu.ez80().unwrap().mb, 0xD0);
+        assert_eq!(cpu.get_bc(), 0x0078);
+        assert_eq!(cpu.get_a(), 0xD0);
+    }
+
+    #[test]
+    fn test_retn_mirrors_pop_long() {
+        // STMIX; RETN mirror ED 5D from a 24-bit return address
+        let mut cpu = ez80(&[0xED, 0x7D, 0xED, 0x5D], true);
+        cpu.memory
+            .load_physical(0xD3_FFFD, &[0x34, 0x12, 0x02])
+            .unwrap();
+        cpu.ez80().unwrap().spl = 0xD3_FFFD;
+        run(&mut cpu, 2);
+        assert!(cpu.ez80().unwrap().madl);
+        assert_eq!(cpu.get_pc(), 0x1234);
+        assert_eq!(cpu.ez80().unwrap().pcu, 0x02);
+        assert_eq!(cpu.ez80().unwrap().spl, 0xD4_0000);
+    }
+
+    #[test]
+    fn test_adl_sbc_flags() {
+        // LD HL,0x000000; LD DE,0x000001; OR A; SBC HL,DE
+        let mut cpu = ez80(
+            &[
+                0x21, 0x00, 0x00, 0x00, 0x11, 0x01, 0x00, 0x00, 0xB7, 0xED, 0x52,
+            ],
+            true,
+        );
+        run(&mut cpu, 4);
+        assert_eq!(cpu.get_hl(), 0xFFFF);
+        assert_eq!(cpu.ez80().unwrap().hlu, 0xFF);
+        assert!(cpu.flags.carry && cpu.flags.sign && !cpu.flags.zero);
+    }
+}
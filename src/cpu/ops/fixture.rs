@@ -0,0 +1,34 @@
+//! Test fixtures shared by the instruction tests of every model
+
+use crate::cpu::{Cpu, CpuModel};
+use crate::io::IoDevice;
+use crate::memory::Memory;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Records port writes and answers reads with the low byte of the port
+pub(crate) struct Recorder(pub(crate) Rc<RefCell<Vec<(u16, u8)>>>);
+
+impl IoDevice for Recorder {
+    fn read_port(&mut self, port: u16) -> u8 {
+        port as u8
+    }
+
+    fn write_port(&mut self, port: u16, value: u8) {
+        self.0.borrow_mut().push((port, value));
+    }
+}
+
+/// Creates a `model` CPU with `program` loaded at address 0
+pub(crate) fn cpu_with(model: CpuModel, program: &[u8]) -> Cpu {
+    let mut cpu = Cpu::new(Memory::default(), model);
+    cpu.load_program(0, program).unwrap();
+    cpu
+}
+
+/// Executes `steps` instructions
+pub(crate) fn run(cpu: &mut Cpu, steps: usize) {
+    for _ in 0..steps {
+        cpu.step().unwrap();
+    }
+}
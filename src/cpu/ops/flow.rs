@@ -0,0 +1,296 @@
+//! Jump, call, return and restart instructions.
+//!
+//! Conditional instructions are registered with their not-taken timing and
+//! add the extra T-states themselves when the branch is taken.
+
+use crate::cpu::{Cpu, CpuModel};
+use crate::Result;
+
+/// Extra T-states a conditional branch takes when the branch is taken
+pub(crate) struct TakenTiming {
+    pub(crate) jr: u32,
+    pub(crate) jp: u32,
+    pub(crate) call: u32,
+    pub(crate) ret: u32,
+}
+
+/// Z80: JR 12 vs 7 (DJNZ 13 vs 8), JP 10 either way, CALL 17 vs 10, RET 11
+/// vs 5
+const Z80_TAKEN: TakenTiming = TakenTiming {
+    jr: 5,
+    jp: 0,
+    call: 7,
+    ret: 6,
+};
+/// 8080: CALL 17 vs 11, the rest as the Z80
+const I8080_TAKEN: TakenTiming = TakenTiming {
+    jr: 0,
+    jp: 0,
+    call: 6,
+    ret: 6,
+};
+/// LR35902: JR 12 vs 8, JP 16 vs 12, CALL 24 vs 12, RET 20 vs 8
+const LR35902_TAKEN: TakenTiming = TakenTiming {
+    jr: 4,
+    jp: 4,
+    call: 12,
+    ret: 12,
+};
+/// R800: JR 3 vs 2, JP 3 either way, CALL 5 vs 3, RET 3 vs 1
+const R800_TAKEN: TakenTiming = TakenTiming {
+    jr: 1,
+    jp: 0,
+    call: 2,
+    ret: 2,
+};
+
+impl Cpu {
+    /// Returns the extra T-states of taken conditional branches on this part
+    pub(crate) fn taken_timing(&self) -> &'static TakenTiming {
+        match self.model {
+            CpuModel::I8080 => &I8080_TAKEN,
+            CpuModel::Lr35902 => &LR35902_TAKEN,
+            CpuModel::R800 => &R800_TAKEN,
+            _ => &Z80_TAKEN,
+        }
+    }
+
+    /// Takes a relative jump with displacement `offset` from the next instruction
+    fn jump_relative(&mut self, offset: u8) {
+        self.advance_pc(offset as i8 as u16);
+        self.wz = self.pc;
+    }
+
+    /// Pushes the address of the next instruction and jumps to `address`
+    pub(crate) fn call(&mut self, address: u16) -> Result<()> {
+        self.push_word(self.pc)?;
+        self.wz = address;
+        self.pc = address;
+        Ok(())
+    }
+}
+
+/// JP nn
+pub fn jp_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address;
+    cpu.pc = address;
+    Ok(())
+}
+
+/// JP cc, nn. MEMPTR takes the target even when the jump is not taken.
+pub fn jp_cc_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address;
+    if cpu.condition(cpu.opcode >> 3) {
+        cpu.pc = address;
+        cpu.t_states += cpu.taken_timing().jp;
+    }
+    Ok(())
+}
+
+/// JP (HL) (also JP (IX) and JP (IY))
+pub fn jp_hl(cpu: &mut Cpu) -> Result<()> {
+    cpu.pc = cpu.index_register();
+    Ok(())
+}
+
+/// JR e
+pub fn jr(cpu: &mut Cpu) -> Result<()> {
+    let offset = cpu.fetch_byte()?;
+    cpu.jump_relative(offset);
+    Ok(())
+}
+
+/// JR cc, e (NZ, Z, NC, C only)
+pub fn jr_cc(cpu: &mut Cpu) -> Result<()> {
+    let offset = cpu.fetch_byte()?;
+    if cpu.condition((cpu.opcode >> 3) & 0x03) {
+        cpu.jump_relative(offset);
+        cpu.t_states += cpu.taken_timing().jr;
+    }
+    Ok(())
+}
+
+/// DJNZ e
+pub fn djnz(cpu: &mut Cpu) -> Result<()> {
+    let offset = cpu.fetch_byte()?;
+    cpu.b = cpu.b.wrapping_sub(1);
+    if cpu.b != 0 {
+        cpu.jump_relative(offset);
+        cpu.t_states += cpu.taken_timing().jr;
+    }
+    Ok(())
+}
+
+/// CALL nn
+pub fn call_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.call(address)
+}
+
+/// CALL cc, nn. MEMPTR takes the target even when the call is not taken.
+pub fn call_cc_nn(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address;
+    if cpu.condition(cpu.opcode >> 3) {
+        cpu.call(address)?;
+        cpu.t_states += cpu.taken_timing().call;
+    }
+    Ok(())
+}
+
+/// RET
+pub fn ret(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.pop_word()?;
+    cpu.wz = address;
+    cpu.pc = address;
+    Ok(())
+}
+
+/// RET cc
+pub fn ret_cc(cpu: &mut Cpu) -> Result<()> {
+    if cpu.condition(cpu.opcode >> 3) {
+        ret(cpu)?;
+        cpu.t_states += cpu.taken_timing().ret;
+    }
+    Ok(())
+}
+
+/// RETN and RETI, which also restore IFF1 from IFF2
+pub fn retn(cpu: &mut Cpu) -> Result<()> {
+    cpu.iff1 = cpu.iff2;
+    ret(cpu)
+}
+
+/// RST p
+pub fn rst(cpu: &mut Cpu) -> Result<()> {
+    cpu.call(u16::from(cpu.opcode & 0x38))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+
+    fn load(program: &[u8]) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, program).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_jp_and_jr() {
+        let mut cpu = load(&[0xC3, 0x00, 0x10]); // JP 0x1000
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x1000);
+
+        let mut cpu = load(&[0x18, 0xFE]); // JR -2
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0000);
+        assert_eq!(cpu.get_t_states(), 12);
+    }
+
+    #[test]
+    fn test_conditional_relative_jump_timing() {
+        let mut cpu = load(&[0x20, 0x10]); // JR NZ,+0x10 with Z clear
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0012);
+        assert_eq!(cpu.get_t_states(), 12);
+
+        let mut cpu = load(&[0xAF, 0x20, 0x10]); // XOR A; JR NZ,+0x10
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert_eq!(cpu.get_t_states(), 4 + 7);
+    }
+
+    #[test]
+    fn test_djnz_loop() {
+        // LD B,3; loop: DJNZ loop
+        let mut cpu = load(&[0x06, 0x03, 0x10, 0xFE]);
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.b, 0);
+        assert_eq!(cpu.get_pc(), 0x0004);
+        assert_eq!(cpu.get_t_states(), 7 + 13 + 13 + 8);
+    }
+
+    #[test]
+    fn test_call_and_ret() {
+        let mut cpu = load(&[0xCD, 0x00, 0x10]); // CALL 0x1000
+        cpu.load_program(0x1000, &[0xC9]).unwrap(); // RET
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x1000);
+        assert_eq!(cpu.sp, 0xFFFD);
+        assert_eq!(cpu.get_t_states(), 17);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert_eq!(cpu.sp, 0xFFFF);
+        assert_eq!(cpu.get_t_states(), 27);
+    }
+
+    #[test]
+    fn test_conditional_call_and_return() {
+        // SCF; CALL NC,0x1000; CALL C,0x1000
+        let mut cpu = load(&[0x37, 0xD4, 0x00, 0x10, 0xDC, 0x00, 0x10]);
+        cpu.load_program(0x1000, &[0xD0, 0xD8]).unwrap(); // RET NC; RET C
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0004);
+        assert_eq!(cpu.get_t_states(), 4 + 10);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x1000);
+        assert_eq!(cpu.get_t_states(), 4 + 10 + 17);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0007);
+        assert_eq!(cpu.get_t_states(), 4 + 10 + 17 + 5 + 11);
+    }
+
+    #[test]
+    fn test_rst() {
+        let mut cpu = load(&[0x00, 0xFF]); // NOP; RST 38H
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0038);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0002);
+    }
+
+    #[test]
+    fn test_jp_hl() {
+        let mut cpu = load(&[0x21, 0x34, 0x12, 0xE9]); // LD HL,0x1234; JP (HL)
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x1234);
+    }
+
+    #[test]
+    fn test_retn_restores_iff1() {
+        let mut cpu = load(&[0xCD, 0x00, 0x10]); // CALL 0x1000
+        cpu.load_program(0x1000, &[0xED, 0x45]).unwrap(); // RETN
+        cpu.iff2 = true;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert!(cpu.iff1);
+        assert_eq!(cpu.get_t_states(), 17 + 14);
+    }
+
+    #[test]
+    fn test_memptr_after_branches() {
+        // JP Z,0x1234 is not taken but still loads MEMPTR
+        let mut cpu = load(&[0xCA, 0x34, 0x12]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0003);
+        assert_eq!(cpu.get_memptr(), 0x1234);
+
+        // JR +2 from 0x0000
+        let mut cpu = load(&[0x18, 0x02]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_memptr(), 0x0004);
+    }
+}
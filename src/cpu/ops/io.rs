@@ -0,0 +1,112 @@
+//! Port input and output instructions.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+/// IN A, (n)
+pub fn in_a_n(cpu: &mut Cpu) -> Result<()> {
+    let port = u16::from_le_bytes([cpu.fetch_byte()?, cpu.a]);
+    cpu.wz = port.wrapping_add(1);
+    cpu.a = cpu.port_in(port);
+    Ok(())
+}
+
+/// OUT (n), A
+pub fn out_n_a(cpu: &mut Cpu) -> Result<()> {
+    let port = u16::from_le_bytes([cpu.fetch_byte()?, cpu.a]);
+    cpu.store_a_memptr(port);
+    cpu.port_out(port, cpu.a);
+    Ok(())
+}
+
+/// IN r, (C). The (HL) encoding, IN (C), only sets the flags.
+pub fn in_r_c(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.port_in(cpu.get_bc());
+    cpu.wz = cpu.get_bc().wrapping_add(1);
+    cpu.update_szp_flags(value);
+    cpu.flags.half_carry = false;
+    cpu.flags.add_subtract = false;
+    let index = cpu.opcode >> 3;
+    if index & 0x07 != 6 {
+        cpu.set_main_reg8(index, value);
+    }
+    Ok(())
+}
+
+/// OUT (C), r. The (HL) encoding outputs zero on NMOS parts and 0xFF on
+/// CMOS parts.
+pub fn out_c_r(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 3;
+    let value = match index & 0x07 {
+        6 => cpu.model.out_c_zero_value(),
+        _ => cpu.main_reg8(index),
+    };
+    cpu.port_out(cpu.get_bc(), value);
+    cpu.wz = cpu.get_bc().wrapping_add(1);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::ops::fixture::Recorder;
+    use crate::cpu::{Cpu, CpuModel};
+    use crate::memory::Memory;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_in_and_out() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = Cpu::default();
+        cpu.set_io_device(Box::new(Recorder(writes.clone())));
+
+        // LD A,0x12; OUT (0xFE),A; IN A,(0x34)
+        cpu.load_program(0, &[0x3E, 0x12, 0xD3, 0xFE, 0xDB, 0x34])
+            .unwrap();
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(*writes.borrow(), vec![(0x12FE, 0x12)]);
+        assert_eq!(cpu.a, 0x34);
+        assert_eq!(cpu.get_t_states(), 7 + 11 + 11);
+    }
+
+    #[test]
+    fn test_out_c_zero_on_cmos() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = Cpu::new(Memory::default(), CpuModel::ZilogCmos);
+        cpu.set_io_device(Box::new(Recorder(writes.clone())));
+        cpu.load_program(0, &[0xED, 0x71]).unwrap(); // OUT (C),0
+        cpu.step().unwrap();
+        assert_eq!(*writes.borrow(), vec![(0x0000, 0xFF)]);
+    }
+
+    #[test]
+    fn test_in_from_open_bus() {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, &[0xDB, 0x00]).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.a, 0xFF);
+    }
+
+    #[test]
+    fn test_in_and_out_via_c() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = Cpu::default();
+        cpu.set_io_device(Box::new(Recorder(writes.clone())));
+
+        // LD BC,0x1280; IN D,(C); OUT (C),D; OUT (C),0
+        let program = [0x01, 0x80, 0x12, 0xED, 0x50, 0xED, 0x51, 0xED, 0x71];
+        cpu.load_program(0, &program).unwrap();
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(cpu.d, 0x80);
+        assert!(cpu.flags.sign);
+        assert!(!cpu.flags.parity);
+        assert_eq!(*writes.borrow(), vec![(0x1280, 0x80), (0x1280, 0x00)]);
+        assert_eq!(cpu.get_t_states(), 10 + 12 + 12 + 12);
+    }
+}
@@ -0,0 +1,375 @@
+//! Load, stack and exchange instructions.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+impl Cpu {
+    /// Sets MEMPTR after storing A at `address`: A in the high byte and the
+    /// low byte of the next address in the low byte
+    pub(crate) fn store_a_memptr(&mut self, address: u16) {
+        self.wz = u16::from_le_bytes([address.wrapping_add(1) as u8, self.a]);
+    }
+
+    /// Sets flags after LD A,I or LD A,R, copying IFF2 into P/V
+    fn update_special_load_flags(&mut self) {
+        self.update_sz_flags(self.a);
+        self.flags.half_carry = false;
+        self.flags.add_subtract = false;
+        self.flags.parity = self.iff2;
+        self.iff2_read = true;
+    }
+}
+
+/// LD r, r'. When one side is (IX+d) or (IY+d) the other side is the plain
+/// H or L rather than an index register half.
+pub fn ld_r_r(cpu: &mut Cpu) -> Result<()> {
+    let (dst, src) = ((cpu.opcode >> 3) & 0x07, cpu.opcode & 0x07);
+    if src == 6 {
+        let value = cpu.reg8(src)?;
+        cpu.set_main_reg8(dst, value);
+        Ok(())
+    } else if dst == 6 {
+        cpu.set_reg8(dst, cpu.main_reg8(src))
+    } else {
+        let value = cpu.reg8(src)?;
+        cpu.set_reg8(dst, value)
+    }
+}
+
+/// LD r, n. For LD (IX+d), n the immediate follows the displacement.
+pub fn ld_r_n(cpu: &mut Cpu) -> Result<()> {
+    let index = cpu.opcode >> 3;
+    if index & 0x07 == 6 {
+        // Fetches the displacement ahead of the immediate
+        cpu.hl_address()?;
+    }
+    let value = cpu.fetch_byte()?;
+    cpu.set_reg8(index, value)
+}
+
+/// LD rr, nn
+pub fn ld_rr_nn(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.fetch_word()?;
+    cpu.set_reg16(cpu.opcode >> 4, value);
+    Ok(())
+}
+
+/// LD (BC), A and LD (DE), A
+pub fn ld_rr_ind_a(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.reg16(cpu.opcode >> 4);
+    cpu.store_a_memptr(address);
+    cpu.memory.write_byte(address, cpu.a)
+}
+
+/// LD A, (BC) and LD A, (DE)
+pub fn ld_a_rr_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.reg16(cpu.opcode >> 4);
+    cpu.wz = address.wrapping_add(1);
+    cpu.a = cpu.memory.read_byte(address)?;
+    Ok(())
+}
+
+/// LD (nn), A
+pub fn ld_nn_ind_a(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.store_a_memptr(address);
+    cpu.memory.write_byte(address, cpu.a)
+}
+
+/// LD A, (nn)
+pub fn ld_a_nn_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address.wrapping_add(1);
+    cpu.a = cpu.memory.read_byte(address)?;
+    Ok(())
+}
+
+/// LD (nn), HL (also IX and IY)
+pub fn ld_nn_ind_hl(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address.wrapping_add(1);
+    cpu.write_word(address, cpu.index_register())
+}
+
+/// LD HL, (nn) (also IX and IY)
+pub fn ld_hl_nn_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address.wrapping_add(1);
+    let value = cpu.read_word(address)?;
+    cpu.set_index_register(value);
+    Ok(())
+}
+
+/// LD (nn), rr
+pub fn ld_nn_ind_rr(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address.wrapping_add(1);
+    cpu.write_word(address, cpu.reg16(cpu.opcode >> 4))
+}
+
+/// LD rr, (nn)
+pub fn ld_rr_nn_ind(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.wz = address.wrapping_add(1);
+    let value = cpu.read_word(address)?;
+    cpu.set_reg16(cpu.opcode >> 4, value);
+    Ok(())
+}
+
+/// LD I, A
+pub fn ld_i_a(cpu: &mut Cpu) -> Result<()> {
+    cpu.i = cpu.a;
+    Ok(())
+}
+
+/// LD R, A
+pub fn ld_r_a(cpu: &mut Cpu) -> Result<()> {
+    cpu.r = cpu.a;
+    Ok(())
+}
+
+/// LD A, I
+pub fn ld_a_i(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = cpu.i;
+    cpu.update_special_load_flags();
+    Ok(())
+}
+
+/// LD A, R
+pub fn ld_a_r(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = cpu.r;
+    cpu.update_special_load_flags();
+    Ok(())
+}
+
+/// LD SP, HL (also IX and IY)
+pub fn ld_sp_hl(cpu: &mut Cpu) -> Result<()> {
+    cpu.sp = cpu.index_register();
+    Ok(())
+}
+
+/// PUSH qq (BC, DE, HL, AF)
+pub fn push_qq(cpu: &mut Cpu) -> Result<()> {
+    let value = match (cpu.opcode >> 4) & 0x03 {
+        3 => cpu.get_af(),
+        index => cpu.reg16(index),
+    };
+    cpu.push_word(value)
+}
+
+/// POP qq (BC, DE, HL, AF)
+pub fn pop_qq(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.pop_word()?;
+    match (cpu.opcode >> 4) & 0x03 {
+        3 => cpu.set_af(value),
+        index => cpu.set_reg16(index, value),
+    }
+    Ok(())
+}
+
+/// EX AF, AF'
+pub fn ex_af_af(cpu: &mut Cpu) -> Result<()> {
+    std::mem::swap(&mut cpu.a, &mut cpu.a_prime);
+    std::mem::swap(&mut cpu.flags, &mut cpu.flags_prime);
+    Ok(())
+}
+
+/// EXX
+pub fn exx(cpu: &mut Cpu) -> Result<()> {
+    std::mem::swap(&mut cpu.b, &mut cpu.b_prime);
+    std::mem::swap(&mut cpu.c, &mut cpu.c_prime);
+    std::mem::swap(&mut cpu.d, &mut cpu.d_prime);
+    std::mem::swap(&mut cpu.e, &mut cpu.e_prime);
+    std::mem::swap(&mut cpu.h, &mut cpu.h_prime);
+    std::mem::swap(&mut cpu.l, &mut cpu.l_prime);
+    Ok(())
+}
+
+/// EX DE, HL
+pub fn ex_de_hl(cpu: &mut Cpu) -> Result<()> {
+    std::mem::swap(&mut cpu.d, &mut cpu.h);
+    std::mem::swap(&mut cpu.e, &mut cpu.l);
+    Ok(())
+}
+
+/// EX (SP), HL (also IX and IY)
+pub fn ex_sp_ind_hl(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.read_word(cpu.sp)?;
+    cpu.write_word(cpu.sp, cpu.index_register())?;
+    cpu.set_index_register(value);
+    cpu.wz = value;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+
+    fn run(program: &[u8], steps: usize) -> Cpu {
+        let mut cpu = Cpu::default();
+        cpu.load_program(0, program).unwrap();
+        for _ in 0..steps {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_register_loads() {
+        let cpu = run(&[0x06, 0x12, 0x48, 0x79], 3); // LD B,0x12; LD C,B; LD A,C
+        assert_eq!(cpu.b, 0x12);
+        assert_eq!(cpu.c, 0x12);
+        assert_eq!(cpu.a, 0x12);
+        assert_eq!(cpu.get_pc(), 4);
+    }
+
+    #[test]
+    fn test_memory_loads() {
+        // LD HL,0x8000; LD (HL),0x55; LD A,(HL); LD (0x9000),A
+        let cpu = run(&[0x21, 0x00, 0x80, 0x36, 0x55, 0x7E, 0x32, 0x00, 0x90], 4);
+        assert_eq!(cpu.a, 0x55);
+        assert_eq!(cpu.memory.read_byte(0x8000).unwrap(), 0x55);
+        assert_eq!(cpu.memory.read_byte(0x9000).unwrap(), 0x55);
+    }
+
+    #[test]
+    fn test_sixteen_bit_memory_loads() {
+        // LD HL,0x1234; LD (0x8000),HL; LD HL,0; LD HL,(0x8000)
+        let program = [
+            0x21, 0x34, 0x12, 0x22, 0x00, 0x80, 0x21, 0x00, 0x00, 0x2A, 0x00, 0x80,
+        ];
+        let cpu = run(&program, 4);
+        assert_eq!(cpu.get_hl(), 0x1234);
+        assert_eq!(cpu.memory.read_byte(0x8000).unwrap(), 0x34);
+        assert_eq!(cpu.memory.read_byte(0x8001).unwrap(), 0x12);
+    }
+
+    #[test]
+    fn test_push_pop() {
+        // LD BC,0xBEEF; PUSH BC; POP DE
+        let cpu = run(&[0x01, 0xEF, 0xBE, 0xC5, 0xD1], 3);
+        assert_eq!(cpu.get_de(), 0xBEEF);
+        assert_eq!(cpu.sp, 0xFFFF);
+    }
+
+    #[test]
+    fn test_push_pop_af() {
+        // LD A,0x12; SCF; PUSH AF; POP BC
+        let cpu = run(&[0x3E, 0x12, 0x37, 0xF5, 0xC1], 4);
+        assert_eq!(cpu.b, 0x12);
+        assert_eq!(cpu.c & 0x01, 0x01);
+    }
+
+    #[test]
+    fn test_exchanges() {
+        // LD DE,0x1111; LD HL,0x2222; EX DE,HL; EXX
+        let cpu = run(&[0x11, 0x11, 0x11, 0x21, 0x22, 0x22, 0xEB, 0xD9], 4);
+        assert_eq!(cpu.h_prime, 0x11);
+        assert_eq!(cpu.d_prime, 0x22);
+        assert_eq!(cpu.get_hl(), 0x0000);
+    }
+
+    #[test]
+    fn test_ex_sp_hl() {
+        // LD SP,0x8000; LD HL,0x1234; PUSH HL; LD HL,0x5678; EX (SP),HL
+        let program = [
+            0x31, 0x00, 0x80, 0x21, 0x34, 0x12, 0xE5, 0x21, 0x78, 0x56, 0xE3,
+        ];
+        let cpu = run(&program, 5);
+        assert_eq!(cpu.get_hl(), 0x1234);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x5678);
+    }
+
+    #[test]
+    fn test_index_loads() {
+        // LD IX,0x8000; LD (IX+2),0x55; LD B,(IX+2); LD (IX-1),B
+        let program = [
+            0xDD, 0x21, 0x00, 0x80, 0xDD, 0x36, 0x02, 0x55, 0xDD, 0x46, 0x02, 0xDD, 0x70, 0xFF,
+        ];
+        let cpu = run(&program, 4);
+        assert_eq!(cpu.ix, 0x8000);
+        assert_eq!(cpu.b, 0x55);
+        assert_eq!(cpu.memory.read_byte(0x7FFF).unwrap(), 0x55);
+        assert_eq!(cpu.get_pc(), 14);
+        assert_eq!(cpu.get_t_states(), 14 + 19 + 19 + 19);
+    }
+
+    #[test]
+    fn test_index_register_halves() {
+        // LD IY,0x1234; LD H,0x99; LD IYL,IYH; LD H,(IY+0) with (0x1212)=0x77
+        let mut cpu = Cpu::default();
+        cpu.memory.write_byte(0x1212, 0x77).unwrap();
+        let program = [
+            0xFD, 0x21, 0x34, 0x12, 0x26, 0x99, 0xFD, 0x6C, 0xFD, 0x66, 0x00,
+        ];
+        cpu.load_program(0, &program).unwrap();
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.iy, 0x1212);
+        assert_eq!(cpu.h, 0x77);
+    }
+
+    #[test]
+    fn test_index_prefix_without_hl() {
+        // DD NOP costs 8 T-states; DD EX DE,HL still exchanges HL
+        let cpu = run(&[0xDD, 0x00], 1);
+        assert_eq!(cpu.get_pc(), 2);
+        assert_eq!(cpu.get_t_states(), 8);
+
+        let cpu = run(&[0x21, 0x34, 0x12, 0xDD, 0xEB], 2);
+        assert_eq!(cpu.get_de(), 0x1234);
+        assert_eq!(cpu.ix, 0x0000);
+
+        // DD FD LD IY,nn: only the last index prefix applies
+        let cpu = run(&[0xDD, 0xFD, 0x21, 0x34, 0x12], 1);
+        assert_eq!(cpu.iy, 0x1234);
+        assert_eq!(cpu.ix, 0x0000);
+        assert_eq!(cpu.get_t_states(), 4 + 14);
+    }
+
+    #[test]
+    fn test_index_stack_operations() {
+        // LD SP,0x8000; LD IX,0xBEEF; PUSH IX; POP IY; LD SP,IY
+        let program = [
+            0x31, 0x00, 0x80, 0xDD, 0x21, 0xEF, 0xBE, 0xDD, 0xE5, 0xFD, 0xE1, 0xFD, 0xF9,
+        ];
+        let cpu = run(&program, 5);
+        assert_eq!(cpu.iy, 0xBEEF);
+        assert_eq!(cpu.sp, 0xBEEF);
+    }
+
+    #[test]
+    fn test_ed_sixteen_bit_memory_loads() {
+        // LD BC,0x1234; LD (0x8000),BC; LD SP,(0x8000)
+        let program = [
+            0x01, 0x34, 0x12, 0xED, 0x43, 0x00, 0x80, 0xED, 0x7B, 0x00, 0x80,
+        ];
+        let cpu = run(&program, 3);
+        assert_eq!(cpu.sp, 0x1234);
+        assert_eq!(cpu.get_pc(), 11);
+        assert_eq!(cpu.get_t_states(), 10 + 20 + 20);
+    }
+
+    #[test]
+    fn test_interrupt_register_loads() {
+        // EI; LD A,0x80; LD I,A; XOR A; LD A,I
+        let cpu = run(&[0xFB, 0x3E, 0x80, 0xED, 0x47, 0xAF, 0xED, 0x57], 5);
+        assert_eq!(cpu.i, 0x80);
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.flags.sign);
+        assert!(cpu.flags.parity);
+    }
+
+    #[test]
+    fn test_memptr_after_accumulator_loads() {
+        let cpu = run(&[0x3A, 0xFF, 0x12], 1); // LD A,(0x12FF)
+        assert_eq!(cpu.get_memptr(), 0x1300);
+
+        let cpu = run(&[0x3E, 0xAB, 0x32, 0xFF, 0x12], 2); // LD A,0xAB; LD (0x12FF),A
+        assert_eq!(cpu.get_memptr(), 0xAB00);
+
+        let cpu = run(&[0xED, 0x4B, 0x34, 0x12], 1); // LD BC,(0x1234)
+        assert_eq!(cpu.get_memptr(), 0x1235);
+    }
+}
@@ -0,0 +1,241 @@
+//! Instructions of the Sharp LR35902 (Game Boy CPU) that take the place of
+//! Z80 opcodes: the high-page and auto-incrementing loads, stack pointer
+//! arithmetic, SWAP, STOP and RETI.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+/// Base of the high page addressed by LDH and LD (C)
+const HIGH_PAGE: u16 = 0xFF00;
+
+impl Cpu {
+    /// Adds a signed displacement to SP, setting H and C from the unsigned
+    /// addition of the low bytes and clearing Z and N
+    fn sp_plus_displacement(&mut self) -> Result<u16> {
+        let offset = self.fetch_byte()?;
+        let sp = self.sp;
+        self.flags.zero = false;
+        self.flags.add_subtract = false;
+        self.flags.half_carry = (sp & 0x0F) + u16::from(offset & 0x0F) > 0x0F;
+        self.flags.carry = (sp & 0xFF) + u16::from(offset) > 0xFF;
+        Ok(sp.wrapping_add(offset as i8 as u16))
+    }
+
+    /// Returns HL and steps it: down for the (HL-) forms (bit 4 set) and up
+    /// for the (HL+) forms
+    fn hl_post_step(&mut self) -> u16 {
+        let hl = self.get_hl();
+        let step = if self.opcode & 0x10 != 0 {
+            0xFFFF
+        } else {
+            0x0001
+        };
+        self.set_hl(hl.wrapping_add(step));
+        hl
+    }
+}
+
+/// LD (nn), SP
+pub fn ld_nn_ind_sp(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.fetch_word()?;
+    cpu.write_word(address, cpu.sp)
+}
+
+/// STOP. The second byte is skipped, and the CPU waits for an interrupt as
+/// for HALT.
+pub fn stop(cpu: &mut Cpu) -> Result<()> {
+    cpu.fetch_byte()?;
+    cpu.halted = true;
+    Ok(())
+}
+
+/// LD (HL+), A and LD (HL-), A
+pub fn ld_hli_a(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.hl_post_step();
+    cpu.memory.write_byte(address, cpu.a)
+}
+
+/// LD A, (HL+) and LD A, (HL-)
+pub fn ld_a_hli(cpu: &mut Cpu) -> Result<()> {
+    let address = cpu.hl_post_step();
+    cpu.a = cpu.memory.read_byte(address)?;
+    Ok(())
+}
+
+/// LDH (n), A
+pub fn ldh_n_a(cpu: &mut Cpu) -> Result<()> {
+    let address = HIGH_PAGE | u16::from(cpu.fetch_byte()?);
+    cpu.memory.write_byte(address, cpu.a)
+}
+
+/// LDH A, (n)
+pub fn ldh_a_n(cpu: &mut Cpu) -> Result<()> {
+    let address = HIGH_PAGE | u16::from(cpu.fetch_byte()?);
+    cpu.a = cpu.memory.read_byte(address)?;
+    Ok(())
+}
+
+/// LD (C), A
+pub fn ld_c_ind_a(cpu: &mut Cpu) -> Result<()> {
+    cpu.memory.write_byte(HIGH_PAGE | u16::from(cpu.c), cpu.a)
+}
+
+/// LD A, (C)
+pub fn ld_a_c_ind(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = cpu.memory.read_byte(HIGH_PAGE | u16::from(cpu.c))?;
+    Ok(())
+}
+
+/// ADD SP, e
+pub fn add_sp_e(cpu: &mut Cpu) -> Result<()> {
+    cpu.sp = cpu.sp_plus_displacement()?;
+    Ok(())
+}
+
+/// LD HL, SP+e
+pub fn ld_hl_sp_e(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.sp_plus_displacement()?;
+    cpu.set_hl(value);
+    Ok(())
+}
+
+/// RETI, which enables interrupts as it returns
+pub fn reti(cpu: &mut Cpu) -> Result<()> {
+    cpu.iff1 = true;
+    cpu.iff2 = true;
+    let address = cpu.pop_word()?;
+    cpu.pc = address;
+    Ok(())
+}
+
+/// SWAP r: exchanges the nibbles of a register
+pub fn swap(cpu: &mut Cpu) -> Result<()> {
+    let result = cpu.reg8(cpu.opcode)?.rotate_left(4);
+    cpu.set_reg8(cpu.opcode, result)?;
+    cpu.flags.zero = result == 0;
+    cpu.flags.add_subtract = false;
+    cpu.flags.half_carry = false;
+    cpu.flags.carry = false;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::ops::fixture::{cpu_with, run};
+    use crate::cpu::CpuModel;
+
+    #[test]
+    fn test_auto_increment_loads() {
+        // LD HL,0xC000; LD A,0x11; LD (HL+),A; LD (HL-),A; LD A,(HL-)
+        let mut cpu = cpu_with(
+            CpuModel::Lr35902,
+            &[0x21, 0x00, 0xC0, 0x3E, 0x11, 0x22, 0x32, 0x3A],
+        );
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_hl(), 0xC001);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_hl(), 0xBFFF);
+        assert_eq!(cpu.read_word(0xC000).unwrap(), 0x1111);
+        assert_eq!(cpu.get_t_states(), 12 + 8 + 8 + 8 + 8);
+    }
+
+    #[test]
+    fn test_high_page_loads() {
+        // LD A,0x42; LDH (0x80),A; LD C,0x81; LD (C),A; LD A,0; LDH A,(0x80)
+        let mut cpu = cpu_with(
+            CpuModel::Lr35902,
+            &[
+                0x3E, 0x42, 0xE0, 0x80, 0x0E, 0x81, 0xE2, 0x3E, 0x00, 0xF0, 0x80,
+            ],
+        );
+        run(&mut cpu, 6);
+        assert_eq!(cpu.read_word(0xFF80).unwrap(), 0x4242);
+        assert_eq!(cpu.get_a(), 0x42);
+        assert_eq!(cpu.get_t_states(), 8 + 12 + 8 + 8 + 8 + 12);
+    }
+
+    #[test]
+    fn test_stack_pointer_arithmetic() {
+        // LD SP,0x00FF; ADD SP,1; LD HL,SP-2; LD (0xC000),SP
+        let mut cpu = cpu_with(
+            CpuModel::Lr35902,
+            &[0x31, 0xFF, 0x00, 0xE8, 0x01, 0xF8, 0xFE, 0x08, 0x00, 0xC0],
+        );
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_sp(), 0x0100);
+        assert!(cpu.flags.half_carry && cpu.flags.carry && !cpu.flags.zero);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_hl(), 0x00FE);
+        assert!(!cpu.flags.half_carry && !cpu.flags.carry);
+        assert_eq!(cpu.read_word(0xC000).unwrap(), 0x0100);
+        assert_eq!(cpu.get_t_states(), 12 + 16 + 12 + 20);
+    }
+
+    #[test]
+    fn test_swap_and_flag_register() {
+        // LD A,0xF0; SWAP A; PUSH AF; POP BC
+        let mut cpu = cpu_with(CpuModel::Lr35902, &[0x3E, 0xF0, 0xCB, 0x37, 0xF5, 0xC1]);
+        cpu.sp = 0xFFFE;
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_a(), 0x0F);
+        assert_eq!(cpu.get_t_states(), 8 + 8);
+
+        // SCF leaves only C set, in bit 4
+        cpu.load_program(0x0004, &[0x37, 0xF5, 0xC1]).unwrap();
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_bc(), 0x0F10);
+
+        // POP AF drops the low nibble
+        cpu.load_program(0x0007, &[0x01, 0xFF, 0x12, 0xC5, 0xF1])
+            .unwrap();
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_af(), 0x12F0);
+    }
+
+    #[test]
+    fn test_z80_opcodes_removed() {
+        let cpu = cpu_with(CpuModel::Lr35902, &[0xDD, 0x21, 0x10, 0x00, 0xD9]);
+        assert!(cpu.decode_at(0).is_err());
+        assert_eq!(cpu.decode_at(4).unwrap().instruction.mnemonic, "RETI");
+        // 0x10 is STOP, two bytes long
+        assert_eq!(cpu.decode_at(2).unwrap().instruction.length, 2);
+    }
+
+    #[test]
+    fn test_conditional_timing() {
+        // XOR A; JP NZ,nn (not taken); JR Z,+0 (taken); CALL Z,0x0100
+        let mut cpu = cpu_with(
+            CpuModel::Lr35902,
+            &[0xAF, 0xC2, 0x00, 0x01, 0x28, 0x00, 0xCC, 0x00, 0x01],
+        );
+        cpu.sp = 0xFFFE;
+        cpu.load_program(0x0100, &[0xC8]).unwrap(); // RET Z
+        run(&mut cpu, 5);
+        assert_eq!(cpu.get_pc(), 0x0009);
+        assert_eq!(cpu.get_t_states(), 4 + 12 + 12 + 24 + 20);
+    }
+
+    #[test]
+    fn test_rotate_and_daa_flags() {
+        // XOR A (Z set); RLCA clears Z on the LR35902
+        let mut cpu = cpu_with(CpuModel::Lr35902, &[0xAF, 0x07]);
+        run(&mut cpu, 2);
+        assert!(!cpu.flags.zero);
+
+        // LD A,0x10; SUB 0x01; DAA gives 0x09 with H cleared
+        let mut cpu = cpu_with(CpuModel::Lr35902, &[0x3E, 0x10, 0xD6, 0x01, 0x27]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_a(), 0x09);
+        assert!(!cpu.flags.half_carry && cpu.flags.add_subtract);
+    }
+
+    #[test]
+    fn test_reti_enables_interrupts() {
+        let mut cpu = cpu_with(CpuModel::Lr35902, &[0xD9]);
+        cpu.sp = 0xFFFE;
+        cpu.push_word(0x1234).unwrap();
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_pc(), 0x1234);
+        assert!(cpu.get_iff1());
+    }
+}
@@ -0,0 +1,263 @@
+//! Execution functions for Z80 instructions, grouped by instruction type.
+//!
+//! Handlers decode register and condition fields from the opcode held in
+//! `Cpu::opcode`, following the standard x/y/z/p/q opcode layout, so that a
+//! single function serves a whole row or column of the opcode table.
+//!
+//! When a handler runs, PC already points past the opcode. Handlers fetch
+//! their immediates and displacements with `fetch_byte`/`fetch_word`, which
+//! advance PC, and branch by assigning PC.
+
+pub mod alu;
+pub mod bit;
+pub mod block;
+pub mod control;
+pub mod ez80;
+#[cfg(test)]
+pub(crate) mod fixture;
+pub mod flow;
+pub mod io;
+pub mod load;
+pub mod lr35902;
+pub mod r800;
+pub mod z180;
+pub mod z80n;
+
+use super::{Cpu, IndexMode};
+use crate::Result;
+
+/// T-states of a memory read cycle
+const MEMORY_READ_T_STATES: u32 = 3;
+/// R800 cycles of a memory read
+const R800_MEMORY_READ_T_STATES: u32 = 1;
+
+impl Cpu {
+    /// Reads an 8-bit register by its opcode encoding (B, C, D, E, H, L, (HL), A).
+    /// Under a DD or FD prefix H, L and (HL) become IXH, IXL and (IX+d).
+    pub(crate) fn reg8(&mut self, index: u8) -> Result<u8> {
+        match (index & 0x07, self.index_mode) {
+            (6, _) => {
+                let address = self.hl_address()?;
+                self.memory.read_byte(address)
+            }
+            (4, IndexMode::Ix) => Ok((self.ix >> 8) as u8),
+            (5, IndexMode::Ix) => Ok(self.ix as u8),
+            (4, IndexMode::Iy) => Ok((self.iy >> 8) as u8),
+            (5, IndexMode::Iy) => Ok(self.iy as u8),
+            _ => Ok(self.main_reg8(index)),
+        }
+    }
+
+    /// Writes an 8-bit register by its opcode encoding (B, C, D, E, H, L, (HL), A).
+    /// Under a DD or FD prefix H, L and (HL) become IXH, IXL and (IX+d).
+    pub(crate) fn set_reg8(&mut self, index: u8, value: u8) -> Result<()> {
+        match (index & 0x07, self.index_mode) {
+            (6, _) => {
+                let address = self.hl_address()?;
+                self.memory.write_byte(address, value)?
+            }
+            (4, IndexMode::Ix) => self.ix = (self.ix & 0x00FF) | (u16::from(value) << 8),
+            (5, IndexMode::Ix) => self.ix = (self.ix & 0xFF00) | u16::from(value),
+            (4, IndexMode::Iy) => self.iy = (self.iy & 0x00FF) | (u16::from(value) << 8),
+            (5, IndexMode::Iy) => self.iy = (self.iy & 0xFF00) | u16::from(value),
+            _ => self.set_main_reg8(index, value),
+        }
+        Ok(())
+    }
+
+    /// Returns the address an (HL) operand refers to: HL itself, or IX/IY plus
+    /// the signed displacement, which is also left in MEMPTR. The
+    /// displacement is fetched on first use and kept for the rest of the
+    /// instruction.
+    pub(crate) fn hl_address(&mut self) -> Result<u16> {
+        match self.index_mode {
+            IndexMode::Hl => Ok(self.get_hl()),
+            _ => {
+                let offset = match self.displacement {
+                    Some(offset) => offset,
+                    None => {
+                        let offset = self.fetch_byte()? as i8;
+                        self.displacement = Some(offset);
+                        offset
+                    }
+                };
+                self.wz = self.index_register().wrapping_add(offset as u16);
+                self.select_index_bank(offset);
+                Ok(self.wz)
+            }
+        }
+    }
+
+    /// Reads one of B, C, D, E, H, L or A by its opcode encoding, ignoring
+    /// any index prefix. The (HL) encoding is not valid here.
+    pub(crate) fn main_reg8(&self, index: u8) -> u8 {
+        match index & 0x07 {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => self.h,
+            5 => self.l,
+            _ => self.a,
+        }
+    }
+
+    /// Writes one of B, C, D, E, H, L or A by its opcode encoding, ignoring
+    /// any index prefix
+    pub(crate) fn set_main_reg8(&mut self, index: u8, value: u8) {
+        match index & 0x07 {
+            0 => self.b = value,
+            1 => self.c = value,
+            2 => self.d = value,
+            3 => self.e = value,
+            4 => self.h = value,
+            5 => self.l = value,
+            _ => self.a = value,
+        }
+    }
+
+    /// Returns HL, IX or IY according to the current index prefix
+    pub(crate) fn index_register(&self) -> u16 {
+        match self.index_mode {
+            IndexMode::Hl => self.get_hl(),
+            IndexMode::Ix => self.ix,
+            IndexMode::Iy => self.iy,
+        }
+    }
+
+    /// Writes HL, IX or IY according to the current index prefix
+    pub(crate) fn set_index_register(&mut self, value: u16) {
+        match self.index_mode {
+            IndexMode::Hl => self.set_hl(value),
+            IndexMode::Ix => self.ix = value,
+            IndexMode::Iy => self.iy = value,
+        }
+    }
+
+    /// Reads a register pair by its opcode encoding (BC, DE, HL, SP), where
+    /// HL follows the index prefix
+    pub(crate) fn reg16(&self, index: u8) -> u16 {
+        match index & 0x03 {
+            0 => self.get_bc(),
+            1 => self.get_de(),
+            2 => self.index_register(),
+            _ => self.sp,
+        }
+    }
+
+    /// Writes a register pair by its opcode encoding (BC, DE, HL, SP), where
+    /// HL follows the index prefix
+    pub(crate) fn set_reg16(&mut self, index: u8, value: u16) {
+        match index & 0x03 {
+            0 => self.set_bc(value),
+            1 => self.set_de(value),
+            2 => self.set_index_register(value),
+            _ => self.sp = value,
+        }
+    }
+
+    /// Evaluates a condition code (NZ, Z, NC, C, PO, PE, P, M)
+    pub(crate) fn condition(&self, cc: u8) -> bool {
+        match cc & 0x07 {
+            0 => !self.flags.zero,
+            1 => self.flags.zero,
+            2 => !self.flags.carry,
+            3 => self.flags.carry,
+            4 => !self.flags.parity,
+            5 => self.flags.parity,
+            6 => !self.flags.sign,
+            _ => self.flags.sign,
+        }
+    }
+
+    /// Fetches the next operand byte at PC, advancing PC and charging the
+    /// memory read. The eZ80 fetches from its code bank whatever bank its
+    /// data accesses use. An instruction injected in IM 0 reads its operands
+    /// from the data bus instead, leaving PC alone.
+    pub(crate) fn fetch_byte(&mut self) -> Result<u8> {
+        let value = if self.fetch_from_bus {
+            self.io.interrupt_acknowledge()
+        } else {
+            let value = match &self.ez80 {
+                Some(ez80) => self.memory.read_physical(ez80.code_address(self.pc)),
+                None => self.memory.read_byte(self.pc)?,
+            };
+            self.advance_pc(1);
+            value
+        };
+        let t_states = if self.model.is_r800() {
+            R800_MEMORY_READ_T_STATES
+        } else {
+            MEMORY_READ_T_STATES
+        };
+        self.t_states += t_states;
+        self.operand_t_states += t_states;
+        Ok(value)
+    }
+
+    /// Moves PC by `offset`, taken as a signed 16-bit displacement. In ADL
+    /// mode the move carries into or borrows from PCU, as the eZ80's PC is
+    /// 24 bits wide there.
+    pub(crate) fn advance_pc(&mut self, offset: u16) {
+        if let Some(ez80) = self.ez80.as_mut().filter(|ez80| ez80.adl) {
+            let address = ez80
+                .code_address(self.pc)
+                .wrapping_add(offset as i16 as u32);
+            ez80.pcu = (address >> 16) as u8;
+        }
+        self.pc = self.pc.wrapping_add(offset);
+    }
+
+    /// Fetches a little-endian operand word at PC
+    pub(crate) fn fetch_word(&mut self) -> Result<u16> {
+        let low = self.fetch_byte()?;
+        let high = self.fetch_byte()?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+
+    /// Reads an I/O port. On a Z180, ports with A15-A8 clear may reach the
+    /// on-chip registers instead of the bus.
+    pub(crate) fn port_in(&mut self, port: u16) -> u8 {
+        match self.read_internal_port(port) {
+            Some(value) => value,
+            None => self.io.read_port(port),
+        }
+    }
+
+    /// Writes an I/O port, or an on-chip register on a Z180
+    pub(crate) fn port_out(&mut self, port: u16, value: u8) {
+        if !self.write_internal_port(port, value) {
+            self.io.write_port(port, value);
+        }
+    }
+
+    /// Reads a little-endian word from memory
+    pub(crate) fn read_word(&self, address: u16) -> Result<u16> {
+        let low = self.memory.read_byte(address)?;
+        let high = self.memory.read_byte(address.wrapping_add(1))?;
+        Ok(u16::from_le_bytes([low, high]))
+    }
+
+    /// Writes a little-endian word to memory
+    pub(crate) fn write_word(&mut self, address: u16, value: u16) -> Result<()> {
+        let [low, high] = value.to_le_bytes();
+        self.memory.write_byte(address, low)?;
+        self.memory.write_byte(address.wrapping_add(1), high)
+    }
+
+    /// Pushes a word onto the stack
+    pub(crate) fn push_word(&mut self, value: u16) -> Result<()> {
+        let [low, high] = value.to_le_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        self.memory.write_byte(self.sp, high)?;
+        self.sp = self.sp.wrapping_sub(1);
+        self.memory.write_byte(self.sp, low)
+    }
+
+    /// Pops a word from the stack
+    pub(crate) fn pop_word(&mut self) -> Result<u16> {
+        let value = self.read_word(self.sp)?;
+        self.sp = self.sp.wrapping_add(2);
+        Ok(value)
+    }
+}
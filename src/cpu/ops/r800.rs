@@ -0,0 +1,114 @@
+//! Multiply instructions added by the ASCII R800 of the MSX turbo R, and its
+//! DRAM page-break penalty.
+//!
+//! The R800 counts one cycle per M1 and per memory access, so its timing
+//! lives in separate tables. On top of those, an access to a different
+//! 256-byte page from the previous one costs a page-break cycle. Only
+//! instruction fetches are tracked here: an instruction whose first byte
+//! lies in another page from the previous instruction's last byte pays it.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+/// Cycles lost to a DRAM page break
+const PAGE_BREAK_T_STATES: u32 = 1;
+
+impl Cpu {
+    /// Charges the page-break cycle for fetching `length` bytes at
+    /// `address`, remembering the page of the last byte for the next fetch
+    pub(crate) fn charge_page_break(&mut self, address: u16, length: u8) {
+        if (address >> 8) as u8 != self.fetch_page {
+            self.t_states += PAGE_BREAK_T_STATES;
+        }
+        let last = address.wrapping_add(u16::from(length).saturating_sub(1));
+        self.fetch_page = (last >> 8) as u8;
+    }
+
+    /// Sets the flags of MULUB and MULUW: Z from the whole product, C when
+    /// it overflows the low half, and S, H, P/V and N clear
+    fn update_multiply_flags(&mut self, zero: bool, carry: bool) {
+        self.flags.sign = false;
+        self.flags.zero = zero;
+        self.flags.half_carry = false;
+        self.flags.parity = false;
+        self.flags.add_subtract = false;
+        self.flags.carry = carry;
+    }
+}
+
+/// MULUB A, r: HL = A * r
+pub fn mulub(cpu: &mut Cpu) -> Result<()> {
+    let product = u16::from(cpu.a) * u16::from(cpu.main_reg8(cpu.opcode >> 3));
+    cpu.set_hl(product);
+    cpu.update_multiply_flags(product == 0, product > 0xFF);
+    Ok(())
+}
+
+/// MULUW HL, rr: DE:HL = HL * rr
+pub fn muluw(cpu: &mut Cpu) -> Result<()> {
+    let product = u32::from(cpu.get_hl()) * u32::from(cpu.reg16(cpu.opcode >> 4));
+    cpu.set_de((product >> 16) as u16);
+    cpu.set_hl(product as u16);
+    cpu.update_multiply_flags(product == 0, product > 0xFFFF);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::ops::fixture::{cpu_with, run};
+    use crate::cpu::CpuModel;
+
+    #[test]
+    fn test_mulub() {
+        // LD A,0x20; LD D,0x10; MULUB A,D
+        let mut cpu = cpu_with(CpuModel::R800, &[0x3E, 0x20, 0x16, 0x10, 0xED, 0xD1]);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.get_hl(), 0x0200);
+        assert!(cpu.flags.carry && !cpu.flags.zero);
+        assert_eq!(cpu.get_t_states(), 2 + 2 + 14);
+    }
+
+    #[test]
+    fn test_muluw() {
+        // LD HL,0x1234; LD BC,0x5678; MULUW HL,BC
+        let mut cpu = cpu_with(
+            CpuModel::R800,
+            &[0x21, 0x34, 0x12, 0x01, 0x78, 0x56, 0xED, 0xC3],
+        );
+        run(&mut cpu, 3);
+        let product = 0x1234u32 * 0x5678;
+        assert_eq!(cpu.get_de(), (product >> 16) as u16);
+        assert_eq!(cpu.get_hl(), product as u16);
+        assert!(cpu.flags.carry);
+        assert_eq!(cpu.get_t_states(), 3 + 3 + 36);
+    }
+
+    #[test]
+    fn test_r800_timing() {
+        // LD IX,0x0100; LD A,(IX+1); INC (HL); BIT 0,(HL); NOP
+        let mut cpu = cpu_with(
+            CpuModel::R800,
+            &[
+                0xDD, 0x21, 0x00, 0x01, 0xDD, 0x7E, 0x01, 0x34, 0xCB, 0x46, 0x00,
+            ],
+        );
+        let mut timings = Vec::new();
+        for _ in 0..5 {
+            let before = cpu.get_t_states();
+            cpu.step().unwrap();
+            timings.push(cpu.get_t_states() - before);
+        }
+        assert_eq!(timings, vec![4, 5, 4, 3, 1]);
+    }
+
+    #[test]
+    fn test_page_break() {
+        // JP 0x0100; NOP at 0x0100 pays for leaving page 0
+        let mut cpu = cpu_with(CpuModel::R800, &[0xC3, 0x00, 0x01]);
+        cpu.load_program(0x0100, &[0x00, 0x00]).unwrap();
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_t_states(), 3 + 1 + 1);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_t_states(), 3 + 1 + 1 + 1);
+    }
+}
@@ -0,0 +1,216 @@
+//! Instructions added by the Z180 (IN0, OUT0, TST, TSTIO, MLT, OTIM/OTDM
+//! and their repeating forms, and SLP), and the TRAP taken on an undefined
+//! opcode.
+//!
+//! IN0, OUT0, TSTIO and OTIM put zero on A15-A8, so they always reach the
+//! on-chip registers when the port falls in the internal block.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+/// Extra T-states for an OTIMR/OTDMR iteration that repeats (16 vs 14)
+const REPEAT_T_STATES: u32 = 2;
+
+impl Cpu {
+    /// Sets the flags of TST and TSTIO from the AND of the operands
+    fn update_test_flags(&mut self, result: u8) {
+        self.update_szp_flags(result);
+        self.flags.half_carry = true;
+        self.flags.add_subtract = false;
+        self.flags.carry = false;
+    }
+}
+
+/// IN0 r, (n). The (HL) encoding, IN0 (n), only sets the flags.
+pub fn in0_r_n(cpu: &mut Cpu) -> Result<()> {
+    let port = u16::from(cpu.fetch_byte()?);
+    let value = cpu.port_in(port);
+    cpu.update_szp_flags(value);
+    cpu.flags.half_carry = false;
+    cpu.flags.add_subtract = false;
+    let index = cpu.opcode >> 3;
+    if index & 0x07 != 6 {
+        cpu.set_main_reg8(index, value);
+    }
+    Ok(())
+}
+
+/// OUT0 (n), r
+pub fn out0_n_r(cpu: &mut Cpu) -> Result<()> {
+    let port = u16::from(cpu.fetch_byte()?);
+    let value = cpu.main_reg8(cpu.opcode >> 3);
+    cpu.port_out(port, value);
+    Ok(())
+}
+
+/// TST r and TST (HL)
+pub fn tst_r(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.reg8(cpu.opcode >> 3)?;
+    cpu.update_test_flags(cpu.a & value);
+    Ok(())
+}
+
+/// TST n
+pub fn tst_n(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.fetch_byte()?;
+    cpu.update_test_flags(cpu.a & value);
+    Ok(())
+}
+
+/// TSTIO n: tests port C against n
+pub fn tstio_n(cpu: &mut Cpu) -> Result<()> {
+    let mask = cpu.fetch_byte()?;
+    let value = cpu.port_in(u16::from(cpu.c));
+    cpu.update_test_flags(value & mask);
+    Ok(())
+}
+
+/// MLT rr: multiplies the two halves of a register pair into the pair
+pub fn mlt(cpu: &mut Cpu) -> Result<()> {
+    let pair = cpu.opcode >> 4;
+    let [low, high] = cpu.reg16(pair).to_le_bytes();
+    cpu.set_reg16(pair, u16::from(high) * u16::from(low));
+    Ok(())
+}
+
+/// OTIM, OTDM, OTIMR and OTDMR: outputs (HL) to port C, then steps HL and
+/// C and decrements B
+pub fn otim(cpu: &mut Cpu) -> Result<()> {
+    let step = if cpu.opcode & 0x08 != 0 {
+        0xFFFF
+    } else {
+        0x0001
+    };
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.port_out(u16::from(cpu.c), value);
+    cpu.set_hl(cpu.get_hl().wrapping_add(step));
+    cpu.c = cpu.c.wrapping_add(step as u8);
+    let b = cpu.b;
+    cpu.b = b.wrapping_sub(1);
+
+    cpu.update_szp_flags(cpu.b);
+    cpu.flags.half_carry = b & 0x0F == 0;
+    cpu.flags.add_subtract = value & 0x80 != 0;
+    cpu.flags.carry = b == 0;
+    if cpu.opcode & 0x10 != 0 && cpu.b != 0 {
+        cpu.rewind_block(REPEAT_T_STATES);
+    }
+    Ok(())
+}
+
+/// SLP: stops the CPU until an interrupt, like HALT
+pub fn slp(cpu: &mut Cpu) -> Result<()> {
+    cpu.halted = true;
+    Ok(())
+}
+
+/// TRAP, run in place of an undefined opcode. ITC records it, with UFO
+/// when the undefined byte followed a displacement, and execution restarts
+/// at 0x0000 with PC stacked one byte into the instruction, or two with UFO.
+pub fn trap(cpu: &mut Cpu) -> Result<()> {
+    let third_opcode = cpu.displacement.is_some();
+    cpu.record_z180_trap(third_opcode);
+    cpu.push_word(cpu.pc.wrapping_sub(if third_opcode { 2 } else { 1 }))?;
+    cpu.pc = 0x0000;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::ops::fixture::{cpu_with, run, Recorder};
+    use crate::cpu::CpuModel;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_mlt() {
+        // LD DE,0x1234; MLT DE
+        let mut cpu = cpu_with(CpuModel::Z180, &[0x11, 0x34, 0x12, 0xED, 0x5C]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_de(), 0x12 * 0x34);
+        assert_eq!(cpu.get_t_states(), 10 + 17);
+    }
+
+    #[test]
+    fn test_tst() {
+        // LD A,0xF0; LD B,0x0F; TST B; TST 0x90
+        let mut cpu = cpu_with(
+            CpuModel::Z180,
+            &[0x3E, 0xF0, 0x06, 0x0F, 0xED, 0x04, 0xED, 0x64, 0x90],
+        );
+        run(&mut cpu, 3);
+        assert!(cpu.flags.zero && cpu.flags.half_carry && !cpu.flags.carry);
+        cpu.step().unwrap();
+        assert!(!cpu.flags.zero && cpu.flags.sign && cpu.flags.parity);
+        assert_eq!(cpu.get_a(), 0xF0);
+        assert_eq!(cpu.get_t_states(), 7 + 7 + 7 + 9);
+    }
+
+    #[test]
+    fn test_otimr() {
+        // LD HL,0x0100; LD BC,0x0314; OTIMR: writes TMDR1L/H and RLDR1L
+        let mut cpu = cpu_with(
+            CpuModel::Z180,
+            &[0x21, 0x00, 0x01, 0x01, 0x14, 0x03, 0xED, 0x93],
+        );
+        cpu.load_program(0x0100, &[0x34, 0x12, 0x78]).unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        let start = cpu.get_t_states();
+        while cpu.get_pc() != 0x0008 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_t_states() - start, 16 + 16 + 14);
+        assert_eq!((cpu.b, cpu.c, cpu.get_hl()), (0, 0x17, 0x0103));
+        assert!(cpu.flags.zero);
+
+        let prt = cpu.z180().unwrap().prt();
+        assert_eq!(prt.read_tmdr(1, false), 0x34);
+        assert_eq!(prt.read_tmdr(1, true), 0x12);
+        assert_eq!(prt.read_rldr(1, false), 0x78);
+    }
+
+    #[test]
+    fn test_slp_wakes_on_interrupt() {
+        // IM 1; EI; SLP
+        let mut cpu = cpu_with(CpuModel::Z180, &[0xED, 0x56, 0xFB, 0xED, 0x76]);
+        cpu.sp = 0x8000;
+        run(&mut cpu, 4);
+        assert!(cpu.is_halted());
+        cpu.set_int_line(true);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.get_pc(), 0x0038);
+    }
+
+    #[test]
+    fn test_undefined_opcodes_trap() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu = cpu_with(CpuModel::Z180, &[]);
+        cpu.set_io_device(Box::new(Recorder(writes.clone())));
+        cpu.sp = 0x8000;
+
+        // OUT (C),0 traps instead of writing to the port
+        cpu.load_program(0x0100, &[0xED, 0x71]).unwrap();
+        cpu.pc = 0x0100;
+        cpu.step().unwrap();
+        assert!(writes.borrow().is_empty());
+        assert_eq!(cpu.get_pc(), 0x0000);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0101);
+        assert_eq!(cpu.read_internal_port(0x34), Some(0x81));
+
+        // Writing TRAP as zero clears it. An undefined DDCB opcode stacks
+        // PC two bytes in and sets UFO.
+        cpu.load_program(0x0000, &[0x3E, 0x01, 0xED, 0x39, 0x34])
+            .unwrap();
+        run(&mut cpu, 2);
+        assert_eq!(cpu.read_internal_port(0x34), Some(0x01));
+        cpu.load_program(0x0200, &[0xDD, 0xCB, 0x05, 0x00]).unwrap();
+        cpu.pc = 0x0200;
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0000);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0x0202);
+        assert_eq!(cpu.read_internal_port(0x34), Some(0xC1));
+    }
+}
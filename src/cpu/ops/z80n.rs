@@ -0,0 +1,359 @@
+//! Instructions added by the Z80N core of the ZX Spectrum Next, all on the
+//! ED page.
+//!
+//! TEST n shares its handler with the Z180's TST n.
+
+use crate::cpu::Cpu;
+use crate::Result;
+
+/// Extra T-states for an LDIRX/LDDRX/LDPIRX iteration that repeats
+const REPEAT_T_STATES: u32 = 5;
+
+/// Next register select port
+pub const NEXTREG_SELECT_PORT: u16 = 0x243B;
+/// Next register data port
+pub const NEXTREG_DATA_PORT: u16 = 0x253B;
+
+impl Cpu {
+    /// Copies `value` to (DE) unless it equals A, the transparent colour
+    fn copy_unless_a(&mut self, value: u8) -> Result<()> {
+        if value != self.a {
+            self.memory.write_byte(self.get_de(), value)?;
+        }
+        Ok(())
+    }
+
+    /// Decrements BC and, for the repeating forms (bit 4 set), moves PC back
+    /// onto the ED prefix while BC is non-zero
+    fn repeat_transfer(&mut self) {
+        self.set_bc(self.get_bc().wrapping_sub(1));
+        if self.opcode & 0x10 != 0 && self.get_bc() != 0 {
+            self.rewind_block(REPEAT_T_STATES);
+        }
+    }
+
+    /// Writes a Next register through the select and data ports
+    fn write_next_register(&mut self, register: u8, value: u8) {
+        self.port_out(NEXTREG_SELECT_PORT, register);
+        self.port_out(NEXTREG_DATA_PORT, value);
+    }
+}
+
+/// SWAPNIB
+pub fn swapnib(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = cpu.a.rotate_left(4);
+    Ok(())
+}
+
+/// MIRROR A
+pub fn mirror_a(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = cpu.a.reverse_bits();
+    Ok(())
+}
+
+/// BSLA, BSRA, BSRL, BSRF and BRLC DE, B
+pub fn barrel_shift(cpu: &mut Cpu) -> Result<()> {
+    let de = cpu.get_de();
+    let shift = u32::from(cpu.b & 0x1F);
+    let result = match cpu.opcode & 0x07 {
+        0 => de.checked_shl(shift).unwrap_or(0),
+        1 => ((de as i16) >> shift.min(15)) as u16,
+        2 => de.checked_shr(shift).unwrap_or(0),
+        3 => !((!de).checked_shr(shift).unwrap_or(0)),
+        _ => de.rotate_left(shift & 0x0F),
+    };
+    cpu.set_de(result);
+    Ok(())
+}
+
+/// MUL D, E
+pub fn mul_d_e(cpu: &mut Cpu) -> Result<()> {
+    cpu.set_de(u16::from(cpu.d) * u16::from(cpu.e));
+    Ok(())
+}
+
+/// ADD HL, A, ADD DE, A and ADD BC, A
+pub fn add_rr_a(cpu: &mut Cpu) -> Result<()> {
+    let pair = 0x33 - cpu.opcode;
+    cpu.set_reg16(pair, cpu.reg16(pair).wrapping_add(u16::from(cpu.a)));
+    Ok(())
+}
+
+/// ADD HL, nn, ADD DE, nn and ADD BC, nn
+pub fn add_rr_nn(cpu: &mut Cpu) -> Result<()> {
+    let pair = 0x36 - cpu.opcode;
+    let value = cpu.fetch_word()?;
+    cpu.set_reg16(pair, cpu.reg16(pair).wrapping_add(value));
+    Ok(())
+}
+
+/// PUSH nn. The operand is stored high byte first.
+pub fn push_nn(cpu: &mut Cpu) -> Result<()> {
+    let high = cpu.fetch_byte()?;
+    let low = cpu.fetch_byte()?;
+    cpu.push_word(u16::from_le_bytes([low, high]))
+}
+
+/// OUTINB: OUTI without the B decrement or any flag changes
+pub fn outinb(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.port_out(cpu.get_bc(), value);
+    cpu.set_hl(cpu.get_hl().wrapping_add(1));
+    Ok(())
+}
+
+/// NEXTREG n, n
+pub fn nextreg_n_n(cpu: &mut Cpu) -> Result<()> {
+    let register = cpu.fetch_byte()?;
+    let value = cpu.fetch_byte()?;
+    cpu.write_next_register(register, value);
+    Ok(())
+}
+
+/// NEXTREG n, A
+pub fn nextreg_n_a(cpu: &mut Cpu) -> Result<()> {
+    let register = cpu.fetch_byte()?;
+    cpu.write_next_register(register, cpu.a);
+    Ok(())
+}
+
+/// PIXELDN: moves HL down one pixel row in the ULA screen layout
+pub fn pixeldn(cpu: &mut Cpu) -> Result<()> {
+    let hl = cpu.get_hl();
+    let hl = if hl & 0x0700 != 0x0700 {
+        hl.wrapping_add(0x0100)
+    } else if hl & 0x00E0 != 0x00E0 {
+        (hl & 0xF8FF).wrapping_add(0x0020)
+    } else {
+        (hl & 0xF81F).wrapping_add(0x0800)
+    };
+    cpu.set_hl(hl);
+    Ok(())
+}
+
+/// PIXELAD: sets HL to the ULA screen address of pixel (E, D)
+pub fn pixelad(cpu: &mut Cpu) -> Result<()> {
+    let (x, y) = (u16::from(cpu.e), u16::from(cpu.d));
+    cpu.set_hl(0x4000 | ((y & 0xC0) << 5) | ((y & 0x07) << 8) | ((y & 0x38) << 2) | (x >> 3));
+    Ok(())
+}
+
+/// SETAE: sets A to the pixel mask of column E within its byte
+pub fn setae(cpu: &mut Cpu) -> Result<()> {
+    cpu.a = 0x80 >> (cpu.e & 0x07);
+    Ok(())
+}
+
+/// JP (C): jumps within the current 16 KB to the 64-byte block read from
+/// port BC
+pub fn jp_c(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.port_in(cpu.get_bc());
+    cpu.pc = (cpu.pc & 0xC000) | (u16::from(value) << 6);
+    Ok(())
+}
+
+/// LDIX, LDDX, LDIRX and LDDRX: LDI/LDD that skip bytes equal to A and
+/// always increment DE
+pub fn ldix(cpu: &mut Cpu) -> Result<()> {
+    let step = if cpu.opcode & 0x08 != 0 {
+        0xFFFF
+    } else {
+        0x0001
+    };
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.copy_unless_a(value)?;
+    cpu.set_hl(cpu.get_hl().wrapping_add(step));
+    cpu.set_de(cpu.get_de().wrapping_add(1));
+    cpu.repeat_transfer();
+    Ok(())
+}
+
+/// LDPIRX: fills from an 8-byte pattern aligned at HL, indexed by the low
+/// bits of E
+pub fn ldpirx(cpu: &mut Cpu) -> Result<()> {
+    let address = (cpu.get_hl() & 0xFFF8) | u16::from(cpu.e & 0x07);
+    let value = cpu.memory.read_byte(address)?;
+    cpu.copy_unless_a(value)?;
+    cpu.set_de(cpu.get_de().wrapping_add(1));
+    cpu.repeat_transfer();
+    Ok(())
+}
+
+/// LDWS: copies (HL) to (DE), then increments L and D, setting the flags
+/// as INC D
+pub fn ldws(cpu: &mut Cpu) -> Result<()> {
+    let value = cpu.memory.read_byte(cpu.get_hl())?;
+    cpu.memory.write_byte(cpu.get_de(), value)?;
+    cpu.l = cpu.l.wrapping_add(1);
+    cpu.d = cpu.inc8(cpu.d);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NEXTREG_DATA_PORT, NEXTREG_SELECT_PORT};
+    use crate::cpu::ops::fixture::{cpu_with, run, Recorder};
+    use crate::cpu::CpuModel;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_accumulator_ops() {
+        // LD A,0x1E; SWAPNIB; MIRROR A; LD E,0x0B; SETAE
+        let mut cpu = cpu_with(
+            CpuModel::Z80n,
+            &[0x3E, 0x1E, 0xED, 0x23, 0xED, 0x24, 0x1E, 0x0B, 0xED, 0x95],
+        );
+        run(&mut cpu, 2);
+        assert_eq!(cpu.a, 0xE1);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.a, 0x87);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.a, 0x10);
+    }
+
+    #[test]
+    fn test_mul_and_barrel_shifts() {
+        // LD DE,0x0C0A; MUL D,E
+        let mut cpu = cpu_with(CpuModel::Z80n, &[0x11, 0x0A, 0x0C, 0xED, 0x30]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_de(), 120);
+        assert_eq!(cpu.get_t_states(), 10 + 8);
+
+        for (opcode, expected) in [
+            (0x28, 0x1230), // BSLA
+            (0x29, 0xF812), // BSRA
+            (0x2A, 0x0812), // BSRL
+            (0x2B, 0xF812), // BSRF
+            (0x2C, 0x1238), // BRLC
+        ] {
+            // LD DE,0x8123; LD B,4; shift
+            let mut cpu = cpu_with(
+                CpuModel::Z80n,
+                &[0x11, 0x23, 0x81, 0x06, 0x04, 0xED, opcode],
+            );
+            run(&mut cpu, 3);
+            assert_eq!(cpu.get_de(), expected, "ED {opcode:02X}");
+        }
+    }
+
+    #[test]
+    fn test_16_bit_adds_and_push() {
+        // LD A,0xFF; LD HL,0x1000; ADD HL,A; ADD BC,0x1234; PUSH 0xBEEF
+        let program = [
+            0x3E, 0xFF, 0x21, 0x00, 0x10, 0xED, 0x31, 0xED, 0x36, 0x34, 0x12, 0xED, 0x8A, 0xBE,
+            0xEF,
+        ];
+        let mut cpu = cpu_with(CpuModel::Z80n, &program);
+        cpu.sp = 0x8000;
+        run(&mut cpu, 5);
+        assert_eq!(cpu.get_hl(), 0x10FF);
+        assert_eq!(cpu.get_bc(), 0x1234);
+        assert_eq!(cpu.read_word(cpu.sp).unwrap(), 0xBEEF);
+        assert_eq!(cpu.get_pc(), 15);
+    }
+
+    #[test]
+    fn test_nextreg_and_outinb() {
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        // NEXTREG 0x07,3; LD A,5; NEXTREG 0x08,A; LD HL,0x0100; LD BC,0x00FE; OUTINB
+        let mut cpu = cpu_with(
+            CpuModel::Z80n,
+            &[
+                0xED, 0x91, 0x07, 0x03, 0x3E, 0x05, 0xED, 0x92, 0x08, 0x21, 0x00, 0x01, 0x01, 0xFE,
+                0x00, 0xED, 0x90,
+            ],
+        );
+        cpu.set_io_device(Box::new(Recorder(writes.clone())));
+        cpu.memory.write_byte(0x0100, 0x42).unwrap();
+        run(&mut cpu, 6);
+        assert_eq!(
+            *writes.borrow(),
+            vec![
+                (NEXTREG_SELECT_PORT, 0x07),
+                (NEXTREG_DATA_PORT, 0x03),
+                (NEXTREG_SELECT_PORT, 0x08),
+                (NEXTREG_DATA_PORT, 0x05),
+                (0x00FE, 0x42),
+            ]
+        );
+        assert_eq!(cpu.get_hl(), 0x0101);
+        assert_eq!(cpu.b, 0x00);
+    }
+
+    #[test]
+    fn test_pixel_addressing() {
+        // LD DE,0x5F23 (y=0x5F, x=0x23); PIXELAD; PIXELDN
+        let mut cpu = cpu_with(CpuModel::Z80n, &[0x11, 0x23, 0x5F, 0xED, 0x94, 0xED, 0x93]);
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_hl(), 0x4F64);
+        run(&mut cpu, 1);
+        assert_eq!(cpu.get_hl(), 0x4884);
+    }
+
+    #[test]
+    fn test_jp_c() {
+        let mut cpu = cpu_with(CpuModel::Z80n, &[]);
+        cpu.set_io_device(Box::new(Recorder(Rc::default())));
+        // LD BC,0x0003; JP (C) at 0x4000
+        cpu.load_program(0x4000, &[0x01, 0x03, 0x00, 0xED, 0x98])
+            .unwrap();
+        cpu.pc = 0x4000;
+        run(&mut cpu, 2);
+        assert_eq!(cpu.get_pc(), 0x40C0);
+    }
+
+    #[test]
+    fn test_transparent_copies() {
+        // LD A,0xE3; LD HL,0x0100; LD DE,0x0200; LD BC,3; LDIRX
+        let mut cpu = cpu_with(
+            CpuModel::Z80n,
+            &[
+                0x3E, 0xE3, 0x21, 0x00, 0x01, 0x11, 0x00, 0x02, 0x01, 0x03, 0x00, 0xED, 0xB4,
+            ],
+        );
+        cpu.load_program(0x0100, &[0x11, 0xE3, 0x33]).unwrap();
+        cpu.load_program(0x0200, &[0xAA, 0xAA, 0xAA]).unwrap();
+        run(&mut cpu, 4);
+        let start = cpu.get_t_states();
+        while cpu.get_pc() != 0x000D {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.get_t_states() - start, 21 + 21 + 16);
+        assert_eq!(cpu.read_word(0x0200).unwrap(), 0xAA11);
+        assert_eq!(cpu.memory.read_byte(0x0202).unwrap(), 0x33);
+        assert_eq!((cpu.get_hl(), cpu.get_de()), (0x0103, 0x0203));
+    }
+
+    #[test]
+    fn test_ldpirx() {
+        // LD A,0; LD HL,0x0105; LD DE,0x0206; LD BC,4; LDPIRX
+        let mut cpu = cpu_with(
+            CpuModel::Z80n,
+            &[
+                0x3E, 0x00, 0x21, 0x05, 0x01, 0x11, 0x06, 0x02, 0x01, 0x04, 0x00, 0xED, 0xB7,
+            ],
+        );
+        cpu.load_program(0x0100, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        while cpu.get_pc() != 0x000D {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.read_word(0x0206).unwrap(), 0x0807);
+        assert_eq!(cpu.read_word(0x0208).unwrap(), 0x0201);
+        assert_eq!(cpu.get_hl(), 0x0105);
+    }
+
+    #[test]
+    fn test_ldws() {
+        // LD HL,0x01FF; LD DE,0x7F00; LDWS
+        let mut cpu = cpu_with(
+            CpuModel::Z80n,
+            &[0x21, 0xFF, 0x01, 0x11, 0x00, 0x7F, 0xED, 0xA5],
+        );
+        cpu.memory.write_byte(0x01FF, 0x5A).unwrap();
+        run(&mut cpu, 3);
+        assert_eq!(cpu.memory.read_byte(0x7F00).unwrap(), 0x5A);
+        assert_eq!((cpu.get_hl(), cpu.get_de()), (0x0100, 0x8000));
+        assert!(cpu.flags.parity && cpu.flags.sign);
+    }
+}
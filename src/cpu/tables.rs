@@ -2,164 +2,532 @@
 
 use super::instruction::create_nop;
 use super::instruction::{ExecuteFn, Instruction, InstructionType};
-use std::collections::HashMap;
+use super::model::CpuModel;
+use super::ops::{alu, bit, block, control, ez80, flow, io, load, lr35902, r800, z180, z80n};
+use std::sync::OnceLock;
+
+/// Expands to the eight register forms of a mnemonic, in opcode order
+macro_rules! r8 {
+    ($prefix:expr) => {
+        r8!($prefix, "")
+    };
+    ($prefix:expr, $suffix:expr) => {
+        [
+            concat!($prefix, "B", $suffix),
+            concat!($prefix, "C", $suffix),
+            concat!($prefix, "D", $suffix),
+            concat!($prefix, "E", $suffix),
+            concat!($prefix, "H", $suffix),
+            concat!($prefix, "L", $suffix),
+            concat!($prefix, "(HL)", $suffix),
+            concat!($prefix, "A", $suffix),
+        ]
+    };
+}
+
+/// Expands to the eight register forms of a mnemonic under a DD or FD
+/// prefix, where H, L and (HL) become the index register halves and `(IX+d)`
+/// or `(IY+d)`
+macro_rules! x8 {
+    ($prefix:expr, $index:literal) => {
+        x8!($prefix, "", $index)
+    };
+    ($prefix:expr, $suffix:expr, $index:literal) => {
+        [
+            concat!($prefix, "B", $suffix),
+            concat!($prefix, "C", $suffix),
+            concat!($prefix, "D", $suffix),
+            concat!($prefix, "E", $suffix),
+            concat!($prefix, $index, "H", $suffix),
+            concat!($prefix, $index, "L", $suffix),
+            concat!($prefix, "(", $index, "+d)", $suffix),
+            concat!($prefix, "A", $suffix),
+        ]
+    };
+}
+
+/// Expands to the mnemonic tables of the DD or FD page for one index register
+macro_rules! index_mnemonics {
+    ($index:literal) => {
+        IndexMnemonics {
+            // Loads between the index halves and (IX+d) use the plain H and L
+            ld_r_r: [
+                x8!("LD B, ", $index),
+                x8!("LD C, ", $index),
+                x8!("LD D, ", $index),
+                x8!("LD E, ", $index),
+                [
+                    concat!("LD ", $index, "H, B"),
+                    concat!("LD ", $index, "H, C"),
+                    concat!("LD ", $index, "H, D"),
+                    concat!("LD ", $index, "H, E"),
+                    concat!("LD ", $index, "H, ", $index, "H"),
+                    concat!("LD ", $index, "H, ", $index, "L"),
+                    concat!("LD H, (", $index, "+d)"),
+                    concat!("LD ", $index, "H, A"),
+                ],
+                [
+                    concat!("LD ", $index, "L, B"),
+                    concat!("LD ", $index, "L, C"),
+                    concat!("LD ", $index, "L, D"),
+                    concat!("LD ", $index, "L, E"),
+                    concat!("LD ", $index, "L, ", $index, "H"),
+                    concat!("LD ", $index, "L, ", $index, "L"),
+                    concat!("LD L, (", $index, "+d)"),
+                    concat!("LD ", $index, "L, A"),
+                ],
+                // The (IX+d),(IX+d) slot is HALT and never registered
+                r8!(concat!("LD (", $index, "+d), ")),
+                x8!("LD A, ", $index),
+            ],
+            alu: [
+                x8!("ADD A, ", $index),
+                x8!("ADC A, ", $index),
+                x8!("SUB ", $index),
+                x8!("SBC A, ", $index),
+                x8!("AND ", $index),
+                x8!("XOR ", $index),
+                x8!("OR ", $index),
+                x8!("CP ", $index),
+            ],
+            inc_r: x8!("INC ", $index),
+            dec_r: x8!("DEC ", $index),
+            ld_r_n: x8!("LD ", ", n", $index),
+            add_rr: [
+                concat!("ADD ", $index, ", BC"),
+                concat!("ADD ", $index, ", DE"),
+                concat!("ADD ", $index, ", ", $index),
+                concat!("ADD ", $index, ", SP"),
+            ],
+            ld_nn: concat!("LD ", $index, ", nn"),
+            store_nn: concat!("LD (nn), ", $index),
+            load_nn: concat!("LD ", $index, ", (nn)"),
+            inc: concat!("INC ", $index),
+            dec: concat!("DEC ", $index),
+            pop: concat!("POP ", $index),
+            push: concat!("PUSH ", $index),
+            ex_sp: concat!("EX (SP), ", $index),
+            jp: concat!("JP (", $index, ")"),
+            ld_sp: concat!("LD SP, ", $index),
+        }
+    };
+}
+
+/// Expands to the eight DDCB/FDCB forms of an operation on `(IX+d)` or
+/// `(IY+d)`. Every encoding but the documented z=6 one also copies the result
+/// into a register.
+macro_rules! xcb {
+    ($prefix:literal, $index:literal) => {
+        [
+            concat!($prefix, "(", $index, "+d), B"),
+            concat!($prefix, "(", $index, "+d), C"),
+            concat!($prefix, "(", $index, "+d), D"),
+            concat!($prefix, "(", $index, "+d), E"),
+            concat!($prefix, "(", $index, "+d), H"),
+            concat!($prefix, "(", $index, "+d), L"),
+            concat!($prefix, "(", $index, "+d)"),
+            concat!($prefix, "(", $index, "+d), A"),
+        ]
+    };
+}
+
+/// Expands to the rotate/shift, BIT, RES and SET mnemonic tables of the
+/// DDCB or FDCB page for one index register
+macro_rules! index_cb_mnemonics {
+    ($index:literal) => {
+        IndexCbMnemonics {
+            rot: [
+                xcb!("RLC ", $index),
+                xcb!("RRC ", $index),
+                xcb!("RL ", $index),
+                xcb!("RR ", $index),
+                xcb!("SLA ", $index),
+                xcb!("SRA ", $index),
+                xcb!("SLL ", $index),
+                xcb!("SRL ", $index),
+            ],
+            bit: [
+                concat!("BIT 0, (", $index, "+d)"),
+                concat!("BIT 1, (", $index, "+d)"),
+                concat!("BIT 2, (", $index, "+d)"),
+                concat!("BIT 3, (", $index, "+d)"),
+                concat!("BIT 4, (", $index, "+d)"),
+                concat!("BIT 5, (", $index, "+d)"),
+                concat!("BIT 6, (", $index, "+d)"),
+                concat!("BIT 7, (", $index, "+d)"),
+            ],
+            res: [
+                xcb!("RES 0, ", $index),
+                xcb!("RES 1, ", $index),
+                xcb!("RES 2, ", $index),
+                xcb!("RES 3, ", $index),
+                xcb!("RES 4, ", $index),
+                xcb!("RES 5, ", $index),
+                xcb!("RES 6, ", $index),
+                xcb!("RES 7, ", $index),
+            ],
+            set: [
+                xcb!("SET 0, ", $index),
+                xcb!("SET 1, ", $index),
+                xcb!("SET 2, ", $index),
+                xcb!("SET 3, ", $index),
+                xcb!("SET 4, ", $index),
+                xcb!("SET 5, ", $index),
+                xcb!("SET 6, ", $index),
+                xcb!("SET 7, ", $index),
+            ],
+        }
+    };
+}
+
+// Mnemonic tables for the unprefixed page, indexed by opcode fields
+const LD_R_R_MNEMONICS: [[&str; 8]; 8] = [
+    r8!("LD B, "),
+    r8!("LD C, "),
+    r8!("LD D, "),
+    r8!("LD E, "),
+    r8!("LD H, "),
+    r8!("LD L, "),
+    r8!("LD (HL), "),
+    r8!("LD A, "),
+];
+
+const ALU_MNEMONICS: [[&str; 8]; 8] = [
+    r8!("ADD A, "),
+    r8!("ADC A, "),
+    r8!("SUB "),
+    r8!("SBC A, "),
+    r8!("AND "),
+    r8!("XOR "),
+    r8!("OR "),
+    r8!("CP "),
+];
+
+const ALU_N_MNEMONICS: [&str; 8] = [
+    "ADD A, n", "ADC A, n", "SUB n", "SBC A, n", "AND n", "XOR n", "OR n", "CP n",
+];
+
+const INC_R_MNEMONICS: [&str; 8] = r8!("INC ");
+const DEC_R_MNEMONICS: [&str; 8] = r8!("DEC ");
+const LD_R_N_MNEMONICS: [&str; 8] = r8!("LD ", ", n");
+
+const LD_RR_NN_MNEMONICS: [&str; 4] = ["LD BC, nn", "LD DE, nn", "LD HL, nn", "LD SP, nn"];
+const INC_RR_MNEMONICS: [&str; 4] = ["INC BC", "INC DE", "INC HL", "INC SP"];
+const DEC_RR_MNEMONICS: [&str; 4] = ["DEC BC", "DEC DE", "DEC HL", "DEC SP"];
+const ADD_HL_RR_MNEMONICS: [&str; 4] = ["ADD HL, BC", "ADD HL, DE", "ADD HL, HL", "ADD HL, SP"];
+const PUSH_MNEMONICS: [&str; 4] = ["PUSH BC", "PUSH DE", "PUSH HL", "PUSH AF"];
+const POP_MNEMONICS: [&str; 4] = ["POP BC", "POP DE", "POP HL", "POP AF"];
+
+const RET_CC_MNEMONICS: [&str; 8] = [
+    "RET NZ", "RET Z", "RET NC", "RET C", "RET PO", "RET PE", "RET P", "RET M",
+];
+
+const JP_CC_MNEMONICS: [&str; 8] = [
+    "JP NZ, nn",
+    "JP Z, nn",
+    "JP NC, nn",
+    "JP C, nn",
+    "JP PO, nn",
+    "JP PE, nn",
+    "JP P, nn",
+    "JP M, nn",
+];
+
+const CALL_CC_MNEMONICS: [&str; 8] = [
+    "CALL NZ, nn",
+    "CALL Z, nn",
+    "CALL NC, nn",
+    "CALL C, nn",
+    "CALL PO, nn",
+    "CALL PE, nn",
+    "CALL P, nn",
+    "CALL M, nn",
+];
+
+const JR_CC_MNEMONICS: [&str; 4] = ["JR NZ, e", "JR Z, e", "JR NC, e", "JR C, e"];
+
+const RST_MNEMONICS: [&str; 8] = [
+    "RST 00H", "RST 08H", "RST 10H", "RST 18H", "RST 20H", "RST 28H", "RST 30H", "RST 38H",
+];
+
+// Mnemonic tables for the ED page, indexed by register or register pair
+const IN_R_C_MNEMONICS: [&str; 8] = [
+    "IN B,(C)", "IN C,(C)", "IN D,(C)", "IN E,(C)", "IN H,(C)", "IN L,(C)", "IN (C)", "IN A,(C)",
+];
+const OUT_C_R_MNEMONICS: [&str; 8] = [
+    "OUT (C),B",
+    "OUT (C),C",
+    "OUT (C),D",
+    "OUT (C),E",
+    "OUT (C),H",
+    "OUT (C),L",
+    "OUT (C),0",
+    "OUT (C),A",
+];
+const SBC_HL_RR_MNEMONICS: [&str; 4] = ["SBC HL, BC", "SBC HL, DE", "SBC HL, HL", "SBC HL, SP"];
+const ADC_HL_RR_MNEMONICS: [&str; 4] = ["ADC HL, BC", "ADC HL, DE", "ADC HL, HL", "ADC HL, SP"];
+const LD_NN_IND_RR_MNEMONICS: [&str; 4] =
+    ["LD (nn), BC", "LD (nn), DE", "LD (nn), HL", "LD (nn), SP"];
+const LD_RR_NN_IND_MNEMONICS: [&str; 4] =
+    ["LD BC, (nn)", "LD DE, (nn)", "LD HL, (nn)", "LD SP, (nn)"];
+const IM_MNEMONICS: [&str; 8] = [
+    "IM 0", "IM 0", "IM 1", "IM 2", "IM 0", "IM 0", "IM 1", "IM 2",
+];
+
+// Mnemonic tables for the CB page, indexed by operation/bit and register
+const ROT_MNEMONICS: [[&str; 8]; 8] = [
+    r8!("RLC "),
+    r8!("RRC "),
+    r8!("RL "),
+    r8!("RR "),
+    r8!("SLA "),
+    r8!("SRA "),
+    r8!("SLL "),
+    r8!("SRL "),
+];
 
-// Static string tables for instruction mnemonics
 const BIT_MNEMONICS: [[&str; 8]; 8] = [
-    [
-        "BIT 0, B",
-        "BIT 0, C",
-        "BIT 0, D",
-        "BIT 0, E",
-        "BIT 0, H",
-        "BIT 0, L",
-        "BIT 0, (HL)",
-        "BIT 0, A",
-    ],
-    [
-        "BIT 1, B",
-        "BIT 1, C",
-        "BIT 1, D",
-        "BIT 1, E",
-        "BIT 1, H",
-        "BIT 1, L",
-        "BIT 1, (HL)",
-        "BIT 1, A",
-    ],
-    [
-        "BIT 2, B",
-        "BIT 2, C",
-        "BIT 2, D",
-        "BIT 2, E",
-        "BIT 2, H",
-        "BIT 2, L",
-        "BIT 2, (HL)",
-        "BIT 2, A",
-    ],
-    [
-        "BIT 3, B",
-        "BIT 3, C",
-        "BIT 3, D",
-        "BIT 3, E",
-        "BIT 3, H",
-        "BIT 3, L",
-        "BIT 3, (HL)",
-        "BIT 3, A",
-    ],
-    [
-        "BIT 4, B",
-        "BIT 4, C",
-        "BIT 4, D",
-        "BIT 4, E",
-        "BIT 4, H",
-        "BIT 4, L",
-        "BIT 4, (HL)",
-        "BIT 4, A",
-    ],
-    [
-        "BIT 5, B",
-        "BIT 5, C",
-        "BIT 5, D",
-        "BIT 5, E",
-        "BIT 5, H",
-        "BIT 5, L",
-        "BIT 5, (HL)",
-        "BIT 5, A",
-    ],
-    [
-        "BIT 6, B",
-        "BIT 6, C",
-        "BIT 6, D",
-        "BIT 6, E",
-        "BIT 6, H",
-        "BIT 6, L",
-        "BIT 6, (HL)",
-        "BIT 6, A",
-    ],
-    [
-        "BIT 7, B",
-        "BIT 7, C",
-        "BIT 7, D",
-        "BIT 7, E",
-        "BIT 7, H",
-        "BIT 7, L",
-        "BIT 7, (HL)",
-        "BIT 7, A",
-    ],
+    r8!("BIT 0, "),
+    r8!("BIT 1, "),
+    r8!("BIT 2, "),
+    r8!("BIT 3, "),
+    r8!("BIT 4, "),
+    r8!("BIT 5, "),
+    r8!("BIT 6, "),
+    r8!("BIT 7, "),
+];
+
+const RES_MNEMONICS: [[&str; 8]; 8] = [
+    r8!("RES 0, "),
+    r8!("RES 1, "),
+    r8!("RES 2, "),
+    r8!("RES 3, "),
+    r8!("RES 4, "),
+    r8!("RES 5, "),
+    r8!("RES 6, "),
+    r8!("RES 7, "),
 ];
 
-// Add these new constant tables for IX/IY instructions
-const IX_LOAD_MNEMONICS: [&str; 8] = [
-    "LD B, (IX+d)",
-    "LD C, (IX+d)",
-    "LD D, (IX+d)",
-    "LD E, (IX+d)",
-    "LD H, (IX+d)",
-    "LD L, (IX+d)",
-    "LD (IX+d), r", // Placeholder for store
-    "LD A, (IX+d)",
+const SET_MNEMONICS: [[&str; 8]; 8] = [
+    r8!("SET 0, "),
+    r8!("SET 1, "),
+    r8!("SET 2, "),
+    r8!("SET 3, "),
+    r8!("SET 4, "),
+    r8!("SET 5, "),
+    r8!("SET 6, "),
+    r8!("SET 7, "),
 ];
 
-const IY_LOAD_MNEMONICS: [&str; 8] = [
-    "LD B, (IY+d)",
-    "LD C, (IY+d)",
-    "LD D, (IY+d)",
-    "LD E, (IY+d)",
-    "LD H, (IY+d)",
-    "LD L, (IY+d)",
-    "LD (IY+d), r", // Placeholder for store
-    "LD A, (IY+d)",
+/// Mnemonics for one DDCB/FDCB page, indexed by operation/bit and register
+struct IndexCbMnemonics {
+    rot: [[&'static str; 8]; 8],
+    bit: [&'static str; 8],
+    res: [[&'static str; 8]; 8],
+    set: [[&'static str; 8]; 8],
+}
+
+const DDCB_MNEMONICS: IndexCbMnemonics = index_cb_mnemonics!("IX");
+const FDCB_MNEMONICS: IndexCbMnemonics = index_cb_mnemonics!("IY");
+
+/// Mnemonics for one DD/FD page, indexed by opcode fields
+struct IndexMnemonics {
+    ld_r_r: [[&'static str; 8]; 8],
+    alu: [[&'static str; 8]; 8],
+    inc_r: [&'static str; 8],
+    dec_r: [&'static str; 8],
+    ld_r_n: [&'static str; 8],
+    add_rr: [&'static str; 4],
+    ld_nn: &'static str,
+    store_nn: &'static str,
+    load_nn: &'static str,
+    inc: &'static str,
+    dec: &'static str,
+    pop: &'static str,
+    push: &'static str,
+    ex_sp: &'static str,
+    jp: &'static str,
+    ld_sp: &'static str,
+}
+
+const IN0_R_N_MNEMONICS: [&str; 8] = [
+    "IN0 B, (n)",
+    "IN0 C, (n)",
+    "IN0 D, (n)",
+    "IN0 E, (n)",
+    "IN0 H, (n)",
+    "IN0 L, (n)",
+    "IN0 (n)",
+    "IN0 A, (n)",
 ];
+const OUT0_N_R_MNEMONICS: [&str; 8] = r8!("OUT0 (n), ");
+const TST_R_MNEMONICS: [&str; 8] = r8!("TST ");
+const SWAP_MNEMONICS: [&str; 8] = r8!("SWAP ");
+const MLT_MNEMONICS: [&str; 4] = ["MLT BC", "MLT DE", "MLT HL", "MLT SP"];
 
-// Add these new constant tables at the top with the other tables
-const IX_ARITHMETIC_MNEMONICS: [[&str; 4]; 4] = [
-    ["ADD IX, BC", "ADD IX, DE", "ADD IX, IX", "ADD IX, SP"],
-    ["ADC IX, BC", "ADC IX, DE", "ADC IX, IX", "ADC IX, SP"],
-    ["SUB IX, BC", "SUB IX, DE", "SUB IX, IX", "SUB IX, SP"],
-    ["SBC IX, BC", "SBC IX, DE", "SBC IX, IX", "SBC IX, SP"],
+/// Undocumented ED opcodes among 0x40-0x7F: the NEG, RETN and IM mirrors,
+/// OUT (C),0 and two NOPs
+const UNDOCUMENTED_ED: [u8; 21] = [
+    0x4C, 0x54, 0x5C, 0x64, 0x6C, 0x74, 0x7C, 0x55, 0x5D, 0x65, 0x6D, 0x75, 0x7D, 0x4E, 0x66, 0x6E,
+    0x76, 0x7E, 0x71, 0x77, 0x7F,
 ];
 
-const IY_ARITHMETIC_MNEMONICS: [[&str; 4]; 4] = [
-    ["ADD IY, BC", "ADD IY, DE", "ADD IY, IY", "ADD IY, SP"],
-    ["ADC IY, BC", "ADC IY, DE", "ADC IY, IY", "ADC IY, SP"],
-    ["SUB IY, BC", "SUB IY, DE", "SUB IY, IY", "SUB IY, SP"],
-    ["SBC IY, BC", "SBC IY, DE", "SBC IY, IY", "SBC IY, SP"],
+/// Intel 8080 T-states of each opcode, with conditional calls and returns
+/// at their not-taken timing
+#[rustfmt::skip]
+const I8080_T_STATES: [u8; 256] = [
+    4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4,
+    4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4,
+    4, 10, 16, 5, 5, 5, 7, 4, 4, 10, 16, 5, 5, 5, 7, 4,
+    4, 10, 13, 5, 10, 10, 10, 4, 4, 10, 13, 5, 5, 5, 7, 4,
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5,
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5,
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5,
+    7, 7, 7, 7, 7, 7, 7, 7, 5, 5, 5, 5, 5, 5, 7, 5,
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+    5, 10, 10, 10, 11, 11, 7, 11, 5, 10, 10, 10, 11, 17, 7, 11,
+    5, 10, 10, 10, 11, 11, 7, 11, 5, 10, 10, 10, 11, 17, 7, 11,
+    5, 10, 10, 18, 11, 11, 7, 11, 5, 5, 10, 5, 11, 17, 7, 11,
+    5, 10, 10, 4, 11, 11, 7, 11, 5, 5, 10, 4, 11, 17, 7, 11,
 ];
 
-// Add these new constant tables for ED prefix instructions
-const ED_BLOCK_MNEMONICS: [&str; 16] = [
-    "LDI", "CPI", "INI", "OUTI", "LDD", "CPD", "IND", "OUTD", "LDIR", "CPIR", "INIR", "OTIR",
-    "LDDR", "CPDR", "INDR", "OTDR",
+/// LR35902 T-states of each opcode, with conditional branches at their
+/// not-taken timing. Opcodes the LR35902 lacks are 0.
+#[rustfmt::skip]
+const LR35902_T_STATES: [u8; 256] = [
+    4, 12, 8, 8, 4, 4, 8, 4, 20, 8, 8, 8, 4, 4, 8, 4,
+    4, 12, 8, 8, 4, 4, 8, 4, 12, 8, 8, 8, 4, 4, 8, 4,
+    8, 12, 8, 8, 4, 4, 8, 4, 8, 8, 8, 8, 4, 4, 8, 4,
+    8, 12, 8, 8, 12, 12, 12, 4, 8, 8, 8, 8, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    8, 8, 8, 8, 8, 8, 4, 8, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    4, 4, 4, 4, 4, 4, 8, 4, 4, 4, 4, 4, 4, 4, 8, 4,
+    8, 12, 12, 16, 12, 16, 8, 16, 8, 16, 12, 0, 12, 24, 8, 16,
+    8, 12, 12, 0, 12, 16, 8, 16, 8, 16, 12, 0, 12, 0, 8, 16,
+    12, 12, 8, 0, 0, 16, 8, 16, 16, 4, 16, 0, 0, 0, 8, 16,
+    12, 12, 8, 4, 0, 16, 8, 16, 12, 8, 16, 4, 0, 0, 8, 16,
 ];
 
-const ED_IO_MNEMONICS: [[&str; 8]; 2] = [
-    [
-        "IN B,(C)", "IN C,(C)", "IN D,(C)", "IN E,(C)", "IN H,(C)", "IN L,(C)", "IN (C)",
-        "IN A,(C)",
-    ],
-    [
-        "OUT (C),B",
-        "OUT (C),C",
-        "OUT (C),D",
-        "OUT (C),E",
-        "OUT (C),H",
-        "OUT (C),L",
-        "OUT (C),0",
-        "OUT (C),A",
-    ],
+/// R800 cycles of each opcode, with conditional branches at their
+/// not-taken timing: one per opcode or operand byte and per memory access,
+/// plus internal cycles
+#[rustfmt::skip]
+const R800_T_STATES: [u8; 256] = [
+    1, 3, 2, 1, 1, 1, 2, 1, 1, 1, 2, 1, 1, 1, 2, 1,
+    2, 3, 2, 1, 1, 1, 2, 1, 3, 1, 2, 1, 1, 1, 2, 1,
+    2, 3, 5, 1, 1, 1, 2, 1, 2, 1, 5, 1, 1, 1, 2, 1,
+    2, 3, 4, 1, 4, 4, 3, 1, 2, 1, 4, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+    1, 3, 3, 3, 3, 4, 2, 4, 1, 3, 3, 0, 3, 5, 2, 4,
+    1, 3, 3, 3, 3, 4, 2, 4, 1, 1, 3, 3, 3, 0, 2, 4,
+    1, 3, 3, 7, 3, 4, 2, 4, 1, 1, 3, 1, 3, 0, 2, 4,
+    1, 3, 3, 2, 3, 4, 2, 4, 1, 1, 3, 1, 3, 0, 2, 4,
 ];
 
+/// Returns true if a DD or FD opcode has an (IX+d) or (IY+d) operand,
+/// where the (HL) encoding appears as source or destination
+fn has_displacement(opcode: u8) -> bool {
+    match opcode {
+        0x34..=0x36 => true,
+        0x76 => false,
+        0x40..=0x7F => opcode & 0x07 == 6 || opcode & 0x38 == 0x30,
+        0x80..=0xBF => opcode & 0x07 == 6,
+        _ => false,
+    }
+}
+
+const DD_MNEMONICS: IndexMnemonics = index_mnemonics!("IX");
+const FD_MNEMONICS: IndexMnemonics = index_mnemonics!("IY");
+
+/// Number of CPU models, each with its own shared set of tables
+const MODEL_COUNT: usize = CpuModel::Ez80 as usize + 1;
+
+/// A 256-entry page of the opcode map, indexed by opcode. An empty slot is
+/// an opcode the page does not define.
+#[derive(Debug, Clone)]
+struct Page([Option<Instruction>; 256]);
+
+impl Default for Page {
+    fn default() -> Self {
+        Self(std::array::from_fn(|_| None))
+    }
+}
+
+impl Page {
+    fn insert(&mut self, opcode: u8, instruction: Instruction) {
+        self.0[usize::from(opcode)] = Some(instruction);
+    }
+
+    fn remove(&mut self, opcode: u8) {
+        self.0[usize::from(opcode)] = None;
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn get(&self, opcode: u8) -> Option<&Instruction> {
+        self.0[usize::from(opcode)].as_ref()
+    }
+
+    fn get_mut(&mut self, opcode: u8) -> Option<&mut Instruction> {
+        self.0[usize::from(opcode)].as_mut()
+    }
+
+    /// Iterates over the defined opcodes and their instructions
+    fn iter_mut(&mut self) -> impl Iterator<Item = (u8, &mut Instruction)> {
+        self.0.iter_mut().enumerate().filter_map(|(opcode, slot)| {
+            slot.as_mut().map(|instruction| (opcode as u8, instruction))
+        })
+    }
+
+    /// Returns the number of defined opcodes
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.0.iter().flatten().count()
+    }
+}
+
+impl std::ops::Index<u8> for Page {
+    type Output = Instruction;
+
+    fn index(&self, opcode: u8) -> &Instruction {
+        self.get(opcode).expect("opcode missing from page")
+    }
+}
+
 /// Represents different instruction tables for the Z80
 #[derive(Debug, Default)]
 pub struct InstructionTables {
     /// Main instruction set (unprefixed)
-    main: HashMap<u8, Instruction>,
+    main: Page,
     /// CB-prefixed instructions (bit operations)
-    cb: HashMap<u8, Instruction>,
-    /// DD/FD-prefixed instructions (IX/IY instructions)
-    ddfd: HashMap<u8, Instruction>,
+    cb: Page,
+    /// DD-prefixed instructions (IX instructions)
+    dd: Page,
+    /// FD-prefixed instructions (IY instructions)
+    fd: Page,
     /// ED-prefixed instructions (extended instructions)
-    ed: HashMap<u8, Instruction>,
+    ed: Page,
+    /// DDCB-prefixed instructions (IX bit operations)
+    ddcb: Page,
+    /// FDCB-prefixed instructions (IY bit operations)
+    fdcb: Page,
 }
 
 impl InstructionTables {
@@ -169,249 +537,1051 @@ impl InstructionTables {
         tables
     }
 
+    /// Returns the tables for `model`. They are built on first use and
+    /// shared by every CPU of that model.
+    pub fn for_model(model: CpuModel) -> &'static Self {
+        static TABLES: [OnceLock<InstructionTables>; MODEL_COUNT] =
+            [const { OnceLock::new() }; MODEL_COUNT];
+        TABLES[model as usize].get_or_init(|| Self::build(model))
+    }
+
+    /// Builds the tables for `model`, adding the extra opcodes of the parts
+    /// that have them
+    fn build(model: CpuModel) -> Self {
+        let mut tables = Self::new();
+        match model {
+            CpuModel::Z180 => {
+                tables.init_z180_traps();
+                tables.init_z180_ed_table();
+            }
+            CpuModel::Z80n => tables.init_z80n_ed_table(),
+            CpuModel::I8080 => tables.init_8080_main_table(),
+            CpuModel::Lr35902 => tables.init_lr35902_tables(),
+            CpuModel::R800 => tables.init_r800_tables(),
+            CpuModel::Ez80 => tables.init_ez80_tables(),
+            _ => {}
+        }
+        tables
+    }
+
     fn initialize(&mut self) {
         self.init_main_table();
         self.init_cb_table();
-        self.init_ddfd_table();
         self.init_ed_table();
+        self.dd = Self::index_table(&DD_MNEMONICS);
+        self.fd = Self::index_table(&FD_MNEMONICS);
+        self.ddcb = Self::index_cb_table(&DDCB_MNEMONICS);
+        self.fdcb = Self::index_cb_table(&FDCB_MNEMONICS);
     }
 
     fn init_main_table(&mut self) {
+        use InstructionType::*;
+
         // Start with NOP (0x00)
         self.main.insert(
             0x00,
             Instruction::new("NOP", 1, 4, InstructionType::Control, create_nop()),
         );
-    }
 
-    fn init_cb_table(&mut self) {
-        // RLC instructions (CB 00-07)
-        for i in 0..8 {
-            self.cb.insert(
-                i,
-                Instruction::new(
-                    match i {
-                        0 => "RLC B",
-                        1 => "RLC C",
-                        2 => "RLC D",
-                        3 => "RLC E",
-                        4 => "RLC H",
-                        5 => "RLC L",
-                        6 => "RLC (HL)",
-                        7 => "RLC A",
-                        _ => unreachable!(),
-                    },
-                    2,                           // CB prefix + opcode
-                    if i == 6 { 15 } else { 8 }, // (HL) takes more T-states
-                    InstructionType::Rotate,
-                    create_nop(), // Placeholder until we implement actual operations
-                ),
+        // 16-bit loads and arithmetic (x=0, z=1/3)
+        for pair in 0..4u8 {
+            let base = pair << 4;
+            let p = pair as usize;
+            self.main.insert(
+                base | 0x01,
+                Instruction::new(LD_RR_NN_MNEMONICS[p], 3, 10, Load, load::ld_rr_nn),
+            );
+            self.main.insert(
+                base | 0x03,
+                Instruction::new(INC_RR_MNEMONICS[p], 1, 6, Arithmetic, alu::inc_rr)
+                    .without_flags(),
+            );
+            self.main.insert(
+                base | 0x09,
+                Instruction::new(ADD_HL_RR_MNEMONICS[p], 1, 11, Arithmetic, alu::add_hl_rr),
+            );
+            self.main.insert(
+                base | 0x0B,
+                Instruction::new(DEC_RR_MNEMONICS[p], 1, 6, Arithmetic, alu::dec_rr)
+                    .without_flags(),
             );
         }
 
-        // RRC instructions (CB 08-0F)
-        for i in 0x08..0x10 {
-            self.cb.insert(
-                i,
+        // 8-bit INC, DEC and LD r,n (x=0, z=4/5/6)
+        for reg in 0..8u8 {
+            let base = reg << 3;
+            let r = reg as usize;
+            let is_hl = reg == 6;
+            self.main.insert(
+                base | 0x04,
+                Instruction::new(
+                    INC_R_MNEMONICS[r],
+                    1,
+                    if is_hl { 11 } else { 4 },
+                    Arithmetic,
+                    alu::inc_r,
+                ),
+            );
+            self.main.insert(
+                base | 0x05,
+                Instruction::new(
+                    DEC_R_MNEMONICS[r],
+                    1,
+                    if is_hl { 11 } else { 4 },
+                    Arithmetic,
+                    alu::dec_r,
+                ),
+            );
+            self.main.insert(
+                base | 0x06,
                 Instruction::new(
-                    match i & 0x07 {
-                        0 => "RRC B",
-                        1 => "RRC C",
-                        2 => "RRC D",
-                        3 => "RRC E",
-                        4 => "RRC H",
-                        5 => "RRC L",
-                        6 => "RRC (HL)",
-                        7 => "RRC A",
-                        _ => unreachable!(),
-                    },
+                    LD_R_N_MNEMONICS[r],
                     2,
-                    if (i & 0x07) == 6 { 15 } else { 8 },
-                    InstructionType::Rotate,
-                    create_nop(),
+                    if is_hl { 10 } else { 7 },
+                    Load,
+                    load::ld_r_n,
                 ),
             );
         }
 
-        // BIT instructions (CB 40-7F)
-        for bit in 0..8 {
-            for reg in 0..8 {
-                let opcode = 0x40 | (bit << 3) | reg;
+        // Relative jumps; conditional forms list their not-taken timing
+        for cc in 0..4u8 {
+            self.main.insert(
+                0x20 | (cc << 3),
+                Instruction::new(JR_CC_MNEMONICS[cc as usize], 2, 7, Jump, flow::jr_cc),
+            );
+        }
+
+        let misc: [(u8, &'static str, u8, u32, InstructionType, ExecuteFn); 31] = [
+            (0x02, "LD (BC), A", 1, 7, Load, load::ld_rr_ind_a),
+            (0x07, "RLCA", 1, 4, Rotate, alu::rlca),
+            (0x0A, "LD A, (BC)", 1, 7, Load, load::ld_a_rr_ind),
+            (0x0F, "RRCA", 1, 4, Rotate, alu::rrca),
+            (0x10, "DJNZ e", 2, 8, Jump, flow::djnz),
+            (0x12, "LD (DE), A", 1, 7, Load, load::ld_rr_ind_a),
+            (0x17, "RLA", 1, 4, Rotate, alu::rla),
+            (0x18, "JR e", 2, 12, Jump, flow::jr),
+            (0x1A, "LD A, (DE)", 1, 7, Load, load::ld_a_rr_ind),
+            (0x1F, "RRA", 1, 4, Rotate, alu::rra),
+            (0x22, "LD (nn), HL", 3, 16, Load, load::ld_nn_ind_hl),
+            (0x27, "DAA", 1, 4, Arithmetic, alu::daa),
+            (0x2A, "LD HL, (nn)", 3, 16, Load, load::ld_hl_nn_ind),
+            (0x2F, "CPL", 1, 4, Arithmetic, alu::cpl),
+            (0x32, "LD (nn), A", 3, 13, Load, load::ld_nn_ind_a),
+            (0x37, "SCF", 1, 4, Arithmetic, alu::scf),
+            (0x3A, "LD A, (nn)", 3, 13, Load, load::ld_a_nn_ind),
+            (0x3F, "CCF", 1, 4, Arithmetic, alu::ccf),
+            (0x76, "HALT", 1, 4, Control, control::halt),
+            (0xC3, "JP nn", 3, 10, Jump, flow::jp_nn),
+            (0xC9, "RET", 1, 10, Return, flow::ret),
+            (0xCD, "CALL nn", 3, 17, Call, flow::call_nn),
+            (0xD3, "OUT (n), A", 2, 11, IO, io::out_n_a),
+            (0xD9, "EXX", 1, 4, Exchange, load::exx),
+            (0xDB, "IN A, (n)", 2, 11, IO, io::in_a_n),
+            (0xE3, "EX (SP), HL", 1, 19, Exchange, load::ex_sp_ind_hl),
+            (0xE9, "JP (HL)", 1, 4, Jump, flow::jp_hl),
+            (0xEB, "EX DE, HL", 1, 4, Exchange, load::ex_de_hl),
+            (0xF3, "DI", 1, 4, Control, control::di),
+            (0xF9, "LD SP, HL", 1, 6, Load, load::ld_sp_hl),
+            (0xFB, "EI", 1, 4, Control, control::ei),
+        ];
+
+        for (opcode, mnemonic, length, t_states, instruction_type, execute) in misc {
+            self.main.insert(
+                opcode,
+                Instruction::new(mnemonic, length, t_states, instruction_type, execute),
+            );
+        }
+
+        // The exchange of A and F carries the flags along with it
+        self.main.insert(
+            0x08,
+            Instruction::new("EX AF, AF'", 1, 4, Exchange, load::ex_af_af).with_flags(),
+        );
+
+        // 8-bit register loads (x=1), with HALT in place of LD (HL),(HL)
+        for dst in 0..8u8 {
+            for src in 0..8u8 {
+                if dst == 6 && src == 6 {
+                    continue;
+                }
+                self.main.insert(
+                    0x40 | (dst << 3) | src,
+                    Instruction::new(
+                        LD_R_R_MNEMONICS[dst as usize][src as usize],
+                        1,
+                        if dst == 6 || src == 6 { 7 } else { 4 },
+                        Load,
+                        load::ld_r_r,
+                    ),
+                );
+            }
+        }
+
+        // 8-bit arithmetic and logic on A (x=2)
+        for op in 0..8u8 {
+            let alu_type = if (4..7).contains(&op) {
+                Logic
+            } else {
+                Arithmetic
+            };
+            for src in 0..8u8 {
+                self.main.insert(
+                    0x80 | (op << 3) | src,
+                    Instruction::new(
+                        ALU_MNEMONICS[op as usize][src as usize],
+                        1,
+                        if src == 6 { 7 } else { 4 },
+                        alu_type,
+                        alu::alu_a_r,
+                    ),
+                );
+            }
+        }
+
+        // Conditional flow, ALU with immediates and restarts (x=3)
+        for y in 0..8u8 {
+            let base = 0xC0 | (y << 3);
+            let i = y as usize;
+            let alu_type = if (4..7).contains(&y) {
+                Logic
+            } else {
+                Arithmetic
+            };
+            self.main.insert(
+                base,
+                Instruction::new(RET_CC_MNEMONICS[i], 1, 5, Return, flow::ret_cc),
+            );
+            self.main.insert(
+                base | 0x02,
+                Instruction::new(JP_CC_MNEMONICS[i], 3, 10, Jump, flow::jp_cc_nn),
+            );
+            self.main.insert(
+                base | 0x04,
+                Instruction::new(CALL_CC_MNEMONICS[i], 3, 10, Call, flow::call_cc_nn),
+            );
+            self.main.insert(
+                base | 0x06,
+                Instruction::new(ALU_N_MNEMONICS[i], 2, 7, alu_type, alu::alu_a_n),
+            );
+            self.main.insert(
+                base | 0x07,
+                Instruction::new(RST_MNEMONICS[i], 1, 11, Call, flow::rst),
+            );
+        }
+
+        // Stack operations on register pairs
+        for pair in 0..4u8 {
+            let base = 0xC0 | (pair << 4);
+            let p = pair as usize;
+            let pop = Instruction::new(POP_MNEMONICS[p], 1, 10, Load, load::pop_qq);
+            self.main
+                .insert(base | 0x01, if pair == 3 { pop.with_flags() } else { pop });
+            self.main.insert(
+                base | 0x05,
+                Instruction::new(PUSH_MNEMONICS[p], 1, 11, Load, load::push_qq),
+            );
+        }
+    }
+
+    fn init_cb_table(&mut self) {
+        for y in 0..8u8 {
+            for reg in 0..8u8 {
+                let (i, r) = (y as usize, reg as usize);
+                let is_hl = reg == 6;
+
+                // Rotates and shifts (CB 00-3F)
+                self.cb.insert(
+                    (y << 3) | reg,
+                    Instruction::new(
+                        ROT_MNEMONICS[i][r],
+                        2,                          // CB prefix + opcode
+                        if is_hl { 15 } else { 8 }, // (HL) takes more T-states
+                        InstructionType::Rotate,
+                        bit::rot_r,
+                    ),
+                );
+
+                // BIT instructions (CB 40-7F)
                 self.cb.insert(
-                    opcode,
+                    0x40 | (y << 3) | reg,
                     Instruction::new(
-                        BIT_MNEMONICS[bit as usize][reg as usize],
+                        BIT_MNEMONICS[i][r],
                         2,
-                        if reg == 6 { 12 } else { 8 },
+                        if is_hl { 12 } else { 8 },
                         InstructionType::BitManip,
-                        create_nop(),
+                        bit::bit_r,
                     ),
                 );
+
+                // RES and SET leave the flags untouched (CB 80-FF)
+                self.cb.insert(
+                    0x80 | (y << 3) | reg,
+                    Instruction::new(
+                        RES_MNEMONICS[i][r],
+                        2,
+                        if is_hl { 15 } else { 8 },
+                        InstructionType::BitManip,
+                        bit::res_r,
+                    )
+                    .without_flags(),
+                );
+                self.cb.insert(
+                    0xC0 | (y << 3) | reg,
+                    Instruction::new(
+                        SET_MNEMONICS[i][r],
+                        2,
+                        if is_hl { 15 } else { 8 },
+                        InstructionType::BitManip,
+                        bit::set_r,
+                    )
+                    .without_flags(),
+                );
             }
         }
     }
 
-    fn init_ddfd_table(&mut self) {
-        // Load instructions using IX/IY (DD/FD 46-7E)
-        for reg in 0..8 {
-            let opcode = 0x46 | (reg << 3);
+    /// Builds a DDCB or FDCB page. Lengths and timings cover the whole
+    /// four-byte instruction: prefix, CB, displacement and opcode.
+    fn index_cb_table(mnemonics: &IndexCbMnemonics) -> Page {
+        let mut table = Page::default();
 
-            // DD prefix (IX) instructions - store in lower half of address space
-            self.ddfd.insert(
-                opcode,
+        for y in 0..8u8 {
+            for reg in 0..8u8 {
+                let (i, r) = (y as usize, reg as usize);
+                table.insert(
+                    (y << 3) | reg,
+                    Instruction::new(
+                        mnemonics.rot[i][r],
+                        4,
+                        23,
+                        InstructionType::Rotate,
+                        bit::rot_indexed,
+                    ),
+                );
+                table.insert(
+                    0x40 | (y << 3) | reg,
+                    Instruction::new(
+                        mnemonics.bit[i],
+                        4,
+                        20,
+                        InstructionType::BitManip,
+                        bit::bit_indexed,
+                    ),
+                );
+                table.insert(
+                    0x80 | (y << 3) | reg,
+                    Instruction::new(
+                        mnemonics.res[i][r],
+                        4,
+                        23,
+                        InstructionType::BitManip,
+                        bit::res_indexed,
+                    )
+                    .without_flags(),
+                );
+                table.insert(
+                    0xC0 | (y << 3) | reg,
+                    Instruction::new(
+                        mnemonics.set[i][r],
+                        4,
+                        23,
+                        InstructionType::BitManip,
+                        bit::set_indexed,
+                    )
+                    .without_flags(),
+                );
+            }
+        }
+
+        table
+    }
+
+    /// Builds a DD or FD page. Only instructions that use H, L, (HL) or HL
+    /// appear here; lengths and timings include the prefix byte.
+    fn index_table(mnemonics: &IndexMnemonics) -> Page {
+        use InstructionType::*;
+        let mut table = Page::default();
+
+        // 16-bit arithmetic on the index register
+        for pair in 0..4u8 {
+            table.insert(
+                (pair << 4) | 0x09,
                 Instruction::new(
-                    IX_LOAD_MNEMONICS[reg as usize],
-                    3,
-                    19,
-                    InstructionType::Load,
-                    create_nop(),
+                    mnemonics.add_rr[pair as usize],
+                    2,
+                    15,
+                    Arithmetic,
+                    alu::add_hl_rr,
                 ),
             );
+        }
+
+        let misc: [(u8, &'static str, u8, u32, InstructionType, ExecuteFn); 10] = [
+            (0x21, mnemonics.ld_nn, 4, 14, Load, load::ld_rr_nn),
+            (0x22, mnemonics.store_nn, 4, 20, Load, load::ld_nn_ind_hl),
+            (0x23, mnemonics.inc, 2, 10, Arithmetic, alu::inc_rr),
+            (0x2A, mnemonics.load_nn, 4, 20, Load, load::ld_hl_nn_ind),
+            (0x2B, mnemonics.dec, 2, 10, Arithmetic, alu::dec_rr),
+            (0xE1, mnemonics.pop, 2, 14, Load, load::pop_qq),
+            (0xE3, mnemonics.ex_sp, 2, 23, Exchange, load::ex_sp_ind_hl),
+            (0xE5, mnemonics.push, 2, 15, Load, load::push_qq),
+            (0xE9, mnemonics.jp, 2, 8, Jump, flow::jp_hl),
+            (0xF9, mnemonics.ld_sp, 2, 10, Load, load::ld_sp_hl),
+        ];
 
-            // FD prefix (IY) instructions - store in upper half of address space
-            self.ddfd.insert(
-                opcode | 0x80, // Use bit 7 to differentiate IY instructions
+        for (opcode, mnemonic, length, t_states, instruction_type, execute) in misc {
+            let instruction =
+                Instruction::new(mnemonic, length, t_states, instruction_type, execute);
+            let instruction = match opcode {
+                0x23 | 0x2B => instruction.without_flags(),
+                _ => instruction,
+            };
+            table.insert(opcode, instruction);
+        }
+
+        // 8-bit INC, DEC and LD r,n on the index halves and (IX+d)
+        for reg in [4u8, 5, 6] {
+            let base = reg << 3;
+            let r = reg as usize;
+            let (inc_dec_t_states, length, ld_t_states) =
+                if reg == 6 { (23, 3, 19) } else { (8, 2, 11) };
+            table.insert(
+                base | 0x04,
+                Instruction::new(
+                    mnemonics.inc_r[r],
+                    length,
+                    inc_dec_t_states,
+                    Arithmetic,
+                    alu::inc_r,
+                ),
+            );
+            table.insert(
+                base | 0x05,
+                Instruction::new(
+                    mnemonics.dec_r[r],
+                    length,
+                    inc_dec_t_states,
+                    Arithmetic,
+                    alu::dec_r,
+                ),
+            );
+            table.insert(
+                base | 0x06,
                 Instruction::new(
-                    IY_LOAD_MNEMONICS[reg as usize], // Use IY mnemonics
-                    3,
-                    19,
-                    InstructionType::Load,
-                    create_nop(),
+                    mnemonics.ld_r_n[r],
+                    length + 1,
+                    ld_t_states,
+                    Load,
+                    load::ld_r_n,
                 ),
             );
         }
 
-        // Updated arithmetic instructions implementation
-        let arithmetic_ops = [(0x84, 0), (0x8C, 1), (0x94, 2), (0x9C, 3)];
-
-        for (base_op, op_idx) in arithmetic_ops {
-            for reg_pair in 0..4 {
-                let opcode = base_op | (reg_pair << 4);
-
-                // DD prefix version (IX)
-                self.ddfd.insert(
-                    opcode,
+        // 8-bit loads that involve H, L or (HL); 0x76 stays HALT
+        for dst in 0..8u8 {
+            for src in 0..8u8 {
+                let uses_index = [dst, src].iter().any(|r| (4..=6).contains(r));
+                if !uses_index || (dst == 6 && src == 6) {
+                    continue;
+                }
+                let indexed = dst == 6 || src == 6;
+                table.insert(
+                    0x40 | (dst << 3) | src,
                     Instruction::new(
-                        IX_ARITHMETIC_MNEMONICS[op_idx][reg_pair as usize],
-                        2,  // Prefix + opcode
-                        15, // Arithmetic takes 15 T-states
-                        InstructionType::Arithmetic,
-                        create_nop(),
+                        mnemonics.ld_r_r[dst as usize][src as usize],
+                        if indexed { 3 } else { 2 },
+                        if indexed { 19 } else { 8 },
+                        Load,
+                        load::ld_r_r,
                     ),
                 );
+            }
+        }
 
-                // FD prefix version (IY)
-                self.ddfd.insert(
-                    opcode | 0x20,
+        // 8-bit arithmetic and logic on the index halves and (IX+d)
+        for op in 0..8u8 {
+            let alu_type = if (4..7).contains(&op) {
+                Logic
+            } else {
+                Arithmetic
+            };
+            for src in [4u8, 5, 6] {
+                let indexed = src == 6;
+                table.insert(
+                    0x80 | (op << 3) | src,
                     Instruction::new(
-                        IY_ARITHMETIC_MNEMONICS[op_idx][reg_pair as usize],
-                        2,
-                        15,
-                        InstructionType::Arithmetic,
-                        create_nop(),
+                        mnemonics.alu[op as usize][src as usize],
+                        if indexed { 3 } else { 2 },
+                        if indexed { 19 } else { 8 },
+                        alu_type,
+                        alu::alu_a_r,
                     ),
                 );
             }
         }
+
+        table
     }
 
     fn init_ed_table(&mut self) {
-        // Block transfer and search instructions (ED A0-BF)
-        let block_instructions = [
-            (0xA0, "LDI", 16),
-            (0xA1, "CPI", 16),
-            (0xA2, "INI", 16),
-            (0xA3, "OUTI", 16),
-            (0xA8, "LDD", 16),
-            (0xA9, "CPD", 16),
-            (0xAA, "IND", 16),
-            (0xAB, "OUTD", 16),
-            (0xB0, "LDIR", 21), // Explicitly define LDIR
-            (0xB1, "CPIR", 21),
-            (0xB2, "INIR", 21),
-            (0xB3, "OTIR", 21),
-            (0xB8, "LDDR", 21),
-            (0xB9, "CPDR", 21),
-            (0xBA, "INDR", 21),
-            (0xBB, "OTDR", 21),
-        ];
+        use InstructionType::*;
 
-        for &(opcode, mnemonic, t_states) in &block_instructions {
+        // Opcodes outside 40-7F and the block group are not decoded by the
+        // CPU and execute as two-byte, 8 T-state NOPs
+        for opcode in 0..=0xFFu8 {
             self.ed.insert(
                 opcode,
-                Instruction::new(mnemonic, 2, t_states, InstructionType::Block, create_nop()),
-            );
-        }
-
-        // I/O instructions (ED 40-7F)
-        let io_instructions = [
-            (0x40, "IN B,(C)"),
-            (0x41, "OUT (C),B"),
-            (0x48, "IN C,(C)"),
-            (0x49, "OUT (C),C"),
-            (0x50, "IN D,(C)"),
-            (0x51, "OUT (C),D"),
-            (0x58, "IN E,(C)"),
-            (0x59, "OUT (C),E"),
-            (0x60, "IN H,(C)"),
-            (0x61, "OUT (C),H"),
-            (0x68, "IN L,(C)"),
-            (0x69, "OUT (C),L"),
-            (0x70, "IN (C)"),
-            (0x71, "OUT (C),0"),
-            (0x78, "IN A,(C)"),
-            (0x79, "OUT (C),A"), // Explicitly define OUT (C),A
+                Instruction::new("NOP", 2, 8, Control, create_nop()).without_flags(),
+            );
+        }
+
+        // I/O through port BC, 16-bit ADC/SBC and LD (nn) forms (ED 40-7F)
+        for reg in 0..8u8 {
+            let base = 0x40 | (reg << 3);
+            let r = reg as usize;
+            self.ed.insert(
+                base,
+                Instruction::new(IN_R_C_MNEMONICS[r], 2, 12, IO, io::in_r_c).with_flags(),
+            );
+            self.ed.insert(
+                base | 0x01,
+                Instruction::new(OUT_C_R_MNEMONICS[r], 2, 12, IO, io::out_c_r).without_flags(),
+            );
+        }
+
+        for pair in 0..4u8 {
+            let base = 0x40 | (pair << 4);
+            let p = pair as usize;
+            self.ed.insert(
+                base | 0x02,
+                Instruction::new(SBC_HL_RR_MNEMONICS[p], 2, 15, Arithmetic, alu::sbc_hl_rr),
+            );
+            self.ed.insert(
+                base | 0x0A,
+                Instruction::new(ADC_HL_RR_MNEMONICS[p], 2, 15, Arithmetic, alu::adc_hl_rr),
+            );
+            self.ed.insert(
+                base | 0x03,
+                Instruction::new(LD_NN_IND_RR_MNEMONICS[p], 4, 20, Load, load::ld_nn_ind_rr),
+            );
+            self.ed.insert(
+                base | 0x0B,
+                Instruction::new(LD_RR_NN_IND_MNEMONICS[p], 4, 20, Load, load::ld_rr_nn_ind),
+            );
+        }
+
+        // NEG, RETN/RETI and IM are mirrored across the whole column
+        for y in 0..8u8 {
+            let base = 0x40 | (y << 3);
+            self.ed.insert(
+                base | 0x04,
+                Instruction::new("NEG", 2, 8, Arithmetic, alu::neg),
+            );
+            let ret_mnemonic = if y == 1 { "RETI" } else { "RETN" };
+            self.ed.insert(
+                base | 0x05,
+                Instruction::new(ret_mnemonic, 2, 14, Return, flow::retn),
+            );
+            self.ed.insert(
+                base | 0x06,
+                Instruction::new(IM_MNEMONICS[y as usize], 2, 8, Control, control::im),
+            );
+        }
+
+        let misc: [(u8, &'static str, u32, InstructionType, ExecuteFn); 6] = [
+            (0x47, "LD I, A", 9, Load, load::ld_i_a),
+            (0x4F, "LD R, A", 9, Load, load::ld_r_a),
+            (0x57, "LD A, I", 9, Load, load::ld_a_i),
+            (0x5F, "LD A, R", 9, Load, load::ld_a_r),
+            (0x67, "RRD", 18, Arithmetic, alu::rrd),
+            (0x6F, "RLD", 18, Arithmetic, alu::rld),
+        ];
+
+        for (opcode, mnemonic, t_states, instruction_type, execute) in misc {
+            let instruction = Instruction::new(mnemonic, 2, t_states, instruction_type, execute);
+            let instruction = match opcode {
+                0x47 | 0x4F => instruction.without_flags(),
+                _ => instruction.with_flags(),
+            };
+            self.ed.insert(opcode, instruction);
+        }
+
+        // Block transfer, search and I/O instructions (ED A0-BB)
+        let block_instructions: [(u8, &'static str, ExecuteFn); 16] = [
+            (0xA0, "LDI", block::ldi),
+            (0xA1, "CPI", block::cpi),
+            (0xA2, "INI", block::ini),
+            (0xA3, "OUTI", block::outi),
+            (0xA8, "LDD", block::ldi),
+            (0xA9, "CPD", block::cpi),
+            (0xAA, "IND", block::ini),
+            (0xAB, "OUTD", block::outi),
+            (0xB0, "LDIR", block::ldi),
+            (0xB1, "CPIR", block::cpi),
+            (0xB2, "INIR", block::ini),
+            (0xB3, "OTIR", block::outi),
+            (0xB8, "LDDR", block::ldi),
+            (0xB9, "CPDR", block::cpi),
+            (0xBA, "INDR", block::ini),
+            (0xBB, "OTDR", block::outi),
         ];
 
-        for &(opcode, mnemonic) in &io_instructions {
+        for (opcode, mnemonic, execute) in block_instructions {
             self.ed.insert(
                 opcode,
-                Instruction::new(mnemonic, 2, 12, InstructionType::IO, create_nop()),
+                Instruction::new(mnemonic, 2, 16, Block, execute).with_flags(),
             );
         }
+    }
 
-        // Extended arithmetic instructions
-        let arithmetic_ops = [
-            (0x4C, "NEG", 8),  // Negate accumulator
-            (0x67, "RRD", 18), // Rotate right decimal
-            (0x6F, "RLD", 18), // Rotate left decimal
-            (0x44, "IM 0", 8), // Set interrupt mode 0
-            (0x54, "IM 1", 8), // Set interrupt mode 1
-            (0x5E, "IM 2", 8), // Set interrupt mode 2
+    /// Adds the Z180 opcodes, which take over ED NOP slots
+    /// Turns the opcodes the Z180 leaves undefined into TRAP: the ED page
+    /// outside the documented Z80 set, SLL, and the DDCB and FDCB forms
+    /// other than those on (IX+d) alone. The Z180 additions then take their
+    /// ED slots back. DD and FD ahead of an opcode that does not use HL still
+    /// run as on the Z80.
+    fn init_z180_traps(&mut self) {
+        let trap = |instruction: &mut Instruction| {
+            *instruction = Instruction::new(
+                "TRAP",
+                instruction.length,
+                instruction.t_states,
+                InstructionType::Control,
+                z180::trap,
+            )
+            .without_flags();
+        };
+        for (opcode, instruction) in self.ed.iter_mut() {
+            let documented = match opcode {
+                0x40..=0x7F => !UNDOCUMENTED_ED.contains(&opcode),
+                0xA0..=0xBF => opcode & 0x04 == 0,
+                _ => false,
+            };
+            if !documented {
+                trap(instruction);
+            }
+        }
+        for (opcode, instruction) in self.cb.iter_mut() {
+            if opcode & 0xF8 == 0x30 {
+                trap(instruction);
+            }
+        }
+        for table in [&mut self.ddcb, &mut self.fdcb] {
+            for (opcode, instruction) in table.iter_mut() {
+                if opcode & 0x07 != 6 || opcode == 0x36 {
+                    trap(instruction);
+                }
+            }
+        }
+    }
+
+    fn init_z180_ed_table(&mut self) {
+        use InstructionType::*;
+
+        for reg in 0..8u8 {
+            let r = reg as usize;
+            self.ed.insert(
+                reg << 3,
+                Instruction::new(IN0_R_N_MNEMONICS[r], 3, 12, IO, z180::in0_r_n).with_flags(),
+            );
+            if reg != 6 {
+                self.ed.insert(
+                    (reg << 3) | 0x01,
+                    Instruction::new(OUT0_N_R_MNEMONICS[r], 3, 13, IO, z180::out0_n_r)
+                        .without_flags(),
+                );
+            }
+            let t_states = if reg == 6 { 10 } else { 7 };
+            self.ed.insert(
+                (reg << 3) | 0x04,
+                Instruction::new(TST_R_MNEMONICS[r], 2, t_states, Logic, z180::tst_r).with_flags(),
+            );
+        }
+
+        for pair in 0..4u8 {
+            self.ed.insert(
+                0x4C | (pair << 4),
+                Instruction::new(MLT_MNEMONICS[pair as usize], 2, 17, Arithmetic, z180::mlt)
+                    .without_flags(),
+            );
+        }
+
+        self.ed.insert(
+            0x64,
+            Instruction::new("TST n", 3, 9, Logic, z180::tst_n).with_flags(),
+        );
+        self.ed.insert(
+            0x74,
+            Instruction::new("TSTIO n", 3, 12, IO, z180::tstio_n).with_flags(),
+        );
+        self.ed.insert(
+            0x76,
+            Instruction::new("SLP", 2, 8, Control, z180::slp).without_flags(),
+        );
+
+        for (opcode, mnemonic) in [
+            (0x83, "OTIM"),
+            (0x8B, "OTDM"),
+            (0x93, "OTIMR"),
+            (0x9B, "OTDMR"),
+        ] {
+            self.ed.insert(
+                opcode,
+                Instruction::new(mnemonic, 2, 14, Block, z180::otim).with_flags(),
+            );
+        }
+    }
+
+    /// Reshapes the main table into the Intel 8080 instruction set. The Z80
+    /// relative jumps, DJNZ and EX AF,AF' become NOPs, EXX an alias of RET,
+    /// CB an alias of JP nn and DD, ED and FD aliases of CALL nn. Every
+    /// opcode takes its 8080 timing.
+    fn init_8080_main_table(&mut self) {
+        for opcode in [0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38] {
+            self.main.insert(
+                opcode,
+                Instruction::new("NOP", 1, 4, InstructionType::Control, create_nop()),
+            );
+        }
+        for (alias, opcode) in [
+            (0xCB, 0xC3),
+            (0xD9, 0xC9),
+            (0xDD, 0xCD),
+            (0xED, 0xCD),
+            (0xFD, 0xCD),
+        ] {
+            let instruction = self.main[opcode].clone();
+            self.main.insert(alias, instruction);
+        }
+        for (opcode, instruction) in self.main.iter_mut() {
+            instruction.t_states = u32::from(I8080_T_STATES[usize::from(opcode)]);
+        }
+    }
+
+    /// Reshapes the tables into the LR35902 opcode map. The DD, ED and FD
+    /// pages, the I/O and exchange instructions and the parity and sign
+    /// conditions are removed, their slots reused by the LR35902's own
+    /// loads, and CB 30-37 becomes SWAP. Every opcode takes its LR35902
+    /// timing.
+    fn init_lr35902_tables(&mut self) {
+        use InstructionType::*;
+
+        self.dd.clear();
+        self.fd.clear();
+        self.ed.clear();
+        self.ddcb.clear();
+        self.fdcb.clear();
+        for opcode in [
+            0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+        ] {
+            self.main.remove(opcode);
+        }
+
+        let lr35902: [(u8, &'static str, u8, InstructionType, ExecuteFn); 15] = [
+            (0x08, "LD (nn), SP", 3, Load, lr35902::ld_nn_ind_sp),
+            (0x10, "STOP", 2, Control, lr35902::stop),
+            (0x22, "LD (HL+), A", 1, Load, lr35902::ld_hli_a),
+            (0x2A, "LD A, (HL+)", 1, Load, lr35902::ld_a_hli),
+            (0x32, "LD (HL-), A", 1, Load, lr35902::ld_hli_a),
+            (0x3A, "LD A, (HL-)", 1, Load, lr35902::ld_a_hli),
+            (0xD9, "RETI", 1, Return, lr35902::reti),
+            (0xE0, "LDH (n), A", 2, Load, lr35902::ldh_n_a),
+            (0xE2, "LD (C), A", 1, Load, lr35902::ld_c_ind_a),
+            (0xE8, "ADD SP, e", 2, Arithmetic, lr35902::add_sp_e),
+            (0xEA, "LD (nn), A", 3, Load, load::ld_nn_ind_a),
+            (0xF0, "LDH A, (n)", 2, Load, lr35902::ldh_a_n),
+            (0xF2, "LD A, (C)", 1, Load, lr35902::ld_a_c_ind),
+            (0xF8, "LD HL, SP+e", 2, Load, lr35902::ld_hl_sp_e),
+            (0xFA, "LD A, (nn)", 3, Load, load::ld_a_nn_ind),
         ];
+        for (opcode, mnemonic, length, instruction_type, execute) in lr35902 {
+            let instruction = Instruction::new(mnemonic, length, 0, instruction_type, execute);
+            let instruction = match opcode {
+                0xF8 => instruction.with_flags(),
+                _ => instruction,
+            };
+            self.main.insert(opcode, instruction);
+        }
+        for (opcode, instruction) in self.main.iter_mut() {
+            instruction.t_states = u32::from(LR35902_T_STATES[usize::from(opcode)]);
+        }
+
+        for reg in 0..8u8 {
+            self.cb.insert(
+                0x30 | reg,
+                Instruction::new(SWAP_MNEMONICS[reg as usize], 2, 0, Rotate, lr35902::swap),
+            );
+        }
+        for (opcode, instruction) in self.cb.iter_mut() {
+            instruction.t_states = match (opcode & 0x07, opcode & 0xC0) {
+                (6, 0x40) => 12,
+                (6, _) => 16,
+                _ => 8,
+            };
+        }
+    }
+
+    /// Adds the R800 multiply instructions to the ED page and gives every
+    /// opcode its R800 timing. DD and FD cost one cycle over the plain
+    /// opcode, and an (IX+d) operand two more.
+    fn init_r800_tables(&mut self) {
+        use InstructionType::*;
 
-        for (opcode, mnemonic, t_states) in arithmetic_ops {
+        for (opcode, mnemonic) in [
+            (0xC1, "MULUB A, B"),
+            (0xC9, "MULUB A, C"),
+            (0xD1, "MULUB A, D"),
+            (0xD9, "MULUB A, E"),
+        ] {
             self.ed.insert(
                 opcode,
-                Instruction::new(
-                    mnemonic,
-                    2,
-                    t_states,
-                    InstructionType::Arithmetic,
-                    create_nop(),
-                ),
+                Instruction::new(mnemonic, 2, 14, Arithmetic, r800::mulub).with_flags(),
+            );
+        }
+        for (opcode, mnemonic) in [(0xC3, "MULUW HL, BC"), (0xF3, "MULUW HL, SP")] {
+            self.ed.insert(
+                opcode,
+                Instruction::new(mnemonic, 2, 36, Arithmetic, r800::muluw).with_flags(),
+            );
+        }
+
+        for (opcode, instruction) in self.main.iter_mut() {
+            instruction.t_states = u32::from(R800_T_STATES[usize::from(opcode)]);
+        }
+        for table in [&mut self.dd, &mut self.fd] {
+            for (opcode, instruction) in table.iter_mut() {
+                let displacement = if has_displacement(opcode) { 2 } else { 0 };
+                instruction.t_states =
+                    u32::from(R800_T_STATES[usize::from(opcode)]) + 1 + displacement;
+            }
+        }
+        for (opcode, instruction) in self.cb.iter_mut() {
+            instruction.t_states = match (opcode & 0x07, opcode & 0xC0) {
+                (6, 0x40) => 3,
+                (6, _) => 5,
+                _ => 2,
+            };
+        }
+        for table in [&mut self.ddcb, &mut self.fdcb] {
+            for (opcode, instruction) in table.iter_mut() {
+                instruction.t_states = if opcode & 0xC0 == 0x40 { 5 } else { 7 };
+            }
+        }
+        for (opcode, instruction) in self.ed.iter_mut() {
+            instruction.t_states = match (opcode >> 6, opcode & 0x07) {
+                _ if matches!(opcode, 0xC1 | 0xC9 | 0xD1 | 0xD9) => 14,
+                _ if matches!(opcode, 0xC3 | 0xF3) => 36,
+                (1, 0 | 1 | 6) => 3,
+                (1, 2 | 4) => 2,
+                (1, 3) => 6,
+                (1, 5) => 5,
+                (1, 7) if opcode & 0x30 == 0x20 => 5,
+                (2, 0..=3) if opcode & 0x20 != 0 => 4,
+                _ => 2,
+            };
+        }
+    }
+
+    /// Adds the eZ80 instructions on top of the Z180 ED opcodes, and swaps
+    /// in width-aware handlers for every instruction that loads, stores or
+    /// adjusts a register pair, touches the stack or changes PC. Timing
+    /// stays that of the Z80.
+    fn init_ez80_tables(&mut self) {
+        use InstructionType::*;
+
+        self.init_z180_ed_table();
+
+        for (opcode, mnemonic) in [
+            (0x02, "LEA BC, IX+d"),
+            (0x12, "LEA DE, IX+d"),
+            (0x22, "LEA HL, IX+d"),
+            (0x32, "LEA IX, IX+d"),
+            (0x54, "LEA IX, IY+d"),
+            (0x03, "LEA BC, IY+d"),
+            (0x13, "LEA DE, IY+d"),
+            (0x23, "LEA HL, IY+d"),
+            (0x33, "LEA IY, IY+d"),
+            (0x55, "LEA IY, IX+d"),
+        ] {
+            self.ed.insert(
+                opcode,
+                Instruction::new(mnemonic, 3, 15, Load, ez80::lea).without_flags(),
+            );
+        }
+        for (opcode, mnemonic) in [(0x65, "PEA IX+d"), (0x66, "PEA IY+d")] {
+            self.ed.insert(
+                opcode,
+                Instruction::new(mnemonic, 3, 19, Load, ez80::pea).without_flags(),
+            );
+        }
+        for (opcode, mnemonic, instruction_type, execute) in [
+            (0x6D, "LD MB, A", Load, ez80::ld_mb_a as ExecuteFn),
+            (0x6E, "LD A, MB", Load, ez80::ld_a_mb),
+            (0x7D, "STMIX", Control, ez80::stmix),
+            (0x7E, "RSMIX", Control, ez80::stmix),
+        ] {
+            self.ed.insert(
+                opcode,
+                Instruction::new(mnemonic, 2, 8, instruction_type, execute).without_flags(),
             );
         }
+
+        let mut main: Vec<(u8, ExecuteFn)> = vec![
+            (0x22, ez80::ld_nn_ind_rr),
+            (0x2A, ez80::ld_rr_nn_ind),
+            (0x32, ez80::ld_nn_ind_a),
+            (0x3A, ez80::ld_a_nn_ind),
+            (0xC3, ez80::jp_nn),
+            (0xCD, ez80::call_nn),
+            (0xC9, ez80::ret),
+            (0xD9, ez80::exx),
+            (0xE3, ez80::ex_sp_ind_hl),
+            (0xE9, ez80::jp_hl),
+            (0xEB, ez80::ex_de_hl),
+            (0xF9, ez80::ld_sp_hl),
+        ];
+        for pair in 0..4u8 {
+            let p = pair << 4;
+            main.push((0x01 | p, ez80::ld_rr_nn));
+            main.push((0x03 | p, ez80::inc_rr));
+            main.push((0x0B | p, ez80::dec_rr));
+            main.push((0x09 | p, ez80::add_hl_rr));
+            main.push((0xC1 | p, ez80::pop_qq));
+            main.push((0xC5 | p, ez80::push_qq));
+        }
+        for p in [0x00, 0x10] {
+            main.push((0x02 | p, ez80::ld_rr_ind_a));
+            main.push((0x0A | p, ez80::ld_a_rr_ind));
+        }
+        for cc in 0..8u8 {
+            let y = cc << 3;
+            main.push((0xC0 | y, ez80::ret_cc));
+            main.push((0xC2 | y, ez80::jp_cc_nn));
+            main.push((0xC4 | y, ez80::call_cc_nn));
+            main.push((0xC7 | y, ez80::rst));
+        }
+        for (opcode, execute) in main {
+            for table in [&mut self.main, &mut self.dd, &mut self.fd] {
+                if let Some(instruction) = table.get_mut(opcode) {
+                    instruction.execute = execute;
+                }
+            }
+        }
+
+        for pair in 0..4u8 {
+            let p = pair << 4;
+            for (opcode, execute) in [
+                (0x43 | p, ez80::ld_nn_ind_rr as ExecuteFn),
+                (0x4B | p, ez80::ld_rr_nn_ind),
+                (0x42 | p, ez80::sbc_hl_rr),
+                (0x4A | p, ez80::adc_hl_rr),
+                (0xA0 | (p & 0x10), ez80::ldi),
+                (0xA8 | (p & 0x10), ez80::ldi),
+                (0xA1 | (p & 0x10), ez80::cpi),
+                (0xA9 | (p & 0x10), ez80::cpi),
+            ] {
+                if let Some(instruction) = self.ed.get_mut(opcode) {
+                    instruction.execute = execute;
+                }
+            }
+        }
+        // RETN, RETI and the RETN mirrors the eZ80 leaves in place
+        for opcode in [0x45, 0x4D, 0x5D, 0x75] {
+            if let Some(instruction) = self.ed.get_mut(opcode) {
+                instruction.execute = ez80::retn;
+            }
+        }
+    }
+
+    /// Adds the Z80N opcodes of the ZX Spectrum Next, which take over ED
+    /// NOP slots. None of them changes the flags except TEST n and LDWS.
+    fn init_z80n_ed_table(&mut self) {
+        use InstructionType::*;
+
+        let z80n: [(u8, &'static str, u8, u32, InstructionType, ExecuteFn); 27] = [
+            (0x23, "SWAPNIB", 2, 8, Rotate, z80n::swapnib),
+            (0x24, "MIRROR A", 2, 8, Rotate, z80n::mirror_a),
+            (0x27, "TEST n", 3, 11, Logic, z180::tst_n),
+            (0x28, "BSLA DE, B", 2, 8, Rotate, z80n::barrel_shift),
+            (0x29, "BSRA DE, B", 2, 8, Rotate, z80n::barrel_shift),
+            (0x2A, "BSRL DE, B", 2, 8, Rotate, z80n::barrel_shift),
+            (0x2B, "BSRF DE, B", 2, 8, Rotate, z80n::barrel_shift),
+            (0x2C, "BRLC DE, B", 2, 8, Rotate, z80n::barrel_shift),
+            (0x30, "MUL D, E", 2, 8, Arithmetic, z80n::mul_d_e),
+            (0x31, "ADD HL, A", 2, 8, Arithmetic, z80n::add_rr_a),
+            (0x32, "ADD DE, A", 2, 8, Arithmetic, z80n::add_rr_a),
+            (0x33, "ADD BC, A", 2, 8, Arithmetic, z80n::add_rr_a),
+            (0x34, "ADD HL, nn", 4, 16, Arithmetic, z80n::add_rr_nn),
+            (0x35, "ADD DE, nn", 4, 16, Arithmetic, z80n::add_rr_nn),
+            (0x36, "ADD BC, nn", 4, 16, Arithmetic, z80n::add_rr_nn),
+            (0x8A, "PUSH nn", 4, 23, Load, z80n::push_nn),
+            (0x90, "OUTINB", 2, 16, IO, z80n::outinb),
+            (0x91, "NEXTREG n, n", 4, 20, IO, z80n::nextreg_n_n),
+            (0x92, "NEXTREG n, A", 3, 17, IO, z80n::nextreg_n_a),
+            (0x93, "PIXELDN", 2, 8, Special, z80n::pixeldn),
+            (0x94, "PIXELAD", 2, 8, Special, z80n::pixelad),
+            (0x95, "SETAE", 2, 8, Special, z80n::setae),
+            (0x98, "JP (C)", 2, 13, Jump, z80n::jp_c),
+            (0xA4, "LDIX", 2, 16, Block, z80n::ldix),
+            (0xA5, "LDWS", 2, 14, Block, z80n::ldws),
+            (0xAC, "LDDX", 2, 16, Block, z80n::ldix),
+            (0xB4, "LDIRX", 2, 16, Block, z80n::ldix),
+        ];
+
+        for (opcode, mnemonic, length, t_states, instruction_type, execute) in z80n {
+            let instruction =
+                Instruction::new(mnemonic, length, t_states, instruction_type, execute);
+            let instruction = match opcode {
+                0x27 | 0xA5 => instruction.with_flags(),
+                _ => instruction.without_flags(),
+            };
+            self.ed.insert(opcode, instruction);
+        }
+
+        self.ed.insert(
+            0xB7,
+            Instruction::new("LDPIRX", 2, 16, Block, z80n::ldpirx).without_flags(),
+        );
+        self.ed.insert(
+            0xBC,
+            Instruction::new("LDDRX", 2, 16, Block, z80n::ldix).without_flags(),
+        );
     }
 
     /// Looks up an instruction in the main table
     pub fn lookup_main(&self, opcode: u8) -> Option<&Instruction> {
-        self.main.get(&opcode)
+        self.main.get(opcode)
     }
 
     /// Looks up a CB-prefixed instruction
     pub fn lookup_cb(&self, opcode: u8) -> Option<&Instruction> {
-        self.cb.get(&opcode)
+        self.cb.get(opcode)
     }
 
-    /// Looks up a DD/FD-prefixed instruction
-    pub fn lookup_ddfd(&self, opcode: u8) -> Option<&Instruction> {
-        self.ddfd.get(&opcode)
+    /// Looks up a DD-prefixed (IX) instruction
+    pub fn lookup_dd(&self, opcode: u8) -> Option<&Instruction> {
+        self.dd.get(opcode)
+    }
+
+    /// Looks up an FD-prefixed (IY) instruction
+    pub fn lookup_fd(&self, opcode: u8) -> Option<&Instruction> {
+        self.fd.get(opcode)
     }
 
     /// Looks up an ED-prefixed instruction
     pub fn lookup_ed(&self, opcode: u8) -> Option<&Instruction> {
-        self.ed.get(&opcode)
+        self.ed.get(opcode)
+    }
+
+    /// Looks up a DDCB-prefixed instruction by its final opcode byte
+    pub fn lookup_ddcb(&self, opcode: u8) -> Option<&Instruction> {
+        self.ddcb.get(opcode)
+    }
+
+    /// Looks up an FDCB-prefixed instruction by its final opcode byte
+    pub fn lookup_fdcb(&self, opcode: u8) -> Option<&Instruction> {
+        self.fdcb.get(opcode)
     }
 }
 
@@ -433,9 +1603,122 @@ mod tests {
     }
 
     #[test]
-    fn test_invalid_opcode() {
+    fn test_tables_shared_per_model() {
+        let z80 = InstructionTables::for_model(CpuModel::ZilogNmos);
+        assert!(std::ptr::eq(
+            z80,
+            InstructionTables::for_model(CpuModel::ZilogNmos)
+        ));
+        let r800 = InstructionTables::for_model(CpuModel::R800);
+        assert!(!std::ptr::eq(z80, r800));
+        assert_eq!(r800.lookup_ed(0xC1).unwrap().mnemonic, "MULUB A, B");
+        assert_eq!(z80.lookup_ed(0xC1).unwrap().mnemonic, "NOP");
+    }
+
+    #[test]
+    fn test_model_ed_opcodes_are_nops_on_z80() {
+        let z80 = InstructionTables::for_model(CpuModel::ZilogNmos);
+        for (model, opcode, mnemonic) in [
+            (CpuModel::Z180, 0x04, "TST B"),
+            (CpuModel::Z180, 0x39, "OUT0 (n), A"),
+            (CpuModel::Z180, 0x83, "OTIM"),
+            (CpuModel::Z80n, 0x23, "SWAPNIB"),
+            (CpuModel::Z80n, 0x30, "MUL D, E"),
+            (CpuModel::Z80n, 0x8A, "PUSH nn"),
+            (CpuModel::R800, 0xC1, "MULUB A, B"),
+            (CpuModel::R800, 0xC3, "MULUW HL, BC"),
+            (CpuModel::Ez80, 0x22, "LEA HL, IX+d"),
+            (CpuModel::Ez80, 0x33, "LEA IY, IY+d"),
+        ] {
+            assert_eq!(
+                z80.lookup_ed(opcode).unwrap().mnemonic,
+                "NOP",
+                "ED {opcode:02X}"
+            );
+            let tables = InstructionTables::for_model(model);
+            assert_eq!(
+                tables.lookup_ed(opcode).unwrap().mnemonic,
+                mnemonic,
+                "{model:?} ED {opcode:02X}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_ed_table_complete() {
         let tables = InstructionTables::new();
-        assert!(tables.lookup_main(0xFF).is_none());
+
+        // Undefined ED opcodes are two-byte NOPs rather than invalid
+        for opcode in 0..=0xFFu8 {
+            assert!(
+                tables.lookup_ed(opcode).is_some(),
+                "ED {opcode:02X} missing"
+            );
+        }
+        let nop = tables.lookup_ed(0x00).unwrap();
+        assert_eq!(nop.mnemonic, "NOP");
+        assert_eq!(nop.length, 2);
+        assert_eq!(nop.t_states, 8);
+        assert_eq!(tables.lookup_ed(0x77).unwrap().mnemonic, "NOP");
+        assert_eq!(tables.lookup_ed(0xA4).unwrap().mnemonic, "NOP");
+    }
+
+    #[test]
+    fn test_ed_mirrors() {
+        let tables = InstructionTables::new();
+
+        for opcode in [0x44, 0x4C, 0x54, 0x5C, 0x64, 0x6C, 0x74, 0x7C] {
+            assert_eq!(tables.lookup_ed(opcode).unwrap().mnemonic, "NEG");
+        }
+        assert_eq!(tables.lookup_ed(0x4D).unwrap().mnemonic, "RETI");
+        assert_eq!(tables.lookup_ed(0x7D).unwrap().mnemonic, "RETN");
+        assert_eq!(tables.lookup_ed(0x4E).unwrap().mnemonic, "IM 0");
+        assert_eq!(tables.lookup_ed(0x76).unwrap().mnemonic, "IM 1");
+        assert_eq!(tables.lookup_ed(0x7E).unwrap().mnemonic, "IM 2");
+
+        let ld = tables.lookup_ed(0x73).unwrap();
+        assert_eq!(ld.mnemonic, "LD (nn), SP");
+        assert_eq!(ld.length, 4);
+        assert_eq!(ld.t_states, 20);
+    }
+
+    #[test]
+    fn test_main_table_complete() {
+        let tables = InstructionTables::new();
+        let prefixes = [0xCB, 0xDD, 0xED, 0xFD];
+
+        for opcode in 0..=0xFFu8 {
+            let entry = tables.lookup_main(opcode);
+            if prefixes.contains(&opcode) {
+                assert!(entry.is_none(), "prefix {opcode:#04x} in main table");
+            } else {
+                assert!(entry.is_some(), "missing opcode {opcode:#04x}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_main_table_timing() {
+        let tables = InstructionTables::new();
+        let expected = [
+            (0x01, "LD BC, nn", 3, 10),
+            (0x34, "INC (HL)", 1, 11),
+            (0x36, "LD (HL), n", 2, 10),
+            (0x46, "LD B, (HL)", 1, 7),
+            (0x76, "HALT", 1, 4),
+            (0x86, "ADD A, (HL)", 1, 7),
+            (0xC5, "PUSH BC", 1, 11),
+            (0xCD, "CALL nn", 3, 17),
+            (0xE3, "EX (SP), HL", 1, 19),
+            (0xFE, "CP n", 2, 7),
+        ];
+
+        for (opcode, mnemonic, length, t_states) in expected {
+            let instruction = tables.lookup_main(opcode).unwrap();
+            assert_eq!(instruction.mnemonic, mnemonic);
+            assert_eq!(instruction.length, length);
+            assert_eq!(instruction.t_states, t_states);
+        }
     }
 
     #[test]
@@ -502,19 +1785,67 @@ mod tests {
         assert_eq!(bit_3_hl.instruction_type, InstructionType::BitManip);
     }
 
+    #[test]
+    fn test_cb_table_complete() {
+        let tables = InstructionTables::new();
+
+        for opcode in 0..=0xFFu8 {
+            let instruction = tables.lookup_cb(opcode).unwrap();
+            assert_eq!(instruction.length, 2);
+        }
+
+        let sll = tables.lookup_cb(0x36).unwrap();
+        assert_eq!(sll.mnemonic, "SLL (HL)");
+        assert_eq!(sll.t_states, 15);
+
+        let res = tables.lookup_cb(0x86).unwrap();
+        assert_eq!(res.mnemonic, "RES 0, (HL)");
+        assert_eq!(res.t_states, 15);
+        assert!(!res.affects_flags);
+
+        let set = tables.lookup_cb(0xFF).unwrap();
+        assert_eq!(set.mnemonic, "SET 7, A");
+        assert_eq!(set.t_states, 8);
+    }
+
+    #[test]
+    fn test_index_cb_tables() {
+        let tables = InstructionTables::new();
+
+        for opcode in 0..=0xFFu8 {
+            assert_eq!(tables.lookup_ddcb(opcode).unwrap().length, 4);
+            assert_eq!(tables.lookup_fdcb(opcode).unwrap().length, 4);
+        }
+
+        let rlc = tables.lookup_ddcb(0x06).unwrap();
+        assert_eq!(rlc.mnemonic, "RLC (IX+d)");
+        assert_eq!(rlc.t_states, 23);
+
+        let rlc_b = tables.lookup_fdcb(0x00).unwrap();
+        assert_eq!(rlc_b.mnemonic, "RLC (IY+d), B");
+        assert_eq!(rlc_b.t_states, 23);
+
+        let bit = tables.lookup_ddcb(0x78).unwrap();
+        assert_eq!(bit.mnemonic, "BIT 7, (IX+d)");
+        assert_eq!(bit.t_states, 20);
+
+        let set = tables.lookup_fdcb(0xFF).unwrap();
+        assert_eq!(set.mnemonic, "SET 7, (IY+d), A");
+    }
+
     #[test]
     fn test_ix_load_instructions() {
         let tables = InstructionTables::new();
 
         // Test LD B,(IX+d)
-        let load_b_ix = tables.lookup_ddfd(0x46).expect("LD B,(IX+d) should exist");
+        let load_b_ix = tables.lookup_dd(0x46).expect("LD B,(IX+d) should exist");
         assert_eq!(load_b_ix.mnemonic, "LD B, (IX+d)");
         assert_eq!(load_b_ix.length, 3);
         assert_eq!(load_b_ix.t_states, 19);
         assert_eq!(load_b_ix.instruction_type, InstructionType::Load);
 
         // Test LD A,(IX+d)
-        let load_a_ix = tables.lookup_ddfd(0x7E).expect("LD A,(IX+d) should exist");
+        let load_a_ix = tables.lookup_dd(0x7E).expect("LD A,(IX+d) should exist");
         assert_eq!(load_a_ix.mnemonic, "LD A, (IX+d)");
         assert_eq!(load_a_ix.length, 3);
         assert_eq!(load_a_ix.t_states, 19);
@@ -525,10 +1856,8 @@ mod tests {
     fn test_iy_load_instructions() {
         let tables = InstructionTables::new();
 
-        // Test LD B,(IY+d) - opcode 0x46 with bit 7 set for IY
-        let load_b_iy = tables
-            .lookup_ddfd(0x46 | 0x80)
-            .expect("LD B,(IY+d) should exist");
+        // Test LD B,(IY+d)
+        let load_b_iy = tables.lookup_fd(0x46).expect("LD B,(IY+d) should exist");
         assert_eq!(load_b_iy.mnemonic, "LD B, (IY+d)");
         assert_eq!(load_b_iy.length, 3);
         assert_eq!(load_b_iy.t_states, 19);
@@ -540,11 +1869,45 @@ mod tests {
         let tables = InstructionTables::new();
 
         // Test ADD IX,BC
-        let add_ix_bc = tables.lookup_ddfd(0x84).expect("ADD IX,BC should exist");
+        let add_ix_bc = tables.lookup_dd(0x09).expect("ADD IX,BC should exist");
         assert_eq!(add_ix_bc.mnemonic, "ADD IX, BC");
         assert_eq!(add_ix_bc.length, 2);
         assert_eq!(add_ix_bc.t_states, 15);
         assert_eq!(add_ix_bc.instruction_type, InstructionType::Arithmetic);
+
+        // Test ADD A,IXH
+        let add_a_ixh = tables.lookup_dd(0x84).expect("ADD A,IXH should exist");
+        assert_eq!(add_a_ixh.mnemonic, "ADD A, IXH");
+        assert_eq!(add_a_ixh.length, 2);
+        assert_eq!(add_a_ixh.t_states, 8);
+    }
+
+    #[test]
+    fn test_index_tables() {
+        let tables = InstructionTables::new();
+
+        // Only opcodes involving H, L, (HL) or HL have DD/FD forms
+        assert_eq!(tables.dd.len(), 85);
+        assert_eq!(tables.fd.len(), 85);
+        assert!(tables.lookup_dd(0x00).is_none());
+        assert!(tables.lookup_dd(0x76).is_none());
+        assert!(tables.lookup_dd(0xEB).is_none());
+
+        assert_eq!(tables.lookup_dd(0x66).unwrap().mnemonic, "LD H, (IX+d)");
+        assert_eq!(tables.lookup_fd(0x74).unwrap().mnemonic, "LD (IY+d), H");
+        assert_eq!(tables.lookup_fd(0x65).unwrap().mnemonic, "LD IYH, IYL");
+        assert_eq!(tables.lookup_dd(0x36).unwrap().mnemonic, "LD (IX+d), n");
+        assert_eq!(tables.lookup_dd(0x36).unwrap().length, 4);
+        assert_eq!(tables.lookup_dd(0x34).unwrap().t_states, 23);
+        assert_eq!(tables.lookup_fd(0xE9).unwrap().mnemonic, "JP (IY)");
+        assert_eq!(tables.lookup_fd(0xE3).unwrap().t_states, 23);
+
+        for opcode in 0..=0xFFu8 {
+            if let Some(instruction) = tables.lookup_dd(opcode) {
+                let indexed = instruction.mnemonic.contains("(IX+d)");
+                assert_eq!(has_displacement(opcode), indexed, "DD {opcode:02X}");
+            }
+        }
     }
 
     #[test]
@@ -566,7 +1929,8 @@ mod tests {
             .expect("LDIR instruction should exist");
         assert_eq!(ldir.mnemonic, "LDIR");
         assert_eq!(ldir.length, 2);
-        assert_eq!(ldir.t_states, 21);
+        // The final iteration; repeating iterations add 5 when executed
+        assert_eq!(ldir.t_states, 16);
         assert_eq!(ldir.instruction_type, InstructionType::Block);
     }
 
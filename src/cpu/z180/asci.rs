@@ -0,0 +1,137 @@
+//! Asynchronous serial communication interface (ASCI) channels.
+//!
+//! Characters move as whole bytes: a byte written to TDR is transmitted at
+//! once, and bytes handed in by the host are received in order. Baud rate
+//! and framing are stored but not timed.
+
+use std::collections::VecDeque;
+
+/// CNTLA receiver enable
+const RE: u8 = 0x40;
+/// CNTLA transmitter enable
+const TE: u8 = 0x20;
+/// STAT receive data register full
+const RDRF: u8 = 0x80;
+/// STAT receive interrupt enable
+const RIE: u8 = 0x08;
+/// STAT transmit data register empty
+const TDRE: u8 = 0x02;
+/// STAT transmit interrupt enable
+const TIE: u8 = 0x01;
+/// STAT bits written by software (RIE, DCD0/CTS1E and TIE)
+const STAT_WRITABLE: u8 = 0x0D;
+
+/// One ASCI channel
+#[derive(Debug, Default)]
+pub struct Asci {
+    cntla: u8,
+    cntlb: u8,
+    stat: u8,
+    // Last byte read from RDR, which stays readable
+    rdr: u8,
+    received: VecDeque<u8>,
+    transmitted: Vec<u8>,
+}
+
+impl Asci {
+    /// Returns control register A
+    pub fn cntla(&self) -> u8 {
+        self.cntla
+    }
+
+    /// Writes control register A
+    pub fn set_cntla(&mut self, value: u8) {
+        self.cntla = value;
+    }
+
+    /// Returns control register B
+    pub fn cntlb(&self) -> u8 {
+        self.cntlb
+    }
+
+    /// Writes control register B
+    pub fn set_cntlb(&mut self, value: u8) {
+        self.cntlb = value;
+    }
+
+    /// Returns the status register
+    pub fn stat(&self) -> u8 {
+        let mut stat = (self.stat & STAT_WRITABLE) | TDRE;
+        if self.cntla & RE != 0 && !self.received.is_empty() {
+            stat |= RDRF;
+        }
+        stat
+    }
+
+    /// Writes the interrupt enable bits of the status register
+    pub fn set_stat(&mut self, value: u8) {
+        self.stat = value & STAT_WRITABLE;
+    }
+
+    /// Writes the transmit data register, sending the byte if the
+    /// transmitter is enabled
+    pub fn write_tdr(&mut self, value: u8) {
+        if self.cntla & TE != 0 {
+            self.transmitted.push(value);
+        }
+    }
+
+    /// Reads the receive data register, taking the next received byte
+    pub fn read_rdr(&mut self) -> u8 {
+        if self.cntla & RE != 0 {
+            if let Some(byte) = self.received.pop_front() {
+                self.rdr = byte;
+            }
+        }
+        self.rdr
+    }
+
+    /// Queues a byte arriving on the receive line
+    pub fn receive(&mut self, byte: u8) {
+        self.received.push_back(byte);
+    }
+
+    /// Takes the bytes transmitted since the last call
+    pub fn take_transmitted(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.transmitted)
+    }
+
+    /// Returns true if the channel is requesting an interrupt
+    pub fn interrupt_requested(&self) -> bool {
+        let stat = self.stat();
+        (stat & RIE != 0 && stat & RDRF != 0) || (stat & TIE != 0 && stat & TDRE != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transmit_and_receive() {
+        let mut asci = Asci::default();
+        asci.write_tdr(b'x');
+        assert!(asci.take_transmitted().is_empty());
+
+        asci.set_cntla(RE | TE);
+        asci.write_tdr(b'o');
+        asci.write_tdr(b'k');
+        assert_eq!(asci.take_transmitted(), b"ok");
+
+        assert_eq!(asci.stat() & RDRF, 0);
+        asci.receive(0x41);
+        assert_ne!(asci.stat() & RDRF, 0);
+        assert_eq!(asci.read_rdr(), 0x41);
+        assert_eq!(asci.stat() & RDRF, 0);
+    }
+
+    #[test]
+    fn test_receive_interrupt() {
+        let mut asci = Asci::default();
+        asci.set_cntla(RE);
+        asci.set_stat(RIE);
+        assert!(!asci.interrupt_requested());
+        asci.receive(0x00);
+        assert!(asci.interrupt_requested());
+    }
+}
@@ -0,0 +1,268 @@
+//! Two-channel DMA controller (DMAC).
+//!
+//! Channel 0 moves data between any combination of memory and I/O using
+//! 20-bit source and destination addresses. Channel 1 moves data between
+//! memory and a single I/O port. Transfers run to completion as soon as a
+//! channel is enabled, without waiting for DREQ.
+
+use super::prt::{byte, set_byte};
+use crate::io::IoDevice;
+use crate::memory::{Memory, PHYSICAL_SIZE};
+
+/// DSTAT channel enable bits (DE0, DE1)
+const DE: [u8; 2] = [0x40, 0x80];
+/// DSTAT write enable bits for DE0 and DE1, active low (DWE0, DWE1)
+const DWE: [u8; 2] = [0x10, 0x20];
+/// DSTAT interrupt enable bits (DIE0, DIE1)
+const DIE: [u8; 2] = [0x04, 0x08];
+/// DSTAT DMA main enable
+const DME: u8 = 0x01;
+/// Clock cycles per byte transferred
+const CYCLES_PER_BYTE: u32 = 6;
+
+/// How a channel steps one end of its transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    MemoryIncrement,
+    MemoryDecrement,
+    MemoryFixed,
+    Io,
+}
+
+impl Mode {
+    /// Decodes a DMODE DM/SM field
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0x03 {
+            0 => Self::MemoryIncrement,
+            1 => Self::MemoryDecrement,
+            2 => Self::MemoryFixed,
+            _ => Self::Io,
+        }
+    }
+
+    /// Reads the byte at `address`
+    fn read(self, address: u32, memory: &Memory, io: &mut dyn IoDevice) -> u8 {
+        match self {
+            Self::Io => io.read_port(address as u16),
+            _ => memory.read_physical(address),
+        }
+    }
+
+    /// Writes the byte at `address`
+    fn write(self, address: u32, value: u8, memory: &mut Memory, io: &mut dyn IoDevice) {
+        match self {
+            Self::Io => io.write_port(address as u16, value),
+            _ => memory.write_physical(address, value),
+        }
+    }
+
+    /// Returns the address of the next byte
+    fn step(self, address: u32) -> u32 {
+        let mask = PHYSICAL_SIZE as u32 - 1;
+        match self {
+            Self::MemoryIncrement => address.wrapping_add(1) & mask,
+            Self::MemoryDecrement => address.wrapping_sub(1) & mask,
+            Self::MemoryFixed | Self::Io => address,
+        }
+    }
+}
+
+/// The DMA controller registers
+#[derive(Debug, Default)]
+pub struct Dma {
+    sar0: u32,
+    dar0: u32,
+    bcr0: u16,
+    mar1: u32,
+    iar1: u16,
+    bcr1: u16,
+    dstat: u8,
+    dmode: u8,
+    dcntl: u8,
+}
+
+impl Dma {
+    /// Reads a register by its offset from SAR0L (0x20-0x32)
+    pub fn read(&self, offset: u8) -> u8 {
+        match offset {
+            0x00..=0x02 => address_byte(self.sar0, offset),
+            0x03..=0x05 => address_byte(self.dar0, offset - 0x03),
+            0x06 => byte(self.bcr0, false),
+            0x07 => byte(self.bcr0, true),
+            0x08..=0x0A => address_byte(self.mar1, offset - 0x08),
+            0x0B => byte(self.iar1, false),
+            0x0C => byte(self.iar1, true),
+            0x0E => byte(self.bcr1, false),
+            0x0F => byte(self.bcr1, true),
+            0x10 => self.dstat | DWE[0] | DWE[1],
+            0x11 => self.dmode,
+            0x12 => self.dcntl,
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a register by its offset from SAR0L, returning true if a
+    /// channel was enabled and should run
+    pub fn write(&mut self, offset: u8, value: u8) -> bool {
+        match offset {
+            0x00..=0x02 => set_address_byte(&mut self.sar0, offset, value),
+            0x03..=0x05 => set_address_byte(&mut self.dar0, offset - 0x03, value),
+            0x06 => set_byte(&mut self.bcr0, false, value),
+            0x07 => set_byte(&mut self.bcr0, true, value),
+            0x08..=0x0A => set_address_byte(&mut self.mar1, offset - 0x08, value),
+            0x0B => set_byte(&mut self.iar1, false, value),
+            0x0C => set_byte(&mut self.iar1, true, value),
+            0x0E => set_byte(&mut self.bcr1, false, value),
+            0x0F => set_byte(&mut self.bcr1, true, value),
+            0x10 => return self.write_dstat(value),
+            0x11 => self.dmode = value,
+            0x12 => self.dcntl = value,
+            _ => {}
+        }
+        false
+    }
+
+    /// Writes DSTAT. A DE bit only changes when its DWE bit is written as
+    /// zero, and setting one also sets DME.
+    fn write_dstat(&mut self, value: u8) -> bool {
+        let mut started = false;
+        self.dstat = (self.dstat & !(DIE[0] | DIE[1])) | (value & (DIE[0] | DIE[1]));
+        for channel in 0..2 {
+            if value & DWE[channel] == 0 {
+                self.dstat = (self.dstat & !DE[channel]) | (value & DE[channel]);
+                if value & DE[channel] != 0 {
+                    self.dstat |= DME;
+                    started = true;
+                }
+            }
+        }
+        started
+    }
+
+    /// Runs every enabled channel to completion, returning the clock cycles
+    /// the transfers took
+    pub fn run(&mut self, memory: &mut Memory, io: &mut dyn IoDevice) -> u32 {
+        if self.dstat & DME == 0 {
+            return 0;
+        }
+        let mut bytes = 0;
+        if self.dstat & DE[0] != 0 {
+            bytes += self.run_channel0(memory, io);
+        }
+        if self.dstat & DE[1] != 0 {
+            bytes += self.run_channel1(memory, io);
+        }
+        bytes * CYCLES_PER_BYTE
+    }
+
+    /// Transfers BCR0 bytes (65536 when zero) as set up by DMODE
+    fn run_channel0(&mut self, memory: &mut Memory, io: &mut dyn IoDevice) -> u32 {
+        let destination = Mode::from_bits(self.dmode >> 4);
+        let source = Mode::from_bits(self.dmode >> 2);
+        let count = transfer_count(self.bcr0);
+        for _ in 0..count {
+            let value = source.read(self.sar0, memory, io);
+            destination.write(self.dar0, value, memory, io);
+            self.sar0 = source.step(self.sar0);
+            self.dar0 = destination.step(self.dar0);
+        }
+        self.bcr0 = 0;
+        self.dstat &= !DE[0];
+        count
+    }
+
+    /// Transfers BCR1 bytes between MAR1 and the IAR1 port, in the direction
+    /// set by the DIM bits of DCNTL
+    fn run_channel1(&mut self, memory: &mut Memory, io: &mut dyn IoDevice) -> u32 {
+        let memory_mode = if self.dcntl & 0x01 == 0 {
+            Mode::MemoryIncrement
+        } else {
+            Mode::MemoryDecrement
+        };
+        let to_memory = self.dcntl & 0x02 != 0;
+        let port = u32::from(self.iar1);
+        let count = transfer_count(self.bcr1);
+        for _ in 0..count {
+            if to_memory {
+                let value = Mode::Io.read(port, memory, io);
+                memory_mode.write(self.mar1, value, memory, io);
+            } else {
+                let value = memory_mode.read(self.mar1, memory, io);
+                Mode::Io.write(port, value, memory, io);
+            }
+            self.mar1 = memory_mode.step(self.mar1);
+        }
+        self.bcr1 = 0;
+        self.dstat &= !DE[1];
+        count
+    }
+
+    /// Returns the channel requesting an interrupt, if any, channel 0
+    /// first. A channel interrupts while it is idle with DIE set.
+    pub fn interrupt_channel(&self) -> Option<usize> {
+        (0..2).find(|&channel| self.dstat & DIE[channel] != 0 && self.dstat & DE[channel] == 0)
+    }
+}
+
+/// Returns the number of bytes a byte count register asks for
+fn transfer_count(bcr: u16) -> u32 {
+    match bcr {
+        0 => 0x10000,
+        count => u32::from(count),
+    }
+}
+
+/// Returns byte `index` (L, H, B) of a 20-bit address register
+fn address_byte(address: u32, index: u8) -> u8 {
+    match index {
+        2 => ((address >> 16) & 0x0F) as u8,
+        _ => (address >> (8 * u32::from(index))) as u8,
+    }
+}
+
+/// Replaces byte `index` (L, H, B) of a 20-bit address register
+fn set_address_byte(address: &mut u32, index: u8, value: u8) {
+    let shift = 8 * u32::from(index);
+    let value = match index {
+        2 => u32::from(value & 0x0F),
+        _ => u32::from(value),
+    };
+    *address = (*address & !(0xFF << shift)) | (value << shift);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::OpenBus;
+
+    #[test]
+    fn test_memory_to_memory_transfer() {
+        let mut memory = Memory::with_mmu();
+        memory.load_physical(0x1_0000, &[1, 2, 3]).unwrap();
+        let mut dma = Dma::default();
+
+        // SAR0 = 0x10000, DAR0 = 0x20000, BCR0 = 3, both incrementing
+        for (offset, value) in [(0x02, 0x01), (0x05, 0x02), (0x06, 0x03)] {
+            dma.write(offset, value);
+        }
+        // DE0 set with DWE0 low
+        assert!(dma.write(0x10, DE[0] | DWE[1]));
+        assert_eq!(dma.run(&mut memory, &mut OpenBus), 3 * CYCLES_PER_BYTE);
+
+        assert_eq!(memory.read_physical(0x2_0002), 3);
+        assert_eq!(dma.read(0x10) & DE[0], 0);
+        assert_eq!(dma.read(0x00), 0x03);
+        assert_eq!(dma.read(0x02), 0x01);
+    }
+
+    #[test]
+    fn test_dstat_write_enable() {
+        let mut dma = Dma::default();
+        // DWE0 high leaves DE0 alone
+        assert!(!dma.write(0x10, DE[0] | DWE[0] | DWE[1]));
+        assert_eq!(dma.read(0x10) & DE[0], 0);
+
+        dma.write(0x10, DIE[0] | DWE[0] | DWE[1]);
+        assert_eq!(dma.interrupt_channel(), Some(0));
+    }
+}
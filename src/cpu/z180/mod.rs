@@ -0,0 +1,321 @@
+//! Zilog Z180 (Hitachi HD64180) on-chip peripherals.
+//!
+//! The Z180 adds an MMU, two ASCI serial channels, a two-channel PRT timer
+//! and a two-channel DMA controller to the Z80 core. Their registers occupy
+//! 64 internal I/O ports, at 0x00-0x3F unless moved by ICR, and are reached
+//! by any I/O cycle that has A15-A8 clear. The MMU translation itself lives
+//! in `Memory`.
+
+mod asci;
+mod dma;
+mod prt;
+
+pub use asci::Asci;
+pub use prt::Prt;
+
+use super::Cpu;
+use dma::Dma;
+
+/// Number of internal I/O ports
+const INTERNAL_PORTS: u16 = 0x40;
+
+// Internal register offsets
+const CNTLA0: u8 = 0x00;
+const CNTLA1: u8 = 0x01;
+const CNTLB0: u8 = 0x02;
+const CNTLB1: u8 = 0x03;
+const STAT0: u8 = 0x04;
+const STAT1: u8 = 0x05;
+const TDR0: u8 = 0x06;
+const TDR1: u8 = 0x07;
+const RDR0: u8 = 0x08;
+const RDR1: u8 = 0x09;
+const TMDR0L: u8 = 0x0C;
+const TMDR0H: u8 = 0x0D;
+const RLDR0L: u8 = 0x0E;
+const RLDR0H: u8 = 0x0F;
+const TCR: u8 = 0x10;
+const TMDR1L: u8 = 0x14;
+const TMDR1H: u8 = 0x15;
+const RLDR1L: u8 = 0x16;
+const RLDR1H: u8 = 0x17;
+const SAR0L: u8 = 0x20;
+const DCNTL: u8 = 0x32;
+const IL: u8 = 0x33;
+const ITC: u8 = 0x34;
+const CBR: u8 = 0x38;
+const BBR: u8 = 0x39;
+const CBAR: u8 = 0x3A;
+const ICR: u8 = 0x3F;
+
+/// ITC bit set by an undefined opcode, cleared only by writing it as zero
+const ITC_TRAP: u8 = 0x80;
+/// ITC bit set when the undefined opcode was the third opcode byte
+const ITC_UFO: u8 = 0x40;
+
+/// Low bits of the internal interrupt vectors, in priority order
+const PRT_VECTORS: [u8; 2] = [0x04, 0x06];
+const DMA_VECTORS: [u8; 2] = [0x08, 0x0A];
+const ASCI_VECTORS: [u8; 2] = [0x0E, 0x10];
+
+/// The Z180 on-chip peripherals
+#[derive(Debug)]
+pub struct Z180 {
+    asci: [Asci; 2],
+    prt: Prt,
+    dma: Dma,
+    // I/O control register: internal port base in bits 7-6
+    icr: u8,
+    // Interrupt vector low register: bits 7-5 of internal vectors
+    il: u8,
+    // Registers that are stored but not otherwise modelled (CSIO, refresh
+    // and wait state control) and ITC
+    other: [u8; INTERNAL_PORTS as usize],
+}
+
+impl Default for Z180 {
+    fn default() -> Self {
+        let mut other = [0; INTERNAL_PORTS as usize];
+        other[usize::from(ITC)] = 0x01; // INT0 enabled
+        Self {
+            asci: Default::default(),
+            prt: Prt::default(),
+            dma: Dma::default(),
+            icr: 0,
+            il: 0,
+            other,
+        }
+    }
+}
+
+impl Z180 {
+    /// Returns an ASCI channel (0 or 1), for example to feed it received
+    /// bytes or collect transmitted ones
+    pub fn asci(&mut self, channel: usize) -> &mut Asci {
+        &mut self.asci[channel]
+    }
+
+    /// Returns the programmable reload timer
+    pub fn prt(&mut self) -> &mut Prt {
+        &mut self.prt
+    }
+
+    /// Returns the internal register an I/O cycle to `port` reaches, if any
+    fn internal_register(&self, port: u16) -> Option<u8> {
+        let base = u16::from(self.icr & 0xC0);
+        let offset = port.wrapping_sub(base);
+        (offset < INTERNAL_PORTS).then_some(offset as u8)
+    }
+
+    /// Returns the low byte of the vector of the highest priority internal
+    /// interrupt being requested
+    fn interrupt_vector(&self) -> Option<u8> {
+        let code = self
+            .prt
+            .interrupt_channel()
+            .map(|channel| PRT_VECTORS[channel])
+            .or_else(|| {
+                self.dma
+                    .interrupt_channel()
+                    .map(|channel| DMA_VECTORS[channel])
+            })
+            .or_else(|| {
+                (0..2)
+                    .find(|&channel| self.asci[channel].interrupt_requested())
+                    .map(|channel| ASCI_VECTORS[channel])
+            })?;
+        Some((self.il & 0xE0) | code)
+    }
+}
+
+impl Cpu {
+    /// Returns the on-chip peripherals of a Z180
+    pub fn z180(&mut self) -> Option<&mut Z180> {
+        self.z180.as_mut()
+    }
+
+    /// Reads an internal Z180 register if `port` reaches one
+    pub(super) fn read_internal_port(&mut self, port: u16) -> Option<u8> {
+        let z180 = self.z180.as_mut()?;
+        let register = z180.internal_register(port)?;
+        let mmu = self.memory.mmu().copied().unwrap_or_default();
+        Some(match register {
+            CNTLA0 | CNTLA1 => z180.asci[usize::from(register & 1)].cntla(),
+            CNTLB0 | CNTLB1 => z180.asci[usize::from(register & 1)].cntlb(),
+            STAT0 | STAT1 => z180.asci[usize::from(register & 1)].stat(),
+            RDR0 | RDR1 => z180.asci[usize::from(register & 1)].read_rdr(),
+            TMDR0L | TMDR0H => z180.prt.read_tmdr(0, register == TMDR0H),
+            RLDR0L | RLDR0H => z180.prt.read_rldr(0, register == RLDR0H),
+            TCR => z180.prt.read_tcr(),
+            TMDR1L | TMDR1H => z180.prt.read_tmdr(1, register == TMDR1H),
+            RLDR1L | RLDR1H => z180.prt.read_rldr(1, register == RLDR1H),
+            SAR0L..=DCNTL => z180.dma.read(register - SAR0L),
+            IL => z180.il,
+            CBR => mmu.cbr,
+            BBR => mmu.bbr,
+            CBAR => mmu.cbar,
+            ICR => z180.icr,
+            _ => z180.other[usize::from(register)],
+        })
+    }
+
+    /// Writes an internal Z180 register if `port` reaches one, returning
+    /// whether it did. Enabling a DMA channel runs its transfer.
+    pub(super) fn write_internal_port(&mut self, port: u16, value: u8) -> bool {
+        let Some(z180) = self.z180.as_mut() else {
+            return false;
+        };
+        let Some(register) = z180.internal_register(port) else {
+            return false;
+        };
+        match register {
+            CNTLA0 | CNTLA1 => z180.asci[usize::from(register & 1)].set_cntla(value),
+            CNTLB0 | CNTLB1 => z180.asci[usize::from(register & 1)].set_cntlb(value),
+            STAT0 | STAT1 => z180.asci[usize::from(register & 1)].set_stat(value),
+            TDR0 | TDR1 => z180.asci[usize::from(register & 1)].write_tdr(value),
+            TMDR0L | TMDR0H => z180.prt.write_tmdr(0, register == TMDR0H, value),
+            RLDR0L | RLDR0H => z180.prt.write_rldr(0, register == RLDR0H, value),
+            TCR => z180.prt.write_tcr(value),
+            TMDR1L | TMDR1H => z180.prt.write_tmdr(1, register == TMDR1H, value),
+            RLDR1L | RLDR1H => z180.prt.write_rldr(1, register == RLDR1H, value),
+            SAR0L..=DCNTL => {
+                if z180.dma.write(register - SAR0L, value) {
+                    let cycles = z180.dma.run(&mut self.memory, self.io.as_mut());
+                    self.t_states += cycles;
+                }
+            }
+            IL => z180.il = value & 0xE0,
+            CBR | BBR | CBAR => {
+                if let Some(mmu) = self.memory.mmu_mut() {
+                    match register {
+                        CBR => mmu.cbr = value,
+                        BBR => mmu.bbr = value,
+                        _ => mmu.cbar = value,
+                    }
+                }
+            }
+            ICR => z180.icr = value,
+            ITC => {
+                let itc = &mut z180.other[usize::from(ITC)];
+                *itc =
+                    (value & !(ITC_TRAP | ITC_UFO)) | (*itc & value & ITC_TRAP) | (*itc & ITC_UFO);
+            }
+            _ => z180.other[usize::from(register)] = value,
+        }
+        true
+    }
+
+    /// Records an undefined opcode trap in ITC, with UFO when the undefined
+    /// byte was the third opcode byte
+    pub(super) fn record_z180_trap(&mut self, third_opcode: bool) {
+        if let Some(z180) = self.z180.as_mut() {
+            let itc = &mut z180.other[usize::from(ITC)];
+            *itc = (*itc & !ITC_UFO) | ITC_TRAP | if third_opcode { ITC_UFO } else { 0 };
+        }
+    }
+
+    /// Advances the on-chip timers by the T-states of the last step
+    pub(super) fn tick_z180(&mut self, t_states: u32) {
+        if let Some(z180) = self.z180.as_mut() {
+            z180.prt.tick(t_states);
+        }
+    }
+
+    /// Returns the vector low byte of a pending internal Z180 interrupt
+    pub(super) fn z180_interrupt_vector(&self) -> Option<u8> {
+        self.z180.as_ref()?.interrupt_vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::ops::fixture::{cpu_with, run};
+    use crate::cpu::CpuModel;
+
+    #[test]
+    fn test_mmu_registers() {
+        // LD A,0x84; OUT0 (CBAR),A; LD A,0x10; OUT0 (BBR),A
+        let mut cpu = cpu_with(
+            CpuModel::Z180,
+            &[0x3E, 0x84, 0xED, 0x39, 0x3A, 0x3E, 0x10, 0xED, 0x39, 0x39],
+        );
+        run(&mut cpu, 4);
+        let mmu = *cpu.memory.mmu().unwrap();
+        assert_eq!((mmu.cbar, mmu.bbr), (0x84, 0x10));
+
+        // Logical 0x4000 is in the bank area, at physical 0x14000
+        cpu.memory.write_byte(0x4000, 0x99).unwrap();
+        assert_eq!(cpu.memory.read_physical(0x1_4000), 0x99);
+    }
+
+    #[test]
+    fn test_internal_ports_need_clear_high_byte() {
+        // LD BC,0x0139; IN A,(C): port 0x0139 is external
+        let mut cpu = cpu_with(CpuModel::Z180, &[0x01, 0x39, 0x01, 0xED, 0x78]);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_a(), 0xFF);
+
+        // IN0 A,(CBAR) reads the reset value
+        let mut cpu = cpu_with(CpuModel::Z180, &[0xED, 0x38, 0x3A]);
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_a(), 0xF0);
+    }
+
+    #[test]
+    fn test_asci_transmit() {
+        // LD A,0x60; OUT0 (CNTLA0),A; LD A,'Z'; OUT0 (TDR0),A
+        let mut cpu = cpu_with(
+            CpuModel::Z180,
+            &[0x3E, 0x60, 0xED, 0x39, 0x00, 0x3E, b'Z', 0xED, 0x39, 0x06],
+        );
+        run(&mut cpu, 4);
+        let z180 = cpu.z180().unwrap();
+        assert_eq!(z180.asci(0).take_transmitted(), b"Z");
+
+        z180.asci(0).receive(b'a');
+        // IN0 A,(RDR0)
+        cpu.load_program(0x000A, &[0xED, 0x38, 0x08]).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.get_a(), b'a');
+    }
+
+    #[test]
+    fn test_prt_interrupt() {
+        // I = 0x40, IL = 0x20; PRT0 counts down from 2 with TIE0 and TDE0
+        let mut cpu = cpu_with(
+            CpuModel::Z180,
+            &[
+                0x3E, 0x20, 0xED, 0x39, 0x33, // LD A,0x20; OUT0 (IL),A
+                0x3E, 0x02, 0xED, 0x39, 0x0C, // LD A,2; OUT0 (TMDR0L),A
+                0xAF, 0xED, 0x39, 0x0D, // XOR A; OUT0 (TMDR0H),A
+                0x3E, 0x11, 0xED, 0x39, 0x10, // LD A,0x11; OUT0 (TCR),A
+                0xFB, 0xED, 0x56, // EI; IM 1
+            ],
+        );
+        cpu.i = 0x40;
+        cpu.sp = 0x8000;
+        cpu.write_word(0x4024, 0x1234).unwrap();
+        while cpu.get_pc() != 0x1234 {
+            cpu.step().unwrap();
+            assert!(cpu.get_t_states() < 1000, "no PRT interrupt");
+        }
+        // The internal vector is used even in IM 1
+        assert_eq!(cpu.get_im(), 1);
+    }
+
+    #[test]
+    fn test_dma_memory_to_memory() {
+        let program = [
+            0x3E, 0x05, 0xED, 0x39, 0x22, // SAR0B = 0x05
+            0x3E, 0x06, 0xED, 0x39, 0x25, // DAR0B = 0x06
+            0x3E, 0x03, 0xED, 0x39, 0x26, // BCR0L = 3
+            0x3E, 0x60, 0xED, 0x39, 0x30, // DSTAT: DE0 with DWE0 low
+        ];
+        let mut cpu = cpu_with(CpuModel::Z180, &program);
+        cpu.memory.load_physical(0x5_0000, b"DMA").unwrap();
+        run(&mut cpu, 8);
+        assert_eq!(cpu.memory.read_physical(0x6_0000), b'D');
+        assert_eq!(cpu.memory.read_physical(0x6_0002), b'A');
+    }
+}
@@ -0,0 +1,159 @@
+//! Programmable reload timer (PRT) with two 16-bit down-counting channels.
+
+/// Clock cycles per timer decrement
+const PRESCALE: u32 = 20;
+/// TCR interrupt flag bits (TIF0, TIF1)
+const TIF: [u8; 2] = [0x40, 0x80];
+/// TCR interrupt enable bits (TIE0, TIE1)
+const TIE: [u8; 2] = [0x10, 0x20];
+/// TCR down-count enable bits (TDE0, TDE1)
+const TDE: [u8; 2] = [0x01, 0x02];
+
+/// Both PRT channels
+#[derive(Debug)]
+pub struct Prt {
+    tmdr: [u16; 2],
+    rldr: [u16; 2],
+    tcr: u8,
+    // Clock cycles not yet counted towards a decrement
+    prescaler: u32,
+    // TIF bits seen by a read of TCR, cleared by the next TMDR read
+    tif_read: u8,
+}
+
+impl Default for Prt {
+    fn default() -> Self {
+        Self {
+            tmdr: [0xFFFF; 2],
+            rldr: [0xFFFF; 2],
+            tcr: 0,
+            prescaler: 0,
+            tif_read: 0,
+        }
+    }
+}
+
+impl Prt {
+    /// Advances both channels by `t_states` clock cycles. A channel that
+    /// counts down to zero sets its TIF bit and reloads from RLDR.
+    pub fn tick(&mut self, t_states: u32) {
+        self.prescaler += t_states;
+        while self.prescaler >= PRESCALE {
+            self.prescaler -= PRESCALE;
+            for channel in 0..2 {
+                if self.tcr & TDE[channel] == 0 {
+                    continue;
+                }
+                self.tmdr[channel] = self.tmdr[channel].wrapping_sub(1);
+                if self.tmdr[channel] == 0 {
+                    self.tcr |= TIF[channel];
+                    self.tmdr[channel] = self.rldr[channel];
+                }
+            }
+        }
+    }
+
+    /// Reads the timer control register
+    pub fn read_tcr(&mut self) -> u8 {
+        self.tif_read = self.tcr & (TIF[0] | TIF[1]);
+        self.tcr
+    }
+
+    /// Writes the timer control register. The TIF bits are read-only.
+    pub fn write_tcr(&mut self, value: u8) {
+        self.tcr = (self.tcr & (TIF[0] | TIF[1])) | (value & !(TIF[0] | TIF[1]));
+    }
+
+    /// Reads the low (`high` false) or high byte of a channel's data
+    /// register. Following a TCR read this clears the channel's TIF bit.
+    pub fn read_tmdr(&mut self, channel: usize, high: bool) -> u8 {
+        if self.tif_read & TIF[channel] != 0 {
+            self.tcr &= !TIF[channel];
+            self.tif_read &= !TIF[channel];
+        }
+        byte(self.tmdr[channel], high)
+    }
+
+    /// Writes one byte of a channel's data register
+    pub fn write_tmdr(&mut self, channel: usize, high: bool, value: u8) {
+        set_byte(&mut self.tmdr[channel], high, value);
+    }
+
+    /// Reads one byte of a channel's reload register
+    pub fn read_rldr(&self, channel: usize, high: bool) -> u8 {
+        byte(self.rldr[channel], high)
+    }
+
+    /// Writes one byte of a channel's reload register
+    pub fn write_rldr(&mut self, channel: usize, high: bool, value: u8) {
+        set_byte(&mut self.rldr[channel], high, value);
+    }
+
+    /// Returns the channel requesting an interrupt, if any, channel 0 first
+    pub fn interrupt_channel(&self) -> Option<usize> {
+        (0..2).find(|&channel| self.tcr & TIF[channel] != 0 && self.tcr & TIE[channel] != 0)
+    }
+}
+
+/// Returns the low or high byte of a 16-bit register
+pub(super) fn byte(value: u16, high: bool) -> u8 {
+    if high {
+        (value >> 8) as u8
+    } else {
+        value as u8
+    }
+}
+
+/// Replaces the low or high byte of a 16-bit register
+pub(super) fn set_byte(register: &mut u16, high: bool, value: u8) {
+    *register = if high {
+        (*register & 0x00FF) | (u16::from(value) << 8)
+    } else {
+        (*register & 0xFF00) | u16::from(value)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_countdown_and_reload() {
+        let mut prt = Prt::default();
+        prt.write_tmdr(0, false, 2);
+        prt.write_tmdr(0, true, 0);
+        prt.write_rldr(0, false, 5);
+        prt.write_rldr(0, true, 0);
+
+        // Stopped channels do not count
+        prt.tick(100);
+        assert_eq!(prt.read_tmdr(0, false), 2);
+
+        prt.write_tcr(TDE[0] | TIE[0]);
+        prt.tick(PRESCALE);
+        assert_eq!(prt.read_tmdr(0, false), 1);
+        assert_eq!(prt.interrupt_channel(), None);
+
+        prt.tick(PRESCALE);
+        assert_eq!(prt.read_tmdr(0, false), 5);
+        assert_eq!(prt.interrupt_channel(), Some(0));
+    }
+
+    #[test]
+    fn test_interrupt_flag_cleared_by_tcr_then_tmdr() {
+        let mut prt = Prt::default();
+        prt.write_tmdr(1, false, 1);
+        prt.write_tmdr(1, true, 0);
+        prt.write_tcr(TDE[1] | TIE[1]);
+        prt.tick(PRESCALE);
+        assert_eq!(prt.interrupt_channel(), Some(1));
+
+        // A TMDR read alone leaves the flag set
+        prt.read_tmdr(1, false);
+        assert_eq!(prt.interrupt_channel(), Some(1));
+
+        assert_ne!(prt.read_tcr() & TIF[1], 0);
+        prt.read_tmdr(1, true);
+        assert_eq!(prt.interrupt_channel(), None);
+    }
+}
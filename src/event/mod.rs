@@ -4,6 +4,7 @@
 #[derive(Debug, Clone, Copy)]
 pub enum Event {
     Interrupt,
+    Nmi,
     Timer,
     // Add more event types as needed
 }
@@ -0,0 +1,49 @@
+//! I/O module handles port-mapped devices attached to the CPU.
+
+/// A device that responds to the Z80 IN and OUT instructions
+///
+/// The full 16-bit address bus is passed as the port number, since the Z80
+/// drives the upper half with A or B during I/O cycles and many machines
+/// decode those lines.
+pub trait IoDevice {
+    /// Reads a byte from the given port
+    fn read_port(&mut self, port: u16) -> u8;
+
+    /// Writes a byte to the given port
+    fn write_port(&mut self, port: u16, value: u8);
+
+    /// Supplies the byte the interrupting device places on the data bus
+    /// during an interrupt acknowledge cycle. In IM 0 this is called once for
+    /// each byte of the instruction to execute; in IM 2 it is the low byte of
+    /// the vector table address. An idle bus floats high.
+    fn interrupt_acknowledge(&mut self) -> u8 {
+        0xFF
+    }
+}
+
+/// An unconnected I/O bus where reads float high and writes are ignored
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OpenBus;
+
+impl IoDevice for OpenBus {
+    fn read_port(&mut self, _port: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_port(&mut self, _port: u16, _value: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_bus_reads_high() {
+        let mut bus = OpenBus;
+        assert_eq!(bus.read_port(0x00FE), 0xFF);
+
+        bus.write_port(0x00FE, 0x12);
+        assert_eq!(bus.read_port(0x00FE), 0xFF);
+        assert_eq!(bus.interrupt_acknowledge(), 0xFF);
+    }
+}
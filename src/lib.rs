@@ -1,5 +1,6 @@
 pub mod cpu;
 pub mod event;
+pub mod io;
 pub mod memory;
 pub mod system;
 pub mod timing;
@@ -10,6 +11,8 @@ use thiserror::Error;
 pub enum EmulatorError {
     #[error("Memory access error at address {0:#04x}")]
     MemoryError(u16),
+    #[error("Physical memory access error at address {0:#06x}")]
+    PhysicalMemoryError(u32),
     #[error("Invalid opcode {0:#02x}")]
     InvalidOpcode(u8),
     #[error("System error: {0}")]
@@ -0,0 +1,51 @@
+//! Tracking of the memory bytes that hold cached decoded instructions, so
+//! that writes to them can be reported to the CPU's block cache.
+
+/// Bitmap of physical bytes holding cached instructions, and the watched
+/// bytes written since the cache last collected them
+#[derive(Debug, Default)]
+pub(super) struct CodeMap {
+    watched: Vec<u64>,
+    writes: Vec<u32>,
+}
+
+impl CodeMap {
+    /// Watches `length` physical bytes from `start`
+    pub(super) fn watch(&mut self, start: u32, length: u32) {
+        for address in start..start + length {
+            let (word, bit) = Self::position(address);
+            if word >= self.watched.len() {
+                self.watched.resize(word + 1, 0);
+            }
+            self.watched[word] |= bit;
+        }
+    }
+
+    /// Stops watching `length` physical bytes from `start`
+    pub(super) fn unwatch(&mut self, start: u32, length: u32) {
+        for address in start..start + length {
+            let (word, bit) = Self::position(address);
+            if let Some(bits) = self.watched.get_mut(word) {
+                *bits &= !bit;
+            }
+        }
+    }
+
+    /// Records a write to a physical byte if it is watched. The byte stays
+    /// watched until the cache unwatches it.
+    pub(super) fn note_write(&mut self, address: u32) {
+        let (word, bit) = Self::position(address);
+        if self.watched.get(word).is_some_and(|bits| bits & bit != 0) {
+            self.writes.push(address);
+        }
+    }
+
+    /// Takes a watched byte written since it was last taken
+    pub(super) fn take_write(&mut self) -> Option<u32> {
+        self.writes.pop()
+    }
+
+    fn position(address: u32) -> (usize, u64) {
+        ((address >> 6) as usize, 1 << (address & 0x3F))
+    }
+}
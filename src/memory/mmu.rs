@@ -0,0 +1,77 @@
+//! Z180 memory management unit, which maps the 64 KB logical address space
+//! onto a 1 MB physical one.
+
+/// Size of the physical address space behind the MMU
+pub const PHYSICAL_SIZE: usize = 0x10_0000;
+
+/// Logical to physical translation set up by the CBAR, CBR and BBR
+/// registers. CBAR splits the logical space at 4 KB boundaries into common
+/// area 0 (untranslated), the bank area (offset by BBR) and common area 1
+/// (offset by CBR).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mmu {
+    /// Common/bank area register: common area 1 start (high nibble) and
+    /// bank area start (low nibble), in 4 KB pages
+    pub cbar: u8,
+    /// Common base register: physical page offset of common area 1
+    pub cbr: u8,
+    /// Bank base register: physical page offset of the bank area
+    pub bbr: u8,
+}
+
+impl Default for Mmu {
+    /// The reset state maps logical 0x0000-0xEFFF as common area 0 and
+    /// 0xF000-0xFFFF as common area 1, both onto the first 64 KB
+    fn default() -> Self {
+        Self {
+            cbar: 0xF0,
+            cbr: 0,
+            bbr: 0,
+        }
+    }
+}
+
+impl Mmu {
+    /// Translates a logical address to a 20-bit physical address
+    pub fn translate(&self, address: u16) -> u32 {
+        let page = (address >> 12) as u8;
+        let base = if page >= self.cbar >> 4 {
+            self.cbr
+        } else if page >= self.cbar & 0x0F {
+            self.bbr
+        } else {
+            0
+        };
+        (u32::from(address) + (u32::from(base) << 12)) & (PHYSICAL_SIZE as u32 - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translation_areas() {
+        // Bank area from 0x4000, common area 1 from 0xC000
+        let mmu = Mmu {
+            cbar: 0xC4,
+            cbr: 0xF0,
+            bbr: 0x20,
+        };
+        assert_eq!(mmu.translate(0x3FFF), 0x0_3FFF);
+        assert_eq!(mmu.translate(0x4000), 0x2_4000);
+        assert_eq!(mmu.translate(0xBFFF), 0x2_BFFF);
+        assert_eq!(mmu.translate(0xC000), 0xF_C000);
+
+        // Physical addresses wrap at 1 MB
+        let mmu = Mmu { cbr: 0xF8, ..mmu };
+        assert_eq!(mmu.translate(0xC000), 0x0_4000);
+    }
+
+    #[test]
+    fn test_reset_mapping() {
+        let mmu = Mmu::default();
+        assert_eq!(mmu.translate(0x1234), 0x1234);
+        assert_eq!(mmu.translate(0xF123), 0xF123);
+    }
+}
@@ -1,12 +1,29 @@
 //! Memory module handles memory management and addressing.
 
+mod code;
+mod mmu;
+
 use crate::Result;
+use code::CodeMap;
+
+pub use mmu::{Mmu, PHYSICAL_SIZE};
 
 const MEMORY_SIZE: usize = 0x10000; // 64KB memory space
 
+/// Size of the 24-bit address space of the eZ80
+pub const LONG_SIZE: usize = 0x100_0000;
+
 /// Represents the memory management unit
 pub struct Memory {
     ram: Vec<u8>,
+    // Logical to physical translation, present on parts with an MMU
+    mmu: Option<Mmu>,
+    // Upper address byte (A23-A16) driven for 16-bit logical addresses,
+    // present on parts with a 24-bit bus
+    address_base: Option<u8>,
+    // Bytes holding cached decoded instructions, present while the CPU's
+    // block cache is enabled
+    code: Option<CodeMap>,
 }
 
 impl Default for Memory {
@@ -20,20 +37,125 @@ impl Memory {
     pub fn new() -> Self {
         Self {
             ram: vec![0; MEMORY_SIZE],
+            mmu: None,
+            address_base: None,
+            code: None,
+        }
+    }
+
+    /// Creates a Memory instance with a 1 MB physical space behind an MMU
+    /// in its reset state
+    pub fn with_mmu() -> Self {
+        let mut memory = Self::new();
+        memory.enable_mmu();
+        memory
+    }
+
+    /// Extends memory to the 1 MB physical space and places it behind an
+    /// MMU, keeping the existing contents in the first 64 KB
+    pub fn enable_mmu(&mut self) {
+        self.ram.resize(PHYSICAL_SIZE, 0);
+        self.mmu.get_or_insert_with(Mmu::default);
+    }
+
+    /// Extends memory to the 16 MB space of a 24-bit bus. 16-bit logical
+    /// addresses then select a 64 KB bank through the address base.
+    pub fn enable_long_addressing(&mut self) {
+        self.ram.resize(LONG_SIZE, 0);
+        self.address_base.get_or_insert(0);
+    }
+
+    /// Sets the upper address byte placed in front of 16-bit logical
+    /// addresses on a 24-bit bus, such as the eZ80's MBASE
+    pub fn set_address_base(&mut self, base: u8) {
+        if let Some(address_base) = &mut self.address_base {
+            *address_base = base;
+        }
+    }
+
+    /// Returns the MMU, if memory has one
+    pub fn mmu(&self) -> Option<&Mmu> {
+        self.mmu.as_ref()
+    }
+
+    /// Returns the MMU for reprogramming, if memory has one
+    pub fn mmu_mut(&mut self) -> Option<&mut Mmu> {
+        self.mmu.as_mut()
+    }
+
+    /// Starts or stops tracking writes to bytes that hold cached decoded
+    /// instructions. Stopping forgets every watched byte.
+    pub(crate) fn track_code(&mut self, enabled: bool) {
+        self.code = enabled.then(CodeMap::default);
+    }
+
+    /// Watches `length` physical bytes from `start` as cached code
+    pub(crate) fn watch_code(&mut self, start: u32, length: u32) {
+        if let Some(code) = &mut self.code {
+            code.watch(start, length);
+        }
+    }
+
+    /// Stops watching `length` physical bytes from `start` as cached code
+    pub(crate) fn unwatch_code(&mut self, start: u32, length: u32) {
+        if let Some(code) = &mut self.code {
+            code.unwatch(start, length);
+        }
+    }
+
+    /// Takes the physical address of a watched code byte written since it
+    /// was last taken
+    pub(crate) fn take_code_write(&mut self) -> Option<u32> {
+        self.code.as_mut().and_then(CodeMap::take_write)
+    }
+
+    /// Reports a write to the code tracker
+    fn note_write(&mut self, address: u32) {
+        if let Some(code) = &mut self.code {
+            code.note_write(address);
+        }
+    }
+
+    /// Returns the physical address a logical address currently maps to
+    pub(crate) fn physical_address(&self, address: u16) -> u32 {
+        self.physical(address) as u32
+    }
+
+    /// Returns the physical address a logical address maps to
+    fn physical(&self, address: u16) -> usize {
+        match (&self.mmu, self.address_base) {
+            (Some(mmu), _) => mmu.translate(address) as usize,
+            (None, Some(base)) => (usize::from(base) << 16) | usize::from(address),
+            (None, None) => address as usize,
         }
     }
 
     /// Reads a byte from memory
     pub fn read_byte(&self, address: u16) -> Result<u8> {
-        Ok(self.ram[address as usize])
+        Ok(self.ram[self.physical(address)])
     }
 
     /// Writes a byte to memory
     pub fn write_byte(&mut self, address: u16, value: u8) -> Result<()> {
-        self.ram[address as usize] = value;
+        let physical = self.physical(address);
+        self.ram[physical] = value;
+        self.note_write(physical as u32);
         Ok(())
     }
 
+    /// Reads a byte by physical address, bypassing the MMU, or by full
+    /// 24-bit address on an eZ80. Addresses wrap at the size of memory.
+    pub fn read_physical(&self, address: u32) -> u8 {
+        self.ram[address as usize % self.ram.len()]
+    }
+
+    /// Writes a byte by physical address, bypassing the MMU
+    pub fn write_physical(&mut self, address: u32, value: u8) {
+        let index = address as usize % self.ram.len();
+        self.ram[index] = value;
+        self.note_write(index as u32);
+    }
+
     /// Loads data into memory at specified address
     pub fn load(&mut self, address: u16, data: &[u8]) -> Result<()> {
         let start = address as usize;
@@ -43,7 +165,26 @@ impl Memory {
             return Err(crate::EmulatorError::MemoryError(address));
         }
 
+        for (offset, &byte) in data.iter().enumerate() {
+            self.write_byte(address.wrapping_add(offset as u16), byte)?;
+        }
+        Ok(())
+    }
+
+    /// Loads data by physical address, such as a ROM image beyond the
+    /// first 64 KB
+    pub fn load_physical(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        let start = address as usize;
+        let end = start + data.len();
+
+        if end > self.ram.len() {
+            return Err(crate::EmulatorError::PhysicalMemoryError(address));
+        }
+
         self.ram[start..end].copy_from_slice(data);
+        for address in start..end {
+            self.note_write(address as u32);
+        }
         Ok(())
     }
 }
@@ -83,6 +224,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_mmu_translation() {
+        let mut memory = Memory::with_mmu();
+        memory.load_physical(0x8_1000, &[0xAB]).unwrap();
+
+        // Bank area from 0x1000 onto physical page 0x81
+        let mmu = memory.mmu_mut().unwrap();
+        mmu.cbar = 0xF1;
+        mmu.bbr = 0x80;
+        assert_eq!(memory.read_byte(0x1000).unwrap(), 0xAB);
+
+        memory.write_byte(0x1001, 0xCD).unwrap();
+        assert_eq!(memory.read_physical(0x8_1001), 0xCD);
+        assert_eq!(memory.read_byte(0x0FFF).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn test_long_addressing() {
+        let mut memory = Memory::new();
+        memory.write_byte(0x1234, 0x11).unwrap();
+        memory.enable_long_addressing();
+        memory.write_physical(0xD0_1234, 0x22);
+
+        // 16-bit addresses select the bank given by the address base
+        assert_eq!(memory.read_byte(0x1234).unwrap(), 0x11);
+        memory.set_address_base(0xD0);
+        assert_eq!(memory.read_byte(0x1234).unwrap(), 0x22);
+        memory.write_byte(0x1235, 0x33).unwrap();
+        assert_eq!(memory.read_physical(0xD0_1235), 0x33);
+    }
+
     #[test]
     fn test_load_program_overflow() {
         let mut memory = Memory::new();
@@ -90,4 +262,14 @@ mod tests {
         let result = memory.load(0, &program);
         assert!(matches!(result, Err(EmulatorError::MemoryError(_))));
     }
+
+    #[test]
+    fn test_load_physical_overflow() {
+        let mut memory = Memory::with_mmu();
+        let result = memory.load_physical(0xF_FFFF, &[0x00, 0x00]);
+        assert!(matches!(
+            result,
+            Err(EmulatorError::PhysicalMemoryError(0xF_FFFF))
+        ));
+    }
 }
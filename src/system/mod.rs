@@ -1,6 +1,10 @@
 //! System module handles the integration between CPU, memory, and I/O devices.
 
-use crate::{cpu::Cpu, memory::Memory, Result};
+use crate::{
+    cpu::{Cpu, CpuModel},
+    memory::Memory,
+    Result,
+};
 
 /// Represents the system bus and coordinates component interaction
 pub struct System {
@@ -10,8 +14,13 @@ pub struct System {
 impl System {
     /// Creates a new System instance
     pub fn new() -> Self {
+        Self::with_model(CpuModel::default())
+    }
+
+    /// Creates a new System instance around the given Z80 model
+    pub fn with_model(model: CpuModel) -> Self {
         let memory = Memory::new();
-        let cpu = Cpu::new(memory);
+        let cpu = Cpu::new(memory, model);
 
         Self { cpu }
     }
@@ -40,7 +49,6 @@ impl Default for System {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::EmulatorError;
 
     #[test]
     fn test_system_initialization() {
@@ -48,6 +56,13 @@ mod tests {
         assert_eq!(system.cpu.get_pc(), 0);
     }
 
+    #[test]
+    fn test_system_model() {
+        let system = System::with_model(CpuModel::NecNmos);
+        assert_eq!(system.cpu.get_model(), CpuModel::NecNmos);
+        assert_eq!(System::default().cpu.get_model(), CpuModel::ZilogNmos);
+    }
+
     #[test]
     fn test_program_execution() {
         let mut system = System::default();
@@ -58,12 +73,12 @@ mod tests {
     }
 
     #[test]
-    fn test_invalid_program() {
+    fn test_undefined_opcode_program() {
         let mut system = System::default();
-        let program = [0xFF]; // Invalid opcode
+        let program = [0xED, 0x00]; // Undefined ED-prefixed opcode runs as a NOP
 
         system.load_program(&program).unwrap();
-        let result = system.tick();
-        assert!(matches!(result, Err(EmulatorError::InvalidOpcode(0xFF))));
+        system.tick().unwrap();
+        assert_eq!(system.cpu.get_pc(), 2);
     }
 }
@@ -1,5 +1,3 @@
-use std::time::Duration;
-
 /// Standard Z80 clock frequency in Hz
 pub const Z80_CLOCK_FREQUENCY: u32 = 4_000_000; // 4MHz
 